@@ -1,127 +1,2087 @@
-use crate::{errors::ApplicationError, tx::Tx};
-use std::collections::HashMap;
+use crate::{
+    amount::{Amount, Asset, Market},
+    clock::{Clock, SystemClock, Timestamp, DAY},
+    codec::Writer,
+    core::Side,
+    errors::ApplicationError,
+    escrow::{Condition, Escrow, ESCROW_ACCOUNT},
+    fees::FeeSchedule,
+    idempotency::IdempotencyCache,
+    interest::{self, Accrual, AccrualKind, InterestSchedule},
+    limits::{LimitTracker, Limits, Usage},
+    margin::{Fill, MarginRequirements, Position},
+    risk::RiskLimits,
+    schedule::{Recurrence, RetryPolicy, Schedule},
+    snapshot::Snapshot,
+    state_hash::StateHash,
+    statement::{self, Statement},
+    tx::{Cause, Leg, Tx, TxContext, TxMeta},
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::RangeInclusive,
+};
+
+/// Lifecycle of an account
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AccountStatus {
+    /// Can send, receive and trade
+    Open,
+    /// Can receive, but not send or trade
+    Frozen,
+    /// Holds no funds and can't be used anymore
+    Closed,
+}
 
 /// A type for managing accounts and their current currency balance
 #[derive(Debug)]
 pub struct Accounts {
-    accounts: HashMap<String, u64>,
+    /// Balances of all accounts that aren't closed
+    accounts: BTreeMap<String, u64>,
+
+    /// What every account that went below zero owes, its balance is zero meanwhile
+    debts: BTreeMap<String, u64>,
+
+    /// How far below zero every account with a credit line can go
+    credit_limits: BTreeMap<String, u64>,
+
+    /// Status of every account that was ever opened
+    statuses: BTreeMap<String, AccountStatus>,
+
+    /// The parent of every sub-account
+    parents: BTreeMap<String, String>,
+
+    /// The sub-accounts of every account that has some
+    children: BTreeMap<String, BTreeSet<String>>,
+
+    /// Every open escrow by id
+    escrows: BTreeMap<u64, Escrow>,
+
+    /// Every scheduled transfer that has occurrences left, by id
+    schedules: BTreeMap<u64, Schedule>,
+
+    /// Interest and fees accrued but not posted yet, in fractions of [`interest::ACCRUAL_SCALE`]
+    accrued: BTreeMap<(String, AccrualKind), u128>,
+
+    /// The day (since the UNIX epoch) that interest was last accrued up to
+    accrued_day: Option<u64>,
+
+    /// Position of every account in every market it traded, by account and symbol
+    positions: BTreeMap<(String, String), Position>,
+
+    /// Every transaction that was applied, in order
+    journal: Vec<Tx>,
+
+    /// Sequence number of the last transaction
+    sequence: u64,
+
+    /// Timestamps the transactions
+    clock: Box<dyn Clock>,
+
+    /// Digest of all balances, kept up to date with every change
+    hash: StateHash,
+
+    /// Withdrawal and transfer limits and how much of them was used
+    limits: LimitTracker,
+
+    /// What is traded, the balances are held in minor units of its quote asset
+    market: Market,
+
+    /// Transactions of recent requests with an idempotency key
+    requests: IdempotencyCache<Tx>,
+
+    /// Interest and custody fee rates and the account they are posted against
+    interest: InterestSchedule,
+
+    /// Margin needed for positions, none without leverage checks and liquidations
+    margin: Option<MarginRequirements>,
+
+    /// Pre-trade limits of every market that has some, by symbol
+    market_risk_limits: BTreeMap<String, RiskLimits>,
+
+    /// Pre-trade limits that replace the ones of the market for an account, by account and symbol
+    risk_limits: BTreeMap<(String, String), RiskLimits>,
+
+    /// Maker and taker fees and the account they are collected in
+    fees: FeeSchedule,
+}
+
+impl Default for Accounts {
+    fn default() -> Self {
+        Accounts::new()
+    }
 }
 
 impl Accounts {
     /// Returns an empty instance of the [`Accounts`] type
     pub fn new() -> Self {
+        Accounts::with_clock(SystemClock)
+    }
+
+    /// Returns an empty instance of the [`Accounts`] type that uses the provided [`Clock`] to timestamp transactions
+    pub fn with_clock(clock: impl Clock + 'static) -> Self {
         Accounts {
-            accounts: HashMap::new(),
+            accounts: BTreeMap::new(),
+            debts: BTreeMap::new(),
+            credit_limits: BTreeMap::new(),
+            statuses: BTreeMap::new(),
+            parents: BTreeMap::new(),
+            children: BTreeMap::new(),
+            escrows: BTreeMap::new(),
+            schedules: BTreeMap::new(),
+            accrued: BTreeMap::new(),
+            accrued_day: None,
+            positions: BTreeMap::new(),
+            journal: Vec::new(),
+            sequence: 0,
+            clock: Box::new(clock),
+            hash: StateHash::default(),
+            limits: LimitTracker::default(),
+            market: Market::default(),
+            requests: IdempotencyCache::default(),
+            interest: InterestSchedule::default(),
+            margin: None,
+            market_risk_limits: BTreeMap::new(),
+            risk_limits: BTreeMap::new(),
+            fees: FeeSchedule::default(),
+        }
+    }
+
+    /// Picks up from the account part of a `snapshot`. The journal starts out empty.
+    pub fn restore(snapshot: &Snapshot, clock: impl Clock + 'static) -> Self {
+        let mut accounts = Accounts::with_clock(clock);
+        accounts.sequence = snapshot.sequence;
+        let mut balances: BTreeMap<String, i128> = snapshot
+            .balances
+            .iter()
+            .map(|(account, balance)| (account.clone(), *balance as i128))
+            .collect();
+        for (account, debt) in &snapshot.debts {
+            balances.insert(account.clone(), -(*debt as i128));
+        }
+        for (account, limit) in &snapshot.credit_limits {
+            accounts.change_credit_limit(account, *limit);
+        }
+        for account in balances.keys() {
+            accounts
+                .statuses
+                .insert(account.clone(), AccountStatus::Open);
+        }
+        accounts.statuses.extend(snapshot.statuses.iter().cloned());
+        for (account, parent) in &snapshot.parents {
+            accounts.link(account, parent);
+        }
+        for escrow in &snapshot.escrows {
+            accounts.hold(escrow.clone());
+        }
+        for schedule in &snapshot.schedules {
+            accounts.put_schedule(schedule.clone());
+        }
+        for accrual in &snapshot.accrued {
+            accounts.accrue(accrual);
+        }
+        accounts.accrued_day = snapshot.accrued_day;
+        accounts.interest = snapshot.interest_schedule.clone();
+        accounts.margin = snapshot.margin.clone();
+        accounts.market = snapshot.market.clone();
+        accounts.fees = snapshot.fee_schedule.clone();
+        for (symbol, limits) in &snapshot.market_risk_limits {
+            accounts
+                .market_risk_limits
+                .insert(symbol.clone(), limits.clone());
         }
+        for (account, symbol, limits) in &snapshot.risk_limits {
+            accounts
+                .risk_limits
+                .insert((account.clone(), symbol.clone()), limits.clone());
+        }
+        for position in &snapshot.positions {
+            accounts.put_position(position.clone());
+        }
+        for (account, limits) in &snapshot.limits {
+            accounts.change_limits(account, limits.clone());
+        }
+        for (account, usage) in &snapshot.usage {
+            accounts.limits.restore_usage(account, usage.clone());
+        }
+        accounts.commit(balances);
+        accounts
+    }
+
+    /// Rebuilds the state by applying `transactions` in sequence to an empty instance
+    /// # Errors
+    /// A transaction can't be applied, e.g. a withdrawal from a missing or underfunded account
+    pub fn replay<'a>(
+        transactions: impl IntoIterator<Item = &'a Tx>,
+    ) -> Result<Self, ApplicationError> {
+        let mut accounts = Accounts::new();
+        for tx in transactions {
+            accounts.apply(tx)?;
+        }
+        Ok(accounts)
+    }
+
+    /// Applies an existing transaction, metadata and all, e.g. one from another ledger's journal.
+    /// Withdrawals and transfers count towards the limits as they would have when they were made,
+    /// but aren't checked against them.
+    /// # Errors
+    /// The transaction is not the next in sequence or can't be applied to the balances
+    pub fn apply(&mut self, tx: &Tx) -> Result<(), ApplicationError> {
+        let sequence = tx.meta().sequence;
+        if sequence <= self.sequence {
+            return Err(ApplicationError::OutOfSequence(sequence));
+        }
+        match tx {
+            Tx::StatusChange {
+                account, status, ..
+            } => {
+                self.check_status_change(account, *status)?;
+                self.change_status(account, *status);
+            }
+            Tx::SubAccount {
+                account, parent, ..
+            } => {
+                self.check_sub_account(parent, account)?;
+                self.link(account, parent);
+                self.change_status(account, AccountStatus::Open);
+            }
+            Tx::EscrowOpened { escrow, .. } => {
+                self.balance_of(&escrow.recipient)?;
+                self.stage_and_commit(tx)?;
+                self.hold(escrow.clone());
+            }
+            Tx::EscrowReleased {
+                id,
+                recipient,
+                amount,
+                ..
+            } => {
+                self.check_payout(*id, recipient, *amount, true)?;
+                self.stage_and_commit(tx)?;
+                self.unhold(*id);
+            }
+            Tx::EscrowRefunded {
+                id, sender, amount, ..
+            } => {
+                self.check_payout(*id, sender, *amount, false)?;
+                self.stage_and_commit(tx)?;
+                self.unhold(*id);
+            }
+            Tx::ScheduleCreated { schedule, .. } => {
+                self.balance_of(&schedule.sender)?;
+                self.balance_of(&schedule.recipient)?;
+            }
+            Tx::ScheduleFailed { id, .. } | Tx::ScheduleCancelled { id, .. } => {
+                self.schedule_of(*id)?;
+            }
+            Tx::InterestAccrued { .. } => {}
+            Tx::CreditLimitSet { account, limit, .. } => {
+                self.balance_of(account)?;
+                self.change_credit_limit(account, *limit);
+            }
+            Tx::PositionFilled { account, .. } => {
+                self.balance_of(account)?;
+            }
+            Tx::LimitsSet {
+                account, limits, ..
+            } => {
+                self.balance_of(account)?;
+                self.change_limits(account, limits.clone());
+            }
+            Tx::InterestScheduleSet { schedule, .. } => {
+                self.check_not_reserved(&schedule.account)?;
+                self.interest = schedule.clone();
+            }
+            Tx::MarginRequirementsSet { requirements, .. } => {
+                self.check_not_reserved(&requirements.account)?;
+                self.margin = Some(requirements.clone());
+            }
+            Tx::MarketSet { market, .. } => {
+                self.check_no_amounts()?;
+                self.market = market.clone();
+            }
+            Tx::FeeScheduleSet { schedule, .. } => {
+                self.check_not_reserved(&schedule.account)?;
+                self.fees = schedule.clone();
+            }
+            Tx::RiskLimitsSet {
+                account,
+                symbol,
+                limits,
+                ..
+            } => {
+                if let Some(account) = account {
+                    self.check_not_reserved(account)?;
+                    self.balance_of(account)?;
+                }
+                self.change_risk_limits(account.as_deref(), symbol, limits.clone());
+            }
+            _ => self.stage_and_commit(tx)?,
+        }
+        self.sequence = sequence;
+        self.track_usage(tx);
+        self.follow_schedules(tx);
+        self.follow_accruals(tx);
+        self.follow_positions(tx);
+        self.journal.push(tx.clone());
+        Ok(())
+    }
+
+    /// All transactions applied so far, oldest first
+    pub fn journal(&self) -> &[Tx] {
+        &self.journal
+    }
+
+    /// Sequence number of the last transaction
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Every account that isn't closed and its balance, ordered by account name
+    pub fn balances(&self) -> Vec<(&str, u64)> {
+        self.accounts
+            .iter()
+            .map(|(account, balance)| (account.as_str(), *balance))
+            .collect()
+    }
+
+    /// Order-independent digest of every account, its balance and status. Two instances with the
+    /// same balances have the same hash, however they got there. It detects accidental
+    /// divergence only, see [`StateHash`].
+    pub fn state_hash(&self) -> StateHash {
+        self.hash
     }
 
     /// Retrieves the balance of an account
     pub fn balance_of(&self, signer: &str) -> Result<&u64, ApplicationError> {
         self.accounts
             .get(signer)
-            .ok_or(ApplicationError::AccountNotFound(signer.to_string()))
+            .ok_or_else(|| self.missing(signer))
+    }
+
+    /// Retrieves the balance of an account as a decimal of [`Accounts::asset`]
+    pub fn balance_amount(&self, signer: &str) -> Result<Amount, ApplicationError> {
+        self.balance_of(signer)
+            .map(|balance| self.market.quote.amount(*balance))
     }
 
-    /// Either deposits the `amount` provided into the `signer` account or adds the amount to the existing account.
+    /// Deposits `amount` of [`Accounts::asset`], see [`Accounts::deposit`]
     /// # Errors
-    /// Attempted overflow
-    pub fn deposit(&mut self, signer: &str, amount: u64) -> Result<Tx, ApplicationError> {
-        if let Some(account) = self.accounts.get_mut(signer) {
-            (*account)
-                .checked_add(amount)
-                .and_then(|r| {
-                    *account = r;
-                    Some(r)
+    /// `amount` has more decimals than the asset, or the deposit fails
+    pub fn deposit_amount(
+        &mut self,
+        account: &str,
+        amount: Amount,
+    ) -> Result<Tx, ApplicationError> {
+        let units = self.market.quote.minor_units(amount)?;
+        self.deposit(account, units)
+    }
+
+    /// Withdraws `amount` of [`Accounts::asset`], see [`Accounts::withdraw`]
+    /// # Errors
+    /// `amount` has more decimals than the asset, or the withdrawal fails
+    pub fn withdraw_amount(
+        &mut self,
+        account: &str,
+        amount: Amount,
+    ) -> Result<Tx, ApplicationError> {
+        let units = self.market.quote.minor_units(amount)?;
+        self.withdraw(account, units)
+    }
+
+    /// Sends `amount` of [`Accounts::asset`], see [`Accounts::send`]
+    /// # Errors
+    /// `amount` has more decimals than the asset, or the transfer fails
+    pub fn send_amount(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Amount,
+    ) -> Result<Tx, ApplicationError> {
+        let units = self.market.quote.minor_units(amount)?;
+        self.send(sender, recipient, units)
+    }
+
+    /// What an account owes after going below zero on its credit line. Its balance is zero
+    /// meanwhile.
+    /// # Errors
+    /// The account doesn't exist or is closed
+    pub fn debt_of(&self, signer: &str) -> Result<u64, ApplicationError> {
+        self.balance_of(signer)?;
+        Ok(self.debts.get(signer).copied().unwrap_or(0))
+    }
+
+    /// The balance of an account minus its debt, negative while it owes something
+    /// # Errors
+    /// The account doesn't exist or is closed
+    pub fn net_balance(&self, signer: &str) -> Result<i128, ApplicationError> {
+        self.net(signer).ok_or_else(|| self.missing(signer))
+    }
+
+    /// How far below zero an account can go, zero without a credit line
+    pub fn credit_limit_of(&self, signer: &str) -> u64 {
+        self.credit_limits.get(signer).copied().unwrap_or(0)
+    }
+
+    /// What an account can spend: its balance plus what is left of its credit line
+    /// # Errors
+    /// The account doesn't exist or is closed
+    pub fn available(&self, signer: &str) -> Result<u128, ApplicationError> {
+        let net = self.net_balance(signer)?;
+        Ok((net + self.credit_limit_of(signer) as i128).max(0) as u128)
+    }
+
+    /// Every account with a credit line and its limit, ordered by account name
+    pub fn credit_limits(&self) -> Vec<(&str, u64)> {
+        self.credit_limits
+            .iter()
+            .map(|(account, limit)| (account.as_str(), *limit))
+            .collect()
+    }
+
+    /// Every account that owes something and its debt, ordered by account name
+    pub fn debts(&self) -> Vec<(&str, u64)> {
+        self.debts
+            .iter()
+            .map(|(account, debt)| (account.as_str(), *debt))
+            .collect()
+    }
+
+    /// Lets an account go up to `limit` below zero when withdrawing, sending or settling trades.
+    /// Lowering the limit below the current debt doesn't call it in, but nothing more can be
+    /// borrowed until it's paid back below the limit.
+    /// # Errors
+    /// The account doesn't exist, is closed or reserved
+    pub fn set_credit_limit(&mut self, account: &str, limit: u64) -> Result<Tx, ApplicationError> {
+        self.check_not_reserved(account)?;
+        self.balance_of(account)?;
+        self.change_credit_limit(account, limit);
+        let meta = self.next_meta(TxContext::default());
+        Ok(self.record(Tx::CreditLimitSet {
+            account: account.to_string(),
+            limit,
+            meta,
+        }))
+    }
+
+    /// What the balances are held in. Amounts passed to and returned by [`Accounts`] are minor
+    /// units of it.
+    pub fn asset(&self) -> &Asset {
+        &self.market.quote
+    }
+
+    /// Sets what the balances are held in, the quote asset of [`Accounts::market`]
+    /// # Errors
+    /// See [`Accounts::set_market`]
+    pub fn set_asset(&mut self, asset: Asset) -> Result<Tx, ApplicationError> {
+        let base = self.market.base.clone();
+        self.set_market(Market::new(base, asset))
+    }
+
+    /// The assets that are traded, positions are held in minor units of the base asset
+    pub fn market(&self) -> &Market {
+        &self.market
+    }
+
+    /// Sets the assets that are traded and what the balances are held in. Amounts are minor
+    /// units and wouldn't be converted, so this has to happen before there are any.
+    /// # Errors
+    /// There are balances, debts, credit lines, limits, positions, accruals, escrows or scheduled
+    /// transfers already
+    pub fn set_market(&mut self, market: Market) -> Result<Tx, ApplicationError> {
+        self.check_no_amounts()?;
+        self.market = market.clone();
+        let meta = self.next_meta(TxContext::default());
+        Ok(self.record(Tx::MarketSet { market, meta }))
+    }
+
+    /// Every sub-account and its parent, ordered by account name
+    pub fn parents(&self) -> Vec<(&str, &str)> {
+        self.parents
+            .iter()
+            .map(|(account, parent)| (account.as_str(), parent.as_str()))
+            .collect()
+    }
+
+    /// Every account that was ever opened and its status, ordered by account name
+    pub fn statuses(&self) -> Vec<(&str, AccountStatus)> {
+        self.statuses
+            .iter()
+            .map(|(account, status)| (account.as_str(), *status))
+            .collect()
+    }
+
+    /// The balance of an account right after `at`, `None` if it wasn't open then. Only covers
+    /// the journal, so it can't look back further than a restore from a snapshot.
+    pub fn balance_at(&self, account: &str, at: Timestamp) -> Option<i128> {
+        statement::balance_at(&self.journal, account, at)
+    }
+
+    /// The statement of an account for the period `timestamps`, both ends included. Like
+    /// [`Accounts::balance_at`], it only covers the journal.
+    pub fn statement(&self, account: &str, timestamps: RangeInclusive<Timestamp>) -> Statement {
+        Statement::generate(&self.journal, account, &self.market.quote, timestamps)
+    }
+
+    /// The status of an account, `None` if it was never opened
+    pub fn status_of(&self, account: &str) -> Option<AccountStatus> {
+        self.statuses.get(account).copied()
+    }
+
+    /// Changes how long idempotency keys are remembered. Keys are kept in memory only and are
+    /// forgotten on restart.
+    pub fn set_idempotency_window(&mut self, window: Timestamp) {
+        self.requests.set_window(window);
+    }
+
+    /// The withdrawal and transfer limits of an account, `None` if it has none
+    pub fn limits_of(&self, account: &str) -> Option<&Limits> {
+        self.limits.limits(account)
+    }
+
+    /// Every account with withdrawal and transfer limits and its limits, ordered by account name
+    pub fn limits(&self) -> Vec<(&str, &Limits)> {
+        self.limits.all_limits()
+    }
+
+    /// What every account withdrew and sent recently, ordered by account name
+    pub fn usage(&self) -> Vec<(&str, &Usage)> {
+        self.limits.all_usage()
+    }
+
+    /// Replaces the withdrawal and transfer limits of an account. Withdrawals and sends made
+    /// before still count towards the new limits.
+    /// # Errors
+    /// The account doesn't exist, is closed or reserved
+    pub fn set_limits(&mut self, account: &str, limits: Limits) -> Result<Tx, ApplicationError> {
+        self.check_not_reserved(account)?;
+        self.balance_of(account)?;
+        self.change_limits(account, limits.clone());
+        let meta = self.next_meta(TxContext::default());
+        Ok(self.record(Tx::LimitsSet {
+            account: account.to_string(),
+            limits,
+            meta,
+        }))
+    }
+
+    /// Opens a new, empty account
+    /// # Errors
+    /// An account with that name exists or was closed
+    pub fn open_account(&mut self, account: &str) -> Result<Tx, ApplicationError> {
+        self.check_not_reserved(account)?;
+        match self.status_of(account) {
+            None => self.set_status(account, AccountStatus::Open),
+            Some(AccountStatus::Closed) => {
+                Err(ApplicationError::AccountClosed(account.to_string()))
+            }
+            Some(_) => Err(ApplicationError::AccountExists(account.to_string())),
+        }
+    }
+
+    /// Opens a new, empty account as a sub-account of `parent`. It holds its own funds and signs
+    /// its own orders, but shares the limits of its parents and can move funds to any account of
+    /// the same entity without limits.
+    /// # Errors
+    /// An account with that name exists or was closed, or the parent doesn't exist or is closed
+    pub fn open_sub_account(
+        &mut self,
+        parent: &str,
+        account: &str,
+    ) -> Result<Tx, ApplicationError> {
+        self.check_sub_account(parent, account)?;
+        self.link(account, parent);
+        self.change_status(account, AccountStatus::Open);
+        let meta = self.next_meta(TxContext::default());
+        Ok(self.record(Tx::SubAccount {
+            account: account.to_string(),
+            parent: parent.to_string(),
+            meta,
+        }))
+    }
+
+    /// The parent of a sub-account, `None` for top-level accounts
+    pub fn parent_of(&self, account: &str) -> Option<&str> {
+        self.parents.get(account).map(|parent| parent.as_str())
+    }
+
+    /// The direct sub-accounts of an account, closed ones included, ordered by name
+    pub fn sub_accounts(&self, account: &str) -> Vec<&str> {
+        self.children
+            .get(account)
+            .map(|children| children.iter().map(|child| child.as_str()).collect())
+            .unwrap_or_default()
+    }
+
+    /// The top-level account of the entity that `account` belongs to, the account itself if it
+    /// isn't a sub-account
+    pub fn root_of<'a>(&'a self, account: &'a str) -> &'a str {
+        let mut root = account;
+        while let Some(parent) = self.parent_of(root) {
+            root = parent;
+        }
+        root
+    }
+
+    /// The combined net balance of an account and all of its sub-accounts, recursively
+    /// # Errors
+    /// The account doesn't exist or is closed
+    pub fn aggregate_balance(&self, account: &str) -> Result<i128, ApplicationError> {
+        let mut total = self.net_balance(account)?;
+        let mut pending = self.sub_accounts(account);
+        while let Some(child) = pending.pop() {
+            total += self.net(child).unwrap_or(0);
+            pending.extend(self.sub_accounts(child));
+        }
+        Ok(total)
+    }
+
+    /// Moves funds between two accounts of the same entity. Unlike [`Accounts::send`], this
+    /// doesn't count towards any limits.
+    /// # Errors
+    /// The accounts belong to different entities, or see [`Accounts::send`]
+    pub fn transfer_internal(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: u64,
+    ) -> Result<Tx, ApplicationError> {
+        if !self.is_internal(sender, recipient) {
+            return Err(ApplicationError::NotSameEntity(
+                sender.to_string(),
+                recipient.to_string(),
+            ));
+        }
+        self.send(sender, recipient, amount)
+    }
+
+    /// Moves `amount` from the sender into escrow until `condition` allows it to be released to
+    /// the recipient or refunded. The escrow's id is the sequence number of the transaction. It
+    /// counts as a send towards the sender's limits.
+    /// # Errors
+    /// See [`Accounts::send`]
+    pub fn open_escrow(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: u64,
+        condition: Condition,
+    ) -> Result<Tx, ApplicationError> {
+        self.check_not_reserved(sender)?;
+        self.check_not_reserved(recipient)?;
+        self.balance_of(recipient)?;
+        let leg = Leg::Transfer {
+            sender: sender.to_string(),
+            recipient: ESCROW_ACCOUNT.to_string(),
+            amount,
+        };
+        self.check_limits(&leg, self.clock.now())?;
+        // the sender can pay before the escrow account is opened
+        self.stage(&[Leg::Withdraw {
+            account: sender.to_string(),
+            amount,
+        }])?;
+        if self.status_of(ESCROW_ACCOUNT).is_none() {
+            self.set_status(ESCROW_ACCOUNT, AccountStatus::Open)?;
+        }
+
+        let staged = self.stage(&[leg])?;
+        self.commit(staged);
+        let meta = self.next_meta(TxContext::default());
+        let escrow = Escrow {
+            id: meta.sequence,
+            sender: sender.to_string(),
+            recipient: recipient.to_string(),
+            amount,
+            condition,
+        };
+        self.hold(escrow.clone());
+        let tx = self.record(Tx::EscrowOpened { escrow, meta });
+        self.track_usage(&tx);
+        Ok(tx)
+    }
+
+    /// Pays an escrow out to its recipient, if its condition allows `by` to do so now
+    /// # Errors
+    /// The escrow doesn't exist, the condition isn't met or the recipient can't receive funds
+    pub fn release_escrow(&mut self, id: u64, by: &str) -> Result<Tx, ApplicationError> {
+        let escrow = self.open_escrow_of(id)?;
+        if !escrow.can_release(by, self.clock.now()) {
+            return Err(ApplicationError::EscrowDenied(id));
+        }
+        let (recipient, amount) = (escrow.recipient.clone(), escrow.amount);
+        self.pay_out(id, &recipient, amount, |meta| Tx::EscrowReleased {
+            id,
+            recipient: recipient.clone(),
+            amount,
+            meta,
+        })
+    }
+
+    /// Pays an escrow back to its sender, if its condition allows `by` to do so now
+    /// # Errors
+    /// The escrow doesn't exist, the condition isn't met or the sender can't receive funds
+    pub fn refund_escrow(&mut self, id: u64, by: &str) -> Result<Tx, ApplicationError> {
+        let escrow = self.open_escrow_of(id)?;
+        if !escrow.can_refund(by, self.clock.now()) {
+            return Err(ApplicationError::EscrowDenied(id));
+        }
+        let (sender, amount) = (escrow.sender.clone(), escrow.amount);
+        self.pay_out(id, &sender, amount, |meta| Tx::EscrowRefunded {
+            id,
+            sender: sender.clone(),
+            amount,
+            meta,
+        })
+    }
+
+    /// The open escrow with this id
+    pub fn escrow(&self, id: u64) -> Option<&Escrow> {
+        self.escrows.get(&id)
+    }
+
+    /// Every open escrow, oldest first
+    pub fn escrows(&self) -> Vec<&Escrow> {
+        self.escrows.values().collect()
+    }
+
+    /// Schedules a transfer of `amount` from `sender` to `recipient` at `start` and then at the
+    /// times of `recurrence`. Executions that fail are retried according to `retry`. The schedule's
+    /// id is the sequence number of the transaction.
+    /// # Errors
+    /// Either account doesn't exist or is closed
+    pub fn schedule_transfer(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: u64,
+        start: Timestamp,
+        recurrence: Recurrence,
+        retry: RetryPolicy,
+    ) -> Result<Tx, ApplicationError> {
+        for account in [sender, recipient] {
+            self.check_not_reserved(account)?;
+            self.balance_of(account)?;
+        }
+        let meta = self.next_meta(TxContext::default());
+        let schedule = Schedule {
+            id: meta.sequence,
+            sender: sender.to_string(),
+            recipient: recipient.to_string(),
+            amount,
+            start,
+            recurrence,
+            retry,
+            runs: 0,
+            attempts: 0,
+            next: start,
+        };
+        Ok(self.record(Tx::ScheduleCreated { schedule, meta }))
+    }
+
+    /// Stops a scheduled transfer, nothing more is executed
+    /// # Errors
+    /// No scheduled transfer with this id has occurrences left
+    pub fn cancel_schedule(&mut self, id: u64) -> Result<Tx, ApplicationError> {
+        self.schedule_of(id)?;
+        let meta = self.next_meta(TxContext::default());
+        Ok(self.record(Tx::ScheduleCancelled { id, meta }))
+    }
+
+    /// Executes every scheduled transfer that is due now, oldest first. Each execution is a
+    /// transfer caused by the schedule, or a record of why it failed. Missed occurrences are
+    /// executed one after another. Returns the recorded transactions.
+    pub fn run_schedules(&mut self) -> Vec<Tx> {
+        let now = self.clock.now();
+        let mut txs = vec![];
+        while let Some(schedule) = self
+            .schedules
+            .values()
+            .filter(|s| s.is_due(now))
+            .min_by_key(|s| (s.next, s.id))
+        {
+            let (id, sender, recipient, amount) = (
+                schedule.id,
+                schedule.sender.clone(),
+                schedule.recipient.clone(),
+                schedule.amount,
+            );
+            let context = TxContext::caused_by(Cause::Schedule(id));
+            let tx = match self.send_with(&sender, &recipient, amount, context) {
+                Ok(tx) => tx,
+                Err(e) => {
+                    let meta = self.next_meta(TxContext::caused_by(Cause::Schedule(id)));
+                    self.record(Tx::ScheduleFailed {
+                        id,
+                        reason: format!("{:?}", e),
+                        meta,
+                    })
+                }
+            };
+            txs.push(tx);
+        }
+        txs
+    }
+
+    /// The scheduled transfer with this id, if it has occurrences left
+    pub fn schedule(&self, id: u64) -> Option<&Schedule> {
+        self.schedules.get(&id)
+    }
+
+    /// Every scheduled transfer that has occurrences left, oldest first
+    pub fn schedules(&self) -> Vec<&Schedule> {
+        self.schedules.values().collect()
+    }
+
+    /// The interest and custody fee rates
+    pub fn interest_schedule(&self) -> &InterestSchedule {
+        &self.interest
+    }
+
+    /// Replaces the interest and custody fee rates from the next accrual on. What accrued so far
+    /// is kept. The account of the schedule has to be open by the time interest is posted.
+    /// # Errors
+    /// The account of the schedule is reserved
+    pub fn set_interest_schedule(
+        &mut self,
+        schedule: InterestSchedule,
+    ) -> Result<Tx, ApplicationError> {
+        self.check_not_reserved(&schedule.account)?;
+        self.interest = schedule.clone();
+        let meta = self.next_meta(TxContext::default());
+        Ok(self.record(Tx::InterestScheduleSet { schedule, meta }))
+    }
+
+    /// Accrues interest and custody fees on every balance and overdraft interest on every debt at
+    /// the rates of the ledger's asset, for each day since the previous accrual (one day the first
+    /// time). Meant to be called once a day, balances are taken as they are now. Returns `None` if
+    /// there is nothing to accrue.
+    pub fn accrue_interest(&mut self) -> Option<Tx> {
+        let day = self.clock.now() / DAY;
+        let days = self
+            .accrued_day
+            .map_or(1, |accrued| day.saturating_sub(accrued));
+        let rates = *self.interest.rates(&self.market.quote.code)?;
+        if days == 0 {
+            return None;
+        }
+        let kinds = [
+            AccrualKind::Interest,
+            AccrualKind::CustodyFee,
+            AccrualKind::Overdraft,
+        ];
+        let accruals = self
+            .accounts
+            .iter()
+            .filter(|(account, _)| !self.accrues_nothing(account))
+            .flat_map(|(account, balance)| {
+                let debt = self.debts.get(account).copied().unwrap_or(0);
+                kinds.into_iter().map(move |kind| Accrual {
+                    account: account.clone(),
+                    kind,
+                    amount: match kind {
+                        AccrualKind::Overdraft => interest::accrual(debt, rates.bps(kind), days),
+                        _ => interest::accrual(*balance, rates.bps(kind), days),
+                    },
                 })
-                .ok_or(ApplicationError::AccountOverFunded(
-                    signer.to_string(),
-                    amount,
-                ))
-                // Using map() here is an easy way to only manipulate the non-error result
-                .map(|_| Tx::Deposit {
-                    account: signer.to_string(),
+            })
+            .filter(|accrual| accrual.amount > 0)
+            .collect();
+        let meta = self.next_meta(TxContext::default());
+        Some(self.record(Tx::InterestAccrued {
+            day,
+            accruals,
+            meta,
+        }))
+    }
+
+    /// The interest or custody fee accrued by `account` and not posted yet, in fractions of
+    /// [`interest::ACCRUAL_SCALE`]
+    pub fn accrued(&self, account: &str, kind: AccrualKind) -> u128 {
+        self.accrued
+            .get(&(account.to_string(), kind))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Everything accrued and not posted yet, ordered by account
+    pub fn accruals(&self) -> Vec<Accrual> {
+        self.accrued
+            .iter()
+            .map(|((account, kind), amount)| Accrual {
+                account: account.clone(),
+                kind: *kind,
+                amount: *amount,
+            })
+            .collect()
+    }
+
+    /// The day (since the UNIX epoch) that interest was last accrued up to
+    pub fn accrued_day(&self) -> Option<u64> {
+        self.accrued_day
+    }
+
+    /// Posts the whole minor units of accrued interest, custody fees and overdraft interest
+    /// against the house account, the fractions stay accrued. Charges are only made as far as
+    /// the balance and credit line cover them. Accounts that are closed, or frozen in case of
+    /// charges, are skipped. All postings happen, or none of them.
+    /// # Errors
+    /// The house account doesn't exist, is frozen or can't pay the interest
+    pub fn post_interest(&mut self) -> Result<Vec<Tx>, ApplicationError> {
+        let house = self.interest.account.clone();
+        let mut available: BTreeMap<&str, u128> = BTreeMap::new();
+        let mut postings = vec![];
+        // charges first, so that the house account can pay interest out of them
+        for kind in [
+            AccrualKind::CustodyFee,
+            AccrualKind::Overdraft,
+            AccrualKind::Interest,
+        ] {
+            for ((account, _), accrued) in self.accrued.iter().filter(|((_, k), _)| *k == kind) {
+                let Ok(funds) = self.available(account) else {
+                    continue;
+                };
+                let funds = available.entry(account).or_insert(funds);
+                let whole = interest::whole_units(*accrued);
+                let amount = if !kind.is_charge() {
+                    whole
+                } else if self.check_not_frozen(account).is_ok() {
+                    whole.min(u64::try_from(*funds).unwrap_or(u64::MAX))
+                } else {
+                    0
+                };
+                if amount == 0 || *account == house {
+                    continue;
+                }
+                *funds = if kind.is_charge() {
+                    *funds - amount as u128
+                } else {
+                    *funds + amount as u128
+                };
+                postings.push((account.clone(), kind, amount));
+            }
+        }
+
+        let legs: Vec<Leg> = postings
+            .iter()
+            .map(|(account, kind, amount)| {
+                let (sender, recipient) = if kind.is_charge() {
+                    (account, &house)
+                } else {
+                    (&house, account)
+                };
+                Leg::Transfer {
+                    sender: sender.clone(),
+                    recipient: recipient.clone(),
+                    amount: *amount,
+                }
+            })
+            .collect();
+        let staged = self.stage(&legs)?;
+        self.commit(staged);
+        Ok(postings
+            .into_iter()
+            .map(|(account, kind, amount)| {
+                let meta = self.next_meta(TxContext::default());
+                self.record(Tx::AccrualPosted {
+                    account,
+                    house: house.clone(),
+                    kind,
                     amount,
+                    meta,
                 })
+            })
+            .collect())
+    }
+
+    /// The maker and taker fees
+    pub fn fee_schedule(&self) -> &FeeSchedule {
+        &self.fees
+    }
+
+    /// Replaces the maker and taker fees from the next match on. The account of the schedule has
+    /// to be open by the time fees are charged.
+    /// # Errors
+    /// The account of the schedule is reserved
+    pub fn set_fee_schedule(&mut self, schedule: FeeSchedule) -> Result<Tx, ApplicationError> {
+        self.check_not_reserved(&schedule.account)?;
+        self.fees = schedule.clone();
+        let meta = self.next_meta(TxContext::default());
+        Ok(self.record(Tx::FeeScheduleSet { schedule, meta }))
+    }
+
+    /// The margin needed for positions, `None` if there are no requirements
+    pub fn margin_requirements(&self) -> Option<&MarginRequirements> {
+        self.margin.as_ref()
+    }
+
+    /// Requires margin for positions from now on. The account of the requirements has to be open
+    /// by the time a position is liquidated.
+    /// # Errors
+    /// The account of the requirements is reserved
+    pub fn set_margin_requirements(
+        &mut self,
+        requirements: MarginRequirements,
+    ) -> Result<Tx, ApplicationError> {
+        self.check_not_reserved(&requirements.account)?;
+        self.margin = Some(requirements.clone());
+        let meta = self.next_meta(TxContext::default());
+        Ok(self.record(Tx::MarginRequirementsSet { requirements, meta }))
+    }
+
+    /// The pre-trade limits of `account` in the market `symbol`: its own, the market's or none
+    pub fn risk_limits_of(&self, account: &str, symbol: &str) -> RiskLimits {
+        self.risk_limits
+            .get(&(account.to_string(), symbol.to_string()))
+            .or_else(|| self.market_risk_limits.get(symbol))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Every market with pre-trade limits and its limits, ordered by symbol
+    pub fn market_risk_limits(&self) -> Vec<(&str, &RiskLimits)> {
+        self.market_risk_limits
+            .iter()
+            .map(|(symbol, limits)| (symbol.as_str(), limits))
+            .collect()
+    }
+
+    /// Every account with pre-trade limits of its own, the market and its limits, ordered by
+    /// account and symbol
+    pub fn risk_limits(&self) -> Vec<(&str, &str, &RiskLimits)> {
+        self.risk_limits
+            .iter()
+            .map(|((account, symbol), limits)| (account.as_str(), symbol.as_str(), limits))
+            .collect()
+    }
+
+    /// Sets the pre-trade limits of every account in the market `symbol` that doesn't have limits
+    /// of its own
+    pub fn set_market_risk_limits(&mut self, symbol: &str, limits: RiskLimits) -> Tx {
+        self.change_risk_limits(None, symbol, limits.clone());
+        let meta = self.next_meta(TxContext::default());
+        self.record(Tx::RiskLimitsSet {
+            account: None,
+            symbol: symbol.to_string(),
+            limits,
+            meta,
+        })
+    }
+
+    /// Sets the pre-trade limits of `account` in the market `symbol`, they replace the ones of
+    /// the market
+    /// # Errors
+    /// The account doesn't exist, is closed or reserved
+    pub fn set_risk_limits(
+        &mut self,
+        account: &str,
+        symbol: &str,
+        limits: RiskLimits,
+    ) -> Result<Tx, ApplicationError> {
+        self.check_not_reserved(account)?;
+        self.balance_of(account)?;
+        self.change_risk_limits(Some(account), symbol, limits.clone());
+        let meta = self.next_meta(TxContext::default());
+        Ok(self.record(Tx::RiskLimitsSet {
+            account: Some(account.to_string()),
+            symbol: symbol.to_string(),
+            limits,
+            meta,
+        }))
+    }
+
+    /// The position of `account` in the market `symbol`, if it ever traded there
+    pub fn position(&self, account: &str, symbol: &str) -> Option<&Position> {
+        self.positions
+            .get(&(account.to_string(), symbol.to_string()))
+    }
+
+    /// Every position, ordered by account and symbol
+    pub fn positions(&self) -> Vec<&Position> {
+        self.positions.values().collect()
+    }
+
+    /// Records `fill` of `account`, which moves its position. The payment isn't part of it.
+    /// # Errors
+    /// The account doesn't exist or is closed
+    pub fn fill(
+        &mut self,
+        account: &str,
+        fill: Fill,
+        context: TxContext,
+    ) -> Result<Tx, ApplicationError> {
+        self.balance_of(account)?;
+        let meta = self.next_meta(context);
+        Ok(self.record(Tx::PositionFilled {
+            account: account.to_string(),
+            fill,
+            meta,
+        }))
+    }
+
+    /// Closes the position of `account` in `symbol` at `price` against the `house` account, which
+    /// takes it over. Closing it costs or yields `notional`. A short position pays as much of it
+    /// as its balance and credit line cover, the house account absorbs the rest.
+    /// # Errors
+    /// There is no open position, the house account can't pay for a long one or the account of a
+    /// short one is frozen
+    pub fn liquidate(
+        &mut self,
+        account: &str,
+        house: &str,
+        symbol: &str,
+        price: u64,
+        notional: u64,
+    ) -> Result<Tx, ApplicationError> {
+        let (fill, leg) = self.liquidation(account, house, symbol, price, notional)?;
+        let staged = self.stage(&[leg])?;
+        self.commit(staged);
+        let meta = self.next_meta(TxContext::default().with_memo("liquidation"));
+        Ok(self.record(Tx::Liquidated {
+            account: account.to_string(),
+            house: house.to_string(),
+            fill,
+            meta,
+        }))
+    }
+
+    /// Checks that [`Accounts::liquidate`] would succeed without changing anything
+    /// # Errors
+    /// See [`Accounts::liquidate`]
+    pub fn check_liquidation(
+        &self,
+        account: &str,
+        house: &str,
+        symbol: &str,
+        price: u64,
+        notional: u64,
+    ) -> Result<(), ApplicationError> {
+        let (_, leg) = self.liquidation(account, house, symbol, price, notional)?;
+        self.check_batch(&[leg])
+    }
+
+    /// The account's side of a liquidation and the payment that goes with it
+    fn liquidation(
+        &self,
+        account: &str,
+        house: &str,
+        symbol: &str,
+        price: u64,
+        notional: u64,
+    ) -> Result<(Fill, Leg), ApplicationError> {
+        let size = self.position(account, symbol).map_or(0, |p| p.size);
+        if size == 0 || account == house {
+            return Err(ApplicationError::PositionNotFound(
+                account.to_string(),
+                symbol.to_string(),
+            ));
+        }
+        let (side, notional) = if size > 0 {
+            (Side::Sell, notional)
         } else {
-            self.accounts.insert(signer.to_string(), amount);
-            Ok(Tx::Deposit {
+            let available = u64::try_from(self.available(account)?).unwrap_or(u64::MAX);
+            (Side::Buy, notional.min(available))
+        };
+        let (sender, recipient) = match side {
+            Side::Buy => (account, house),
+            Side::Sell => (house, account),
+        };
+        let leg = Leg::Transfer {
+            sender: sender.to_string(),
+            recipient: recipient.to_string(),
+            amount: notional,
+        };
+        let fill = Fill {
+            symbol: symbol.to_string(),
+            side,
+            amount: u64::try_from(size.unsigned_abs()).unwrap_or(u64::MAX),
+            price,
+            notional,
+        };
+        Ok((fill, leg))
+    }
+
+    /// Stops the account from sending funds, incoming funds are still accepted
+    /// # Errors
+    /// The account doesn't exist, is frozen already or closed
+    pub fn freeze(&mut self, account: &str) -> Result<Tx, ApplicationError> {
+        self.check_not_reserved(account)?;
+        self.check_status_change(account, AccountStatus::Frozen)?;
+        self.set_status(account, AccountStatus::Frozen)
+    }
+
+    /// Lifts a [`Accounts::freeze`]
+    /// # Errors
+    /// The account doesn't exist, isn't frozen or closed
+    pub fn unfreeze(&mut self, account: &str) -> Result<Tx, ApplicationError> {
+        self.check_not_reserved(account)?;
+        if self.status_of(account) == Some(AccountStatus::Open) {
+            return Err(ApplicationError::AccountNotFrozen(account.to_string()));
+        }
+        self.check_status_change(account, AccountStatus::Open)?;
+        self.set_status(account, AccountStatus::Open)
+    }
+
+    /// Closes an empty account for good
+    /// # Errors
+    /// The account doesn't exist, is closed already, still holds funds, owes something, has open
+    /// sub-accounts or holds a position
+    pub fn close(&mut self, account: &str) -> Result<Tx, ApplicationError> {
+        self.check_not_reserved(account)?;
+        self.check_status_change(account, AccountStatus::Closed)?;
+        self.set_status(account, AccountStatus::Closed)
+    }
+
+    /// Adds the `amount` to an existing account, frozen or not
+    /// # Errors
+    /// The account doesn't exist or is closed, or attempted overflow
+    pub fn deposit(&mut self, signer: &str, amount: u64) -> Result<Tx, ApplicationError> {
+        self.deposit_with(signer, amount, TxContext::default())
+    }
+
+    /// Like [`Accounts::deposit`], with a memo and/or cause attached to the transaction. With an
+    /// idempotency key, a retry of the same request returns the original transaction.
+    pub fn deposit_with(
+        &mut self,
+        signer: &str,
+        amount: u64,
+        context: TxContext,
+    ) -> Result<Tx, ApplicationError> {
+        self.execute_one(
+            Leg::Deposit {
                 account: signer.to_string(),
                 amount,
-            })
-        }
+            },
+            context,
+        )
     }
 
     /// Withdraws the `amount` from the `signer` account.
     /// # Errors
-    /// Attempted overflow
+    /// The account doesn't exist, is frozen or closed, doesn't have the funds or the withdrawal
+    /// would exceed its limit
     pub fn withdraw(&mut self, signer: &str, amount: u64) -> Result<Tx, ApplicationError> {
-        if let Some(account) = self.accounts.get_mut(signer) {
-            (*account)
-                .checked_sub(amount)
-                .and_then(|r| {
-                    *account = r;
-                    Some(r)
-                })
-                .ok_or(ApplicationError::AccountUnderFunded(
-                    signer.to_string(),
-                    amount,
-                ))
-                // Using map() here is an easy way to only manipulate the non-error result
-                .map(|_| Tx::Withdraw {
-                    account: signer.to_string(),
-                    amount,
-                })
-        } else {
-            Err(ApplicationError::AccountNotFound(signer.to_string()))
-        }
+        self.withdraw_with(signer, amount, TxContext::default())
     }
 
-    /// Withdraws the amount from the sender account and deposits it in the recipient account.
+    /// Like [`Accounts::withdraw`], with a memo and/or cause attached to the transaction. With an
+    /// idempotency key, a retry of the same request returns the original transaction.
+    pub fn withdraw_with(
+        &mut self,
+        signer: &str,
+        amount: u64,
+        context: TxContext,
+    ) -> Result<Tx, ApplicationError> {
+        self.execute_one(
+            Leg::Withdraw {
+                account: signer.to_string(),
+                amount,
+            },
+            context,
+        )
+    }
+
+    /// Moves the amount from the sender account to the recipient account. Both have to exist, and
+    /// the sender can't be frozen.
     ///
     /// # Errors
-    /// The account doesn't exist
+    /// An account doesn't exist or is closed, the sender is frozen, doesn't have the funds or
+    /// would exceed its limits, or the recipient would overflow
     pub fn send(
         &mut self,
         sender: &str,
         recipient: &str,
         amount: u64,
-    ) -> Result<(Tx, Tx), ApplicationError> {
-        if self.accounts.contains_key(sender)  // sender exists
-            && self.accounts.contains_key(recipient) // recipient exists
-            && self
-                .accounts
-                .get(sender)
-                .map(|amt| *amt >= amount) // sender has sufficient funds
-                .unwrap_or(false)
-        {
-            // The ? operator is a built-in shorthand for
-            // if let Err(e) = my_func_call() { return Err(e); }
-            let tx_withdraw = self.withdraw(sender, amount)?;
-            self.deposit(recipient, amount)
-                .map_err(|e| {
-                    // return the funds to the sender on error
-                    self.deposit(sender, amount).unwrap();
-                    e
-                })
-                .map(|tx_deposit| (tx_withdraw, tx_deposit))
+    ) -> Result<Tx, ApplicationError> {
+        self.send_with(sender, recipient, amount, TxContext::default())
+    }
+
+    /// Like [`Accounts::send`], with a memo and/or cause attached to the transaction. With an
+    /// idempotency key, a retry of the same request returns the original transaction.
+    pub fn send_with(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: u64,
+        context: TxContext,
+    ) -> Result<Tx, ApplicationError> {
+        self.execute_one(
+            Leg::Transfer {
+                sender: sender.to_string(),
+                recipient: recipient.to_string(),
+                amount,
+            },
+            context,
+        )
+    }
+
+    /// Applies all `legs` in order, or none of them. Every leg is validated against the balances
+    /// the previous legs would leave behind before anything changes. Each leg is recorded as
+    /// its own [`Tx`] carrying the same `context`.
+    /// # Errors
+    /// The first leg that can't be applied
+    pub fn execute_batch(
+        &mut self,
+        legs: Vec<Leg>,
+        context: TxContext,
+    ) -> Result<Vec<Tx>, ApplicationError> {
+        let staged = self.stage(&legs)?;
+        self.commit(staged);
+        Ok(legs
+            .into_iter()
+            .map(|leg| {
+                let meta = self.next_meta(context.clone());
+                self.record(leg.into_tx(meta))
+            })
+            .collect())
+    }
+
+    /// Applies a single deposit, withdrawal or send. A request with an idempotency key that was
+    /// seen within the window gets the original transaction back instead.
+    fn execute_one(&mut self, leg: Leg, context: TxContext) -> Result<Tx, ApplicationError> {
+        let now = self.clock.now();
+        let key = context.idempotency_key.clone();
+        if let Some(key) = &key {
+            if let Some(tx) = self.requests.get(key, now) {
+                return match tx.leg() {
+                    Some(original) if original == leg => Ok(tx.clone()),
+                    _ => Err(ApplicationError::IdempotencyKeyReused(key.clone())),
+                };
+            }
+        }
+
+        match &leg {
+            Leg::Deposit { account, .. } | Leg::Withdraw { account, .. } => {
+                self.check_not_reserved(account)?
+            }
+            Leg::Transfer {
+                sender, recipient, ..
+            } => {
+                self.check_not_reserved(sender)?;
+                self.check_not_reserved(recipient)?;
+            }
+        }
+        self.check_limits(&leg, now)?;
+        let staged = self.stage(std::slice::from_ref(&leg))?;
+        self.commit(staged);
+        let meta = self.next_meta(context);
+        let tx = self.record(leg.into_tx(meta));
+        self.track_usage(&tx);
+        if let Some(key) = key {
+            self.requests.insert(&key, now, tx.clone());
+        }
+        Ok(tx)
+    }
+
+    /// Checks that [`Accounts::execute_batch`] would accept `legs` without changing anything
+    /// # Errors
+    /// The first leg that can't be applied
+    pub fn check_batch(&self, legs: &[Leg]) -> Result<(), ApplicationError> {
+        self.stage(legs).map(|_| ())
+    }
+
+    /// Computes the new net balances of every account touched by `legs` without changing
+    /// anything. Debits can go below zero as far as the account's credit line allows.
+    fn stage(&self, legs: &[Leg]) -> Result<BTreeMap<String, i128>, ApplicationError> {
+        let mut staged: BTreeMap<String, i128> = BTreeMap::new();
+        let current = |staged: &BTreeMap<String, i128>, account: &str| {
+            staged.get(account).copied().or_else(|| self.net(account))
+        };
+        for leg in legs {
+            match leg {
+                Leg::Deposit { account, amount } => {
+                    let balance = current(&staged, account).ok_or_else(|| self.missing(account))?;
+                    let new = credit(account, balance, *amount)?;
+                    staged.insert(account.clone(), new);
+                }
+                Leg::Withdraw { account, amount } => {
+                    let balance = current(&staged, account).ok_or_else(|| self.missing(account))?;
+                    self.check_not_frozen(account)?;
+                    let new = debit(account, balance, *amount, self.credit_limit_of(account))?;
+                    staged.insert(account.clone(), new);
+                }
+                Leg::Transfer {
+                    sender,
+                    recipient,
+                    amount,
+                } => {
+                    let sender_balance =
+                        current(&staged, sender).ok_or_else(|| self.missing(sender))?;
+                    current(&staged, recipient).ok_or_else(|| self.missing(recipient))?;
+                    self.check_not_frozen(sender)?;
+                    let new = debit(
+                        sender,
+                        sender_balance,
+                        *amount,
+                        self.credit_limit_of(sender),
+                    )?;
+                    staged.insert(sender.clone(), new);
+                    // read the recipient after the debit in case it's the sender
+                    let recipient_balance = current(&staged, recipient).unwrap_or(0);
+                    let new = credit(recipient, recipient_balance, *amount)?;
+                    staged.insert(recipient.clone(), new);
+                }
+            }
+        }
+        Ok(staged)
+    }
+
+    /// Balance minus debt of an account that isn't closed
+    fn net(&self, account: &str) -> Option<i128> {
+        let balance = *self.accounts.get(account)? as i128;
+        Some(balance - self.debts.get(account).copied().unwrap_or(0) as i128)
+    }
+
+    /// The error for an account without a balance
+    fn missing(&self, account: &str) -> ApplicationError {
+        match self.status_of(account) {
+            Some(AccountStatus::Closed) => ApplicationError::AccountClosed(account.to_string()),
+            _ => ApplicationError::AccountNotFound(account.to_string()),
+        }
+    }
+
+    fn check_not_frozen(&self, account: &str) -> Result<(), ApplicationError> {
+        match self.status_of(account) {
+            Some(AccountStatus::Frozen) => {
+                Err(ApplicationError::AccountFrozen(account.to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether `account` can move into status `to`
+    fn check_status_change(
+        &self,
+        account: &str,
+        to: AccountStatus,
+    ) -> Result<(), ApplicationError> {
+        use AccountStatus::*;
+        match (self.status_of(account), to) {
+            (None, Open) => Ok(()),
+            (None, _) => Err(ApplicationError::AccountNotFound(account.to_string())),
+            (Some(Closed), _) => Err(ApplicationError::AccountClosed(account.to_string())),
+            (Some(Open), Open) => Err(ApplicationError::AccountExists(account.to_string())),
+            (Some(Frozen), Frozen) => Err(ApplicationError::AccountFrozen(account.to_string())),
+            (Some(_), Closed) => {
+                if let Some(balance) = self.accounts.get(account).filter(|b| **b > 0) {
+                    return Err(ApplicationError::AccountNotEmpty(
+                        account.to_string(),
+                        *balance,
+                    ));
+                }
+                if let Some(debt) = self.debts.get(account) {
+                    return Err(ApplicationError::AccountInDebt(account.to_string(), *debt));
+                }
+                let open = self
+                    .sub_accounts(account)
+                    .into_iter()
+                    .filter(|child| self.status_of(child) != Some(Closed))
+                    .count();
+                if open > 0 {
+                    return Err(ApplicationError::AccountHasSubAccounts(
+                        account.to_string(),
+                        open,
+                    ));
+                }
+                if let Some(position) = self
+                    .positions
+                    .values()
+                    .find(|p| p.account == account && p.size != 0)
+                {
+                    return Err(ApplicationError::AccountHasPosition(
+                        account.to_string(),
+                        position.symbol.clone(),
+                    ));
+                }
+                Ok(())
+            }
+            (Some(_), _) => Ok(()),
+        }
+    }
+
+    /// Moves `account` into `status` and records the change
+    fn set_status(&mut self, account: &str, status: AccountStatus) -> Result<Tx, ApplicationError> {
+        self.change_status(account, status);
+        let meta = self.next_meta(TxContext::default());
+        Ok(self.record(Tx::StatusChange {
+            account: account.to_string(),
+            status,
+            meta,
+        }))
+    }
+
+    /// Nothing is held in minor units of the asset yet, so it can still change
+    fn check_no_amounts(&self) -> Result<(), ApplicationError> {
+        let in_use = self.accounts.values().any(|balance| *balance > 0)
+            || !self.debts.is_empty()
+            || !self.credit_limits.is_empty()
+            || !self.limits.all_limits().is_empty()
+            || self.positions.values().any(|p| p.size != 0)
+            || !self.accrued.is_empty()
+            || !self.escrows.is_empty()
+            || !self.schedules.is_empty();
+        if in_use {
+            Err(ApplicationError::AssetInUse(self.market.quote.code.clone()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Accounts for internal use can't be used directly
+    fn check_not_reserved(&self, account: &str) -> Result<(), ApplicationError> {
+        if account == ESCROW_ACCOUNT {
+            Err(ApplicationError::ReservedAccount(account.to_string()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Applies the movement of funds of `tx`, if any
+    fn stage_and_commit(&mut self, tx: &Tx) -> Result<(), ApplicationError> {
+        let staged = self.stage(&tx.leg().into_iter().collect::<Vec<_>>())?;
+        self.commit(staged);
+        Ok(())
+    }
+
+    fn open_escrow_of(&self, id: u64) -> Result<&Escrow, ApplicationError> {
+        self.escrows
+            .get(&id)
+            .ok_or(ApplicationError::EscrowNotFound(id))
+    }
+
+    /// Whether escrow `id` can be paid out as `amount` to `to`, its recipient if `released`,
+    /// otherwise its sender
+    fn check_payout(
+        &self,
+        id: u64,
+        to: &str,
+        amount: u64,
+        released: bool,
+    ) -> Result<(), ApplicationError> {
+        let escrow = self.open_escrow_of(id)?;
+        let expected = if released {
+            &escrow.recipient
         } else {
-            if !self.accounts.contains_key(sender) {
-                Err(ApplicationError::AccountNotFound(sender.to_string()))
-            } else {
-                Err(ApplicationError::AccountNotFound(recipient.to_string()))
+            &escrow.sender
+        };
+        if escrow.amount == amount && expected == to {
+            Ok(())
+        } else {
+            Err(ApplicationError::EscrowNotFound(id))
+        }
+    }
+
+    /// Moves the funds of escrow `id` to `to` and records the transaction made by `tx`
+    fn pay_out(
+        &mut self,
+        id: u64,
+        to: &str,
+        amount: u64,
+        tx: impl FnOnce(TxMeta) -> Tx,
+    ) -> Result<Tx, ApplicationError> {
+        let staged = self.stage(&[Leg::Transfer {
+            sender: ESCROW_ACCOUNT.to_string(),
+            recipient: to.to_string(),
+            amount,
+        }])?;
+        self.commit(staged);
+        self.unhold(id);
+        let meta = self.next_meta(TxContext::default());
+        Ok(self.record(tx(meta)))
+    }
+
+    /// Keeps track of an open escrow and updates the hash
+    fn hold(&mut self, escrow: Escrow) {
+        self.hash.add(&escrow_entry(&escrow));
+        self.escrows.insert(escrow.id, escrow);
+    }
+
+    /// Forgets a paid out escrow and updates the hash
+    fn unhold(&mut self, id: u64) {
+        if let Some(escrow) = self.escrows.remove(&id) {
+            self.hash.remove(&escrow_entry(&escrow));
+        }
+    }
+
+    /// Whether `account` can be opened as a sub-account of `parent`
+    fn check_sub_account(&self, parent: &str, account: &str) -> Result<(), ApplicationError> {
+        self.check_not_reserved(account)?;
+        self.check_not_reserved(parent)?;
+        match self.status_of(parent) {
+            None => return Err(ApplicationError::AccountNotFound(parent.to_string())),
+            Some(AccountStatus::Closed) => {
+                return Err(ApplicationError::AccountClosed(parent.to_string()))
+            }
+            Some(_) => {}
+        }
+        self.check_status_change(account, AccountStatus::Open)
+    }
+
+    /// Makes `account` a sub-account of `parent` and updates the hash
+    fn link(&mut self, account: &str, parent: &str) {
+        self.parents.insert(account.to_string(), parent.to_string());
+        self.children
+            .entry(parent.to_string())
+            .or_default()
+            .insert(account.to_string());
+        self.hash.add(&link_entry(account, parent));
+    }
+
+    /// Moves `account` into `status` and updates the hash. Closed accounts lose their balance entry.
+    fn change_status(&mut self, account: &str, status: AccountStatus) {
+        let previous = self.statuses.insert(account.to_string(), status);
+        let balance = match (previous, self.accounts.get(account)) {
+            (Some(previous), Some(balance)) => {
+                self.hash
+                    .remove(&balance_entry(account, *balance, previous));
+                *balance
+            }
+            _ => 0,
+        };
+        if status == AccountStatus::Closed {
+            self.accounts.remove(account);
+        } else {
+            self.accounts.insert(account.to_string(), balance);
+            self.hash.add(&balance_entry(account, balance, status));
+        }
+    }
+
+    /// Stores the `staged` net balances as balances and debts and updates the hash
+    fn commit(&mut self, staged: BTreeMap<String, i128>) {
+        for (account, net) in staged {
+            let balance = u64::try_from(net.max(0)).unwrap_or(u64::MAX);
+            let debt = u64::try_from((-net).max(0)).unwrap_or(u64::MAX);
+            let status = self.status_of(&account).unwrap_or(AccountStatus::Open);
+            if let Some(old) = self.accounts.insert(account.clone(), balance) {
+                self.hash.remove(&balance_entry(&account, old, status));
+            }
+            self.hash.add(&balance_entry(&account, balance, status));
+            if let Some(old) = self.debts.remove(&account) {
+                self.hash.remove(&debt_entry(&account, old));
+            }
+            if debt > 0 {
+                self.hash.add(&debt_entry(&account, debt));
+                self.debts.insert(account, debt);
+            }
+        }
+    }
+
+    /// Sets the credit limit of `account` and updates the hash
+    fn change_credit_limit(&mut self, account: &str, limit: u64) {
+        if let Some(old) = self.credit_limits.remove(account) {
+            self.hash.remove(&credit_limit_entry(account, old));
+        }
+        if limit > 0 {
+            self.hash.add(&credit_limit_entry(account, limit));
+            self.credit_limits.insert(account.to_string(), limit);
+        }
+    }
+
+    /// Replaces the limits of `account` and updates the hash
+    fn change_limits(&mut self, account: &str, limits: Limits) {
+        if let Some(old) = self.limits.limits(account) {
+            self.hash.remove(&limits_entry(account, old));
+        }
+        self.hash.add(&limits_entry(account, &limits));
+        self.limits.set_limits(account, limits);
+    }
+
+    /// Replaces the pre-trade limits of `account` in `symbol`, or the ones of the market
+    fn change_risk_limits(&mut self, account: Option<&str>, symbol: &str, limits: RiskLimits) {
+        match account {
+            Some(account) => {
+                self.risk_limits
+                    .insert((account.to_string(), symbol.to_string()), limits);
+            }
+            None => {
+                self.market_risk_limits.insert(symbol.to_string(), limits);
             }
         }
     }
+
+    /// Counts withdrawals, transfers and escrowed funds towards the limits, except transfers that
+    /// settle a trade and transfers within an entity. They count for the account and all its
+    /// parents.
+    fn track_usage(&mut self, tx: &Tx) {
+        let timestamp = tx.meta().timestamp;
+        match tx {
+            Tx::Withdraw {
+                account, amount, ..
+            } => {
+                for account in self.lineage(account) {
+                    self.limits.record_withdrawal(&account, *amount, timestamp);
+                }
+            }
+            Tx::Transfer {
+                sender,
+                recipient,
+                amount,
+                meta,
+            } if !matches!(meta.cause, Some(Cause::Trade { .. }))
+                && !self.is_internal(sender, recipient) =>
+            {
+                for account in self.lineage(sender) {
+                    self.limits.record_send(&account, *amount, timestamp);
+                }
+            }
+            Tx::EscrowOpened { escrow, .. } => {
+                for account in self.lineage(&escrow.sender) {
+                    self.limits.record_send(&account, escrow.amount, timestamp);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Checks `leg` against the limits of the account it draws from and of all its parents.
+    /// Transfers within an entity aren't limited.
+    fn check_limits(&self, leg: &Leg, now: Timestamp) -> Result<(), ApplicationError> {
+        let (account, amount) = match leg {
+            Leg::Deposit { .. } => return Ok(()),
+            Leg::Withdraw { account, amount } => (account, *amount),
+            Leg::Transfer {
+                sender,
+                recipient,
+                amount,
+            } => {
+                if self.is_internal(sender, recipient) {
+                    return Ok(());
+                }
+                (sender, *amount)
+            }
+        };
+        for limited in self.lineage(account) {
+            let checked = match leg {
+                Leg::Withdraw { .. } => self.limits.check_withdrawal(&limited, amount, now),
+                _ => self.limits.check_send(&limited, amount, now),
+            };
+            checked.map_err(|(limit, remaining)| {
+                ApplicationError::LimitExceeded(limited.clone(), limit, remaining)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// `account` followed by its parent, the parent's parent and so on
+    fn lineage(&self, account: &str) -> Vec<String> {
+        let mut lineage = vec![account.to_string()];
+        let mut current = account;
+        while let Some(parent) = self.parent_of(current) {
+            lineage.push(parent.to_string());
+            current = parent;
+        }
+        lineage
+    }
+
+    /// Whether both accounts belong to the same entity
+    fn is_internal(&self, sender: &str, recipient: &str) -> bool {
+        self.root_of(sender) == self.root_of(recipient)
+    }
+
+    /// Assigns the next sequence number and the current time
+    fn next_meta(&mut self, context: TxContext) -> TxMeta {
+        self.sequence += 1;
+        TxMeta::new(self.sequence, self.clock.now(), context)
+    }
+
+    /// Appends a transaction to the journal, moves the scheduled transfers and accrued interest
+    /// along with it and hands it back
+    fn record(&mut self, tx: Tx) -> Tx {
+        self.follow_schedules(&tx);
+        self.follow_accruals(&tx);
+        self.follow_positions(&tx);
+        self.journal.push(tx.clone());
+        tx
+    }
+
+    /// Moves the scheduled transfers along with `tx`: creates, cancels or reschedules them after
+    /// an execution. A transfer only executes a schedule if it matches it.
+    fn follow_schedules(&mut self, tx: &Tx) {
+        match tx {
+            Tx::ScheduleCreated { schedule, .. } => self.put_schedule(schedule.clone()),
+            Tx::ScheduleCancelled { id, .. } => {
+                self.take_schedule(*id);
+            }
+            Tx::ScheduleFailed { id, meta, .. } => {
+                self.reschedule(*id, |schedule| schedule.failed(meta.timestamp))
+            }
+            Tx::Transfer {
+                sender,
+                recipient,
+                amount,
+                meta,
+            } => {
+                let Some(Cause::Schedule(id)) = meta.cause else {
+                    return;
+                };
+                let executes = self.schedules.get(&id).is_some_and(|s| {
+                    s.sender == *sender && s.recipient == *recipient && s.amount == *amount
+                });
+                if executes {
+                    self.reschedule(id, Schedule::succeeded);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Updates schedule `id` with `next`, drops it if it has no occurrences left
+    fn reschedule(&mut self, id: u64, next: impl FnOnce(&mut Schedule) -> bool) {
+        if let Some(mut schedule) = self.take_schedule(id) {
+            if next(&mut schedule) {
+                self.put_schedule(schedule);
+            }
+        }
+    }
+
+    /// Keeps track of a scheduled transfer and updates the hash
+    fn put_schedule(&mut self, schedule: Schedule) {
+        self.hash.add(&schedule_entry(&schedule));
+        self.schedules.insert(schedule.id, schedule);
+    }
+
+    /// Forgets a scheduled transfer and updates the hash
+    fn take_schedule(&mut self, id: u64) -> Option<Schedule> {
+        let schedule = self.schedules.remove(&id)?;
+        self.hash.remove(&schedule_entry(&schedule));
+        Some(schedule)
+    }
+
+    /// Adds what accrued and takes away what was posted with `tx`
+    fn follow_accruals(&mut self, tx: &Tx) {
+        match tx {
+            Tx::InterestAccrued { day, accruals, .. } => {
+                for accrual in accruals {
+                    self.accrue(accrual);
+                }
+                self.accrued_day = Some(*day);
+            }
+            Tx::AccrualPosted {
+                account,
+                kind,
+                amount,
+                ..
+            } => self.accrue_by(account, *kind, |accrued| {
+                accrued.saturating_sub(*amount as u128 * interest::ACCRUAL_SCALE)
+            }),
+            _ => {}
+        }
+    }
+
+    fn accrue(&mut self, accrual: &Accrual) {
+        self.accrue_by(&accrual.account, accrual.kind, |accrued| {
+            accrued.saturating_add(accrual.amount)
+        });
+    }
+
+    /// Changes what `account` accrued as `kind` and updates the hash
+    fn accrue_by(&mut self, account: &str, kind: AccrualKind, change: impl FnOnce(u128) -> u128) {
+        let key = (account.to_string(), kind);
+        let old = self.accrued.remove(&key).unwrap_or(0);
+        if old > 0 {
+            self.hash.remove(&accrual_entry(account, kind, old));
+        }
+        let new = change(old);
+        if new > 0 {
+            self.hash.add(&accrual_entry(account, kind, new));
+            self.accrued.insert(key, new);
+        }
+    }
+
+    /// Moves the positions along with `tx`: fills and liquidations. The house account takes the
+    /// other side of a liquidation.
+    fn follow_positions(&mut self, tx: &Tx) {
+        match tx {
+            Tx::PositionFilled { account, fill, .. } => self.change_position(account, fill),
+            Tx::Liquidated {
+                account,
+                house,
+                fill,
+                ..
+            } => {
+                self.change_position(account, fill);
+                let other = Fill {
+                    side: match fill.side {
+                        Side::Buy => Side::Sell,
+                        Side::Sell => Side::Buy,
+                    },
+                    ..fill.clone()
+                };
+                self.change_position(house, &other);
+            }
+            _ => {}
+        }
+    }
+
+    /// Applies `fill` to the position of `account` and updates the hash
+    fn change_position(&mut self, account: &str, fill: &Fill) {
+        let key = (account.to_string(), fill.symbol.clone());
+        let mut position = match self.positions.remove(&key) {
+            Some(old) => {
+                self.hash.remove(&position_entry(&old));
+                old
+            }
+            None => Position::new(account, &fill.symbol),
+        };
+        position.fill(fill.side.clone(), fill.amount, fill.notional);
+        self.put_position(position);
+    }
+
+    fn put_position(&mut self, position: Position) {
+        self.hash.add(&position_entry(&position));
+        self.positions.insert(
+            (position.account.clone(), position.symbol.clone()),
+            position,
+        );
+    }
+
+    /// Accounts for internal use and the house account don't earn or pay interest
+    fn accrues_nothing(&self, account: &str) -> bool {
+        account == ESCROW_ACCOUNT || account == self.interest.account
+    }
+
+    fn schedule_of(&self, id: u64) -> Result<&Schedule, ApplicationError> {
+        self.schedules
+            .get(&id)
+            .ok_or(ApplicationError::ScheduleNotFound(id))
+    }
+}
+
+/// What the [`StateHash`] covers of an account
+fn balance_entry(account: &str, balance: u64, status: AccountStatus) -> Vec<u8> {
+    let mut w = Writer::new();
+    w.put_str(account);
+    w.put_u64(balance);
+    w.put(&status);
+    w.into_bytes()
+}
+
+/// What the [`StateHash`] covers of a sub-account
+fn link_entry(account: &str, parent: &str) -> Vec<u8> {
+    let mut w = Writer::new();
+    w.put_str("parent");
+    w.put_str(account);
+    w.put_str(parent);
+    w.into_bytes()
+}
+
+/// What the [`StateHash`] covers of an open escrow
+fn escrow_entry(escrow: &Escrow) -> Vec<u8> {
+    let mut w = Writer::new();
+    w.put_str("escrow");
+    w.put(escrow);
+    w.into_bytes()
+}
+
+/// What the [`StateHash`] covers of a scheduled transfer
+fn schedule_entry(schedule: &Schedule) -> Vec<u8> {
+    let mut w = Writer::new();
+    w.put_str("schedule");
+    w.put(schedule);
+    w.into_bytes()
+}
+
+/// What the [`StateHash`] covers of accrued interest or fees
+fn accrual_entry(account: &str, kind: AccrualKind, accrued: u128) -> Vec<u8> {
+    let mut w = Writer::new();
+    w.put_str("accrued");
+    w.put_str(account);
+    w.put(&kind);
+    w.put_u128(accrued);
+    w.into_bytes()
+}
+
+/// What the [`StateHash`] covers of a position
+fn position_entry(position: &Position) -> Vec<u8> {
+    let mut w = Writer::new();
+    w.put_str("position");
+    w.put(position);
+    w.into_bytes()
+}
+
+/// What the [`StateHash`] covers of a debt
+fn debt_entry(account: &str, debt: u64) -> Vec<u8> {
+    let mut w = Writer::new();
+    w.put_str("debt");
+    w.put_str(account);
+    w.put_u64(debt);
+    w.into_bytes()
+}
+
+/// What the [`StateHash`] covers of a credit line
+fn credit_limit_entry(account: &str, limit: u64) -> Vec<u8> {
+    let mut w = Writer::new();
+    w.put_str("credit_limit");
+    w.put_str(account);
+    w.put_u64(limit);
+    w.into_bytes()
+}
+
+fn limits_entry(account: &str, limits: &Limits) -> Vec<u8> {
+    let mut w = Writer::new();
+    w.put_str("limits");
+    w.put_str(account);
+    w.put(limits);
+    w.into_bytes()
+}
+
+/// Adds `amount` to the net `balance`, which pays back debt first. A balance has to fit a u64.
+fn credit(account: &str, balance: i128, amount: u64) -> Result<i128, ApplicationError> {
+    let new = balance + amount as i128;
+    if new > u64::MAX as i128 {
+        return Err(ApplicationError::AccountOverFunded(
+            account.to_string(),
+            amount,
+        ));
+    }
+    Ok(new)
+}
+
+/// Subtracts `amount` from the net `balance`, which can go down to -`credit_limit`
+fn debit(
+    account: &str,
+    balance: i128,
+    amount: u64,
+    credit_limit: u64,
+) -> Result<i128, ApplicationError> {
+    let new = balance - amount as i128;
+    if new < -(credit_limit as i128) {
+        return Err(ApplicationError::AccountUnderFunded(
+            account.to_string(),
+            amount,
+        ));
+    }
+    Ok(new)
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::{
+        clock::ManualClock,
+        core::MatchingEngine,
+        interest::Rates,
+        limits::{Cap, Limit, Window},
+        tx::TxId,
+    };
+
     use super::*;
 
+    /// Metadata of a transaction without memo at time 0
+    fn meta(sequence: u64, cause: Option<Cause>) -> TxMeta {
+        TxMeta::new(
+            sequence,
+            0,
+            TxContext {
+                cause,
+                ..TxContext::default()
+            },
+        )
+    }
+
     #[test]
     fn test_accounts_withdraw_underfunded() {
-        let mut accounts = Accounts::new();
-        accounts.deposit("a-key", 0).unwrap();
+        let mut accounts = Accounts::with_clock(ManualClock::default());
+        accounts.open_account("a-key").unwrap();
         let actual = accounts.withdraw("a-key", 100);
         assert_eq!(
             actual,
@@ -134,7 +2094,8 @@ mod tests {
 
     #[test]
     fn test_accounts_deposit_overfunded() {
-        let mut accounts = Accounts::new();
+        let mut accounts = Accounts::with_clock(ManualClock::default());
+        accounts.open_account("a-key").unwrap();
         accounts
             .deposit("a-key", 1)
             .expect("Initial deposit failed");
@@ -150,55 +2111,78 @@ mod tests {
 
     #[test]
     fn test_accounts_deposit_works() {
-        let mut accounts = Accounts::new();
+        let mut accounts = Accounts::with_clock(ManualClock::default());
         let amt = 100;
+        assert_eq!(
+            accounts.deposit("a-key", amt),
+            Err(ApplicationError::AccountNotFound("a-key".to_string()))
+        );
+        accounts.open_account("a-key").unwrap();
         let actual = accounts.deposit("a-key", amt);
         assert_eq!(
             actual,
             Ok(Tx::Deposit {
                 account: "a-key".to_string(),
-                amount: amt
+                amount: amt,
+                meta: meta(2, None),
             })
         );
     }
 
+    #[test]
+    fn test_accounts_asset_is_set_before_the_first_amount() {
+        let mut accounts = Accounts::with_clock(ManualClock::default());
+        accounts.open_account("a-key").unwrap();
+        accounts.set_asset(Asset::new("USD", 2)).unwrap();
+        accounts
+            .deposit_amount("a-key", Amount::parse("1.5", 1).unwrap())
+            .unwrap();
+        assert_eq!(accounts.balance_of("a-key"), Ok(&150));
+        assert_eq!(
+            accounts.set_asset(Asset::new("USD", 0)),
+            Err(ApplicationError::AssetInUse("USD".to_string()))
+        );
+
+        let replayed = Accounts::replay(accounts.journal()).unwrap();
+        assert_eq!(replayed.asset(), &Asset::new("USD", 2));
+        assert_eq!(replayed.balance_amount("a-key"), Ok(Amount::new(150, 2)));
+    }
+
     #[test]
     fn test_accounts_withdraw_works() {
-        let mut accounts = Accounts::new();
+        let mut accounts = Accounts::with_clock(ManualClock::default());
         let amt = 100;
+        accounts.open_account("a-key").unwrap();
         accounts.deposit("a-key", amt).expect("Couldn't deposit");
         let actual = accounts.withdraw("a-key", amt);
         assert_eq!(
             actual,
             Ok(Tx::Withdraw {
                 account: "a-key".to_string(),
-                amount: amt
+                amount: amt,
+                meta: meta(3, None),
             })
         );
     }
 
     #[test]
     fn test_accounts_send_works() {
-        let mut accounts = Accounts::new();
+        let mut accounts = Accounts::with_clock(ManualClock::default());
         let amt = 100;
+        accounts.open_account("a-key").unwrap();
         accounts.deposit("a-key", amt).expect("Couldn't deposit");
 
-        // creating the receiver is also required
-        accounts.deposit("b-key", 0).expect("Couldn't deposit");
+        // the receiver has to be opened as well
+        accounts.open_account("b-key").unwrap();
 
-        let (tx1, tx2) = accounts.send("a-key", "b-key", amt).expect("Send failed");
-        assert_eq!(
-            tx1,
-            Tx::Withdraw {
-                account: "a-key".to_string(),
-                amount: amt
-            }
-        );
+        let tx = accounts.send("a-key", "b-key", amt).expect("Send failed");
         assert_eq!(
-            tx2,
-            Tx::Deposit {
-                account: "b-key".to_string(),
-                amount: amt
+            tx,
+            Tx::Transfer {
+                sender: "a-key".to_string(),
+                recipient: "b-key".to_string(),
+                amount: amt,
+                meta: meta(4, None),
             }
         );
 
@@ -207,23 +2191,25 @@ mod tests {
             actual,
             Ok(Tx::Withdraw {
                 account: "b-key".to_string(),
-                amount: amt
+                amount: amt,
+                meta: meta(5, None),
             })
         );
     }
 
     #[test]
     fn test_accounts_send_underfunded_fails_and_rolls_back() {
-        let mut accounts = Accounts::new();
+        let mut accounts = Accounts::with_clock(ManualClock::default());
         let amt = 100;
+        accounts.open_account("a-key").unwrap();
         accounts.deposit("a-key", amt).expect("Couldn't deposit");
 
-        // creating the receiver is also required
-        accounts.deposit("b-key", 0).expect("Couldn't deposit");
+        // the receiver has to be opened as well
+        accounts.open_account("b-key").unwrap();
 
         let actual = accounts.send("a-key", "b-key", amt + 1);
         assert!(actual.is_err());
-        let expected: HashMap<String, u64> =
+        let expected: BTreeMap<String, u64> =
             vec![("a-key".to_string(), amt), ("b-key".to_string(), 0)]
                 .into_iter()
                 .collect();
@@ -232,21 +2218,908 @@ mod tests {
 
     #[test]
     fn test_accounts_send_overfunded_fails_and_rolls_back() {
-        let mut accounts = Accounts::new();
+        let mut accounts = Accounts::with_clock(ManualClock::default());
         let amt = 100;
+        accounts.open_account("a-key").unwrap();
         accounts.deposit("a-key", amt).expect("Couldn't deposit");
 
-        // creating the receiver is also required
+        accounts.open_account("b-key").unwrap();
         accounts
             .deposit("b-key", u64::MAX)
             .expect("Couldn't deposit");
 
         let actual = accounts.send("a-key", "b-key", 1);
         assert!(actual.is_err());
-        let expected: HashMap<String, u64> =
+        let expected: BTreeMap<String, u64> =
             vec![("a-key".to_string(), amt), ("b-key".to_string(), u64::MAX)]
                 .into_iter()
                 .collect();
         assert_eq!(accounts.accounts, expected);
     }
+
+    #[test]
+    fn test_accounts_journal_records_applied_txs() {
+        let mut accounts = Accounts::with_clock(ManualClock::default());
+        accounts.open_account("a-key").unwrap();
+        accounts.deposit("a-key", 100).expect("Couldn't deposit");
+        accounts.open_account("b-key").unwrap();
+        assert!(accounts.withdraw("a-key", 101).is_err());
+        accounts.send("a-key", "b-key", 40).expect("Send failed");
+
+        assert_eq!(
+            accounts.journal(),
+            &[
+                Tx::StatusChange {
+                    account: "a-key".to_string(),
+                    status: AccountStatus::Open,
+                    meta: meta(1, None),
+                },
+                Tx::Deposit {
+                    account: "a-key".to_string(),
+                    amount: 100,
+                    meta: meta(2, None),
+                },
+                Tx::StatusChange {
+                    account: "b-key".to_string(),
+                    status: AccountStatus::Open,
+                    meta: meta(3, None),
+                },
+                Tx::Transfer {
+                    sender: "a-key".to_string(),
+                    recipient: "b-key".to_string(),
+                    amount: 40,
+                    meta: meta(4, None),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_accounts_replay_fails_on_invalid_tx() {
+        let txs = vec![Tx::Withdraw {
+            account: "a-key".to_string(),
+            amount: 1,
+            meta: meta(1, None),
+        }];
+        assert_eq!(
+            Accounts::replay(&txs).map(|a| a.accounts),
+            Err(ApplicationError::AccountNotFound("a-key".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_accounts_apply_rejects_out_of_sequence_tx() {
+        let mut accounts = Accounts::with_clock(ManualClock::default());
+        let tx = accounts.open_account("a-key").unwrap();
+        assert_eq!(accounts.apply(&tx), Err(ApplicationError::OutOfSequence(1)));
+    }
+
+    #[test]
+    fn test_accounts_tx_metadata() {
+        let clock = ManualClock::new(1_000);
+        let mut accounts = Accounts::with_clock(clock.clone());
+        accounts.open_account("a-key").unwrap();
+        accounts.deposit("a-key", 100).expect("Couldn't deposit");
+        accounts.open_account("b-key").unwrap();
+
+        clock.advance(500);
+        let cause = Cause::Trade {
+            taker_ordinal: 2,
+            maker_ordinal: 1,
+        };
+        let tx = accounts
+            .send_with(
+                "a-key",
+                "b-key",
+                10,
+                TxContext::caused_by(cause.clone()).with_memo("settlement"),
+            )
+            .expect("Send failed");
+
+        let meta = tx.meta();
+        assert_eq!(meta.sequence, 4);
+        assert_eq!(meta.timestamp, 1_500);
+        assert_eq!(meta.id, TxId::new(1_500, 4));
+        assert_ne!(meta.id, accounts.journal()[1].meta().id);
+        assert_eq!(meta.cause, Some(cause));
+        assert_eq!(meta.memo.as_deref(), Some("settlement"));
+    }
+
+    #[test]
+    fn test_accounts_send_underfunded_reports_sender() {
+        let mut accounts = Accounts::with_clock(ManualClock::default());
+        accounts.open_account("a-key").unwrap();
+        accounts.deposit("a-key", 1).expect("Couldn't deposit");
+        accounts.open_account("b-key").unwrap();
+        assert_eq!(
+            accounts.send("a-key", "b-key", 2),
+            Err(ApplicationError::AccountUnderFunded("a-key".to_string(), 2))
+        );
+        assert_eq!(
+            accounts.send("a-key", "c-key", 1),
+            Err(ApplicationError::AccountNotFound("c-key".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_accounts_execute_batch_is_atomic() {
+        let mut accounts = Accounts::with_clock(ManualClock::default());
+        for account in ["a-key", "b-key", "c-key", "d-key"] {
+            accounts.open_account(account).unwrap();
+        }
+        accounts.deposit("a-key", 100).expect("Couldn't deposit");
+
+        // the second transfer relies on the first one's funds
+        let legs = vec![
+            Leg::Transfer {
+                sender: "a-key".to_string(),
+                recipient: "b-key".to_string(),
+                amount: 60,
+            },
+            Leg::Transfer {
+                sender: "b-key".to_string(),
+                recipient: "a-key".to_string(),
+                amount: 10,
+            },
+            Leg::Deposit {
+                account: "c-key".to_string(),
+                amount: 5,
+            },
+        ];
+        let txs = accounts
+            .execute_batch(legs, TxContext::default())
+            .expect("Batch failed");
+        assert_eq!(txs.len(), 3);
+        assert_eq!(accounts.balance_of("a-key"), Ok(&50));
+        assert_eq!(accounts.balance_of("b-key"), Ok(&50));
+        assert_eq!(accounts.balance_of("c-key"), Ok(&5));
+
+        // the last leg fails, so none of them apply
+        let legs = vec![
+            Leg::Withdraw {
+                account: "a-key".to_string(),
+                amount: 50,
+            },
+            Leg::Deposit {
+                account: "d-key".to_string(),
+                amount: 1,
+            },
+            Leg::Withdraw {
+                account: "b-key".to_string(),
+                amount: 51,
+            },
+        ];
+        assert_eq!(
+            accounts.execute_batch(legs, TxContext::default()),
+            Err(ApplicationError::AccountUnderFunded(
+                "b-key".to_string(),
+                51
+            ))
+        );
+        assert_eq!(accounts.balance_of("a-key"), Ok(&50));
+        assert_eq!(accounts.balance_of("d-key"), Ok(&0));
+        assert_eq!(accounts.journal().len(), 8);
+    }
+
+    /// A tiny xorshift generator so the property test is reproducible without extra dependencies
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self, bound: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % bound
+        }
+    }
+
+    #[test]
+    fn test_accounts_replay_round_trip_property() {
+        let keys = ["a-key", "b-key", "c-key", "d-key"];
+        for seed in 1..=200 {
+            let mut rng = Rng(seed);
+            let mut accounts = Accounts::with_clock(ManualClock::default());
+            for _ in 0..rng.next(50) {
+                let signer = keys[rng.next(keys.len() as u64) as usize];
+                let other = keys[rng.next(keys.len() as u64) as usize];
+                // mostly small amounts, sometimes huge ones to provoke overflows
+                let amount = match rng.next(10) {
+                    0 => u64::MAX - rng.next(100),
+                    _ => rng.next(1_000),
+                };
+                // failed operations are part of the property too
+                let _ = match rng.next(10) {
+                    0..=1 => accounts.open_account(signer),
+                    2 => accounts.freeze(signer),
+                    3 => accounts.unfreeze(signer),
+                    4 => accounts.close(signer),
+                    5..=6 => accounts.deposit(signer, amount),
+                    7 => accounts.withdraw(signer, amount),
+                    _ => accounts.send(signer, other, amount),
+                };
+            }
+
+            let replayed = Accounts::replay(accounts.journal())
+                .unwrap_or_else(|e| panic!("seed {}: replay failed with {:?}", seed, e));
+            assert_eq!(replayed.accounts, accounts.accounts, "seed {}", seed);
+            assert_eq!(replayed.statuses, accounts.statuses, "seed {}", seed);
+            assert_eq!(replayed.hash, accounts.hash, "seed {}", seed);
+            assert_eq!(replayed.journal, accounts.journal, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn test_accounts_state_hash_depends_only_on_balances() {
+        let recomputed = |accounts: &Accounts| {
+            let entries: Vec<Vec<u8>> = accounts
+                .balances()
+                .into_iter()
+                .map(|(account, balance)| balance_entry(account, balance, AccountStatus::Open))
+                .collect();
+            StateHash::of(entries.iter().map(Vec::as_slice))
+        };
+
+        let mut a = Accounts::with_clock(ManualClock::default());
+        a.open_account("a-key").unwrap();
+        a.open_account("b-key").unwrap();
+        a.deposit("a-key", 100).unwrap();
+        a.send("a-key", "b-key", 40).unwrap();
+        assert!(a
+            .execute_batch(
+                vec![
+                    Leg::Withdraw {
+                        account: "b-key".to_string(),
+                        amount: 40,
+                    },
+                    Leg::Withdraw {
+                        account: "b-key".to_string(),
+                        amount: 1,
+                    },
+                ],
+                TxContext::default(),
+            )
+            .is_err());
+        assert_eq!(a.state_hash(), recomputed(&a));
+
+        // same balances, different history
+        let mut b = Accounts::with_clock(ManualClock::default());
+        b.open_account("b-key").unwrap();
+        b.open_account("a-key").unwrap();
+        b.deposit("b-key", 40).unwrap();
+        b.deposit("a-key", 60).unwrap();
+        assert_eq!(a.state_hash(), b.state_hash());
+
+        b.freeze("a-key").unwrap();
+        assert_ne!(a.state_hash(), b.state_hash());
+        b.unfreeze("a-key").unwrap();
+        assert_eq!(a.state_hash(), b.state_hash());
+
+        b.withdraw("a-key", 1).unwrap();
+        assert_ne!(a.state_hash(), b.state_hash());
+        assert_eq!(
+            Accounts::replay(a.journal()).unwrap().state_hash(),
+            a.state_hash()
+        );
+    }
+
+    #[test]
+    fn test_accounts_lifecycle() {
+        let mut accounts = Accounts::with_clock(ManualClock::default());
+        accounts.open_account("a-key").unwrap();
+        accounts.open_account("b-key").unwrap();
+        accounts.deposit("a-key", 10).unwrap();
+        assert_eq!(
+            accounts.open_account("a-key"),
+            Err(ApplicationError::AccountExists("a-key".to_string()))
+        );
+        assert_eq!(
+            accounts.unfreeze("a-key"),
+            Err(ApplicationError::AccountNotFrozen("a-key".to_string()))
+        );
+
+        // frozen accounts receive, but don't send
+        accounts.freeze("a-key").unwrap();
+        assert_eq!(accounts.status_of("a-key"), Some(AccountStatus::Frozen));
+        let frozen = Err(ApplicationError::AccountFrozen("a-key".to_string()));
+        assert_eq!(accounts.freeze("a-key"), frozen);
+        assert_eq!(accounts.withdraw("a-key", 1), frozen);
+        assert_eq!(accounts.send("a-key", "b-key", 1), frozen);
+        accounts.deposit("a-key", 1).unwrap();
+        accounts.deposit("b-key", 1).unwrap();
+        accounts.send("b-key", "a-key", 1).unwrap();
+        accounts.unfreeze("a-key").unwrap();
+
+        // only empty accounts can be closed, for good
+        assert_eq!(
+            accounts.close("a-key"),
+            Err(ApplicationError::AccountNotEmpty("a-key".to_string(), 12))
+        );
+        accounts.withdraw("a-key", 12).unwrap();
+        accounts.close("a-key").unwrap();
+        let closed = Err(ApplicationError::AccountClosed("a-key".to_string()));
+        assert_eq!(accounts.open_account("a-key"), closed);
+        assert_eq!(accounts.deposit("a-key", 1), closed);
+        assert_eq!(accounts.send("b-key", "a-key", 1), closed);
+        assert_eq!(
+            accounts.balance_of("a-key"),
+            Err(ApplicationError::AccountClosed("a-key".to_string()))
+        );
+        assert_eq!(accounts.balances(), vec![("b-key", 0)]);
+
+        let replayed = Accounts::replay(accounts.journal()).unwrap();
+        assert_eq!(replayed.statuses(), accounts.statuses());
+        assert_eq!(replayed.state_hash(), accounts.state_hash());
+    }
+
+    #[test]
+    fn test_accounts_limits() {
+        let clock = ManualClock::new(DAY);
+        let mut accounts = Accounts::with_clock(clock.clone());
+        accounts.open_account("a-key").unwrap();
+        accounts.open_account("b-key").unwrap();
+        accounts.deposit("a-key", 1_000).unwrap();
+        accounts
+            .set_limits(
+                "a-key",
+                Limits {
+                    withdrawal: Some(Cap {
+                        amount: 100,
+                        window: Window::Rolling(DAY),
+                    }),
+                    max_transfer: Some(200),
+                    daily_send: Some(300),
+                },
+            )
+            .unwrap();
+
+        accounts.withdraw("a-key", 70).unwrap();
+        assert_eq!(
+            accounts.withdraw("a-key", 31),
+            Err(ApplicationError::LimitExceeded(
+                "a-key".to_string(),
+                Limit::Withdrawal(100),
+                30
+            ))
+        );
+        assert_eq!(
+            accounts.send("a-key", "b-key", 201),
+            Err(ApplicationError::LimitExceeded(
+                "a-key".to_string(),
+                Limit::MaxTransfer(200),
+                200
+            ))
+        );
+        accounts.send("a-key", "b-key", 200).unwrap();
+        assert_eq!(
+            accounts.send("a-key", "b-key", 101),
+            Err(ApplicationError::LimitExceeded(
+                "a-key".to_string(),
+                Limit::DailySend(300),
+                100
+            ))
+        );
+        // batches, e.g. trade settlements, aren't limited
+        let transfer = Leg::Transfer {
+            sender: "a-key".to_string(),
+            recipient: "b-key".to_string(),
+            amount: 101,
+        };
+        assert!(accounts
+            .execute_batch(vec![transfer], TxContext::default())
+            .is_ok());
+        assert_eq!(accounts.balance_of("a-key"), Ok(&629));
+
+        // a replay restores the limits and counts the usage without checking it
+        let mut replayed = Accounts::with_clock(clock.clone());
+        for tx in accounts.journal() {
+            replayed.apply(tx).unwrap();
+        }
+        assert!(matches!(
+            replayed.withdraw("a-key", 31),
+            Err(ApplicationError::LimitExceeded(
+                _,
+                Limit::Withdrawal(100),
+                30
+            ))
+        ));
+
+        // the allowance comes back with time
+        clock.advance(DAY);
+        assert!(accounts.withdraw("a-key", 100).is_ok());
+        assert!(accounts.send("a-key", "b-key", 200).is_ok());
+    }
+
+    #[test]
+    fn test_accounts_idempotency_key_executes_once() {
+        let clock = ManualClock::new(1_000);
+        let mut accounts = Accounts::with_clock(clock.clone());
+        accounts.set_idempotency_window(100);
+        accounts.open_account("a-key").unwrap();
+        accounts.open_account("b-key").unwrap();
+        let request = || TxContext::default().with_idempotency_key("req-1");
+
+        let deposit = accounts.deposit_with("a-key", 10, request()).unwrap();
+        clock.advance(99);
+        assert_eq!(accounts.deposit_with("a-key", 10, request()), Ok(deposit));
+        assert_eq!(accounts.balance_of("a-key"), Ok(&10));
+        assert_eq!(
+            accounts.withdraw_with("a-key", 10, request()),
+            Err(ApplicationError::IdempotencyKeyReused("req-1".to_string()))
+        );
+
+        // failed requests aren't remembered
+        let send = || TxContext::default().with_idempotency_key("req-2");
+        assert!(accounts.send_with("a-key", "b-key", 20, send()).is_err());
+        accounts.deposit("a-key", 10).unwrap();
+        let sent = accounts.send_with("a-key", "b-key", 20, send()).unwrap();
+        assert_eq!(accounts.send_with("a-key", "b-key", 20, send()), Ok(sent));
+        assert_eq!(accounts.balance_of("b-key"), Ok(&20));
+
+        // after the window the key is new again
+        clock.advance(1);
+        assert!(accounts.deposit_with("a-key", 10, request()).is_ok());
+        assert_eq!(accounts.balance_of("a-key"), Ok(&10));
+        assert_eq!(accounts.journal().len(), 6);
+    }
+
+    #[test]
+    fn test_accounts_sub_accounts() {
+        let clock = ManualClock::new(DAY);
+        let mut accounts = Accounts::with_clock(clock.clone());
+        accounts.open_account("fund").unwrap();
+        accounts.open_sub_account("fund", "fund/a").unwrap();
+        accounts.open_sub_account("fund/a", "fund/a/1").unwrap();
+        accounts.open_account("other").unwrap();
+        assert_eq!(
+            accounts.open_sub_account("nobody", "x"),
+            Err(ApplicationError::AccountNotFound("nobody".to_string()))
+        );
+        assert_eq!(
+            accounts.open_sub_account("fund", "other"),
+            Err(ApplicationError::AccountExists("other".to_string()))
+        );
+        assert_eq!(accounts.parent_of("fund/a/1"), Some("fund/a"));
+        assert_eq!(accounts.sub_accounts("fund"), vec!["fund/a"]);
+        assert_eq!(accounts.root_of("fund/a/1"), "fund");
+
+        accounts.deposit("fund", 10).unwrap();
+        accounts.deposit("fund/a", 20).unwrap();
+        accounts.deposit("fund/a/1", 30).unwrap();
+        assert_eq!(accounts.aggregate_balance("fund"), Ok(60));
+        assert_eq!(accounts.aggregate_balance("fund/a"), Ok(50));
+
+        // limits of a parent are shared by the whole entity, internal transfers are free
+        accounts
+            .set_limits(
+                "fund",
+                Limits {
+                    daily_send: Some(5),
+                    ..Limits::default()
+                },
+            )
+            .unwrap();
+        accounts.transfer_internal("fund/a/1", "fund", 30).unwrap();
+        assert_eq!(
+            accounts.transfer_internal("fund", "other", 1),
+            Err(ApplicationError::NotSameEntity(
+                "fund".to_string(),
+                "other".to_string()
+            ))
+        );
+        accounts.send("fund/a", "other", 5).unwrap();
+        assert_eq!(
+            accounts.send("fund", "other", 1),
+            Err(ApplicationError::LimitExceeded(
+                "fund".to_string(),
+                Limit::DailySend(5),
+                0
+            ))
+        );
+
+        assert_eq!(
+            accounts.close("fund/a"),
+            Err(ApplicationError::AccountNotEmpty("fund/a".to_string(), 15))
+        );
+        accounts.transfer_internal("fund/a", "fund", 15).unwrap();
+        assert_eq!(
+            accounts.close("fund/a"),
+            Err(ApplicationError::AccountHasSubAccounts(
+                "fund/a".to_string(),
+                1
+            ))
+        );
+        accounts.close("fund/a/1").unwrap();
+        accounts.close("fund/a").unwrap();
+        assert_eq!(accounts.aggregate_balance("fund"), Ok(55));
+
+        // the hierarchy survives a replay and a restore
+        let mut replayed = Accounts::with_clock(clock.clone());
+        for tx in accounts.journal() {
+            replayed.apply(tx).unwrap();
+        }
+        assert_eq!(replayed.parents(), accounts.parents());
+        assert_eq!(replayed.state_hash(), accounts.state_hash());
+        let snapshot = Snapshot::capture(&accounts, &MatchingEngine::with_clock(clock.clone()), 0);
+        let restored = Accounts::restore(&snapshot, clock);
+        assert_eq!(restored.state_hash(), accounts.state_hash());
+    }
+
+    #[test]
+    fn test_accounts_escrow_release_and_refund() {
+        let clock = ManualClock::new(1_000);
+        let mut accounts = Accounts::with_clock(clock.clone());
+        for account in ["alice", "bob", "carol"] {
+            accounts.open_account(account).unwrap();
+        }
+        accounts.deposit("alice", 100).unwrap();
+        let arbiter = Condition::Approval {
+            arbiter: "carol".to_string(),
+        };
+        assert_eq!(
+            accounts.open_escrow("alice", "bob", 101, arbiter.clone()),
+            Err(ApplicationError::AccountUnderFunded(
+                "alice".to_string(),
+                101
+            ))
+        );
+        assert_eq!(
+            accounts.open_escrow("alice", "nobody", 1, arbiter.clone()),
+            Err(ApplicationError::AccountNotFound("nobody".to_string()))
+        );
+        let approved = accounts
+            .open_escrow("alice", "bob", 30, arbiter)
+            .unwrap()
+            .meta()
+            .sequence;
+        let timed = accounts
+            .open_escrow("alice", "bob", 20, Condition::Deadline(2_000))
+            .unwrap()
+            .meta()
+            .sequence;
+        assert_eq!(accounts.balance_of("alice"), Ok(&50));
+        assert_eq!(accounts.balance_of(ESCROW_ACCOUNT), Ok(&50));
+        assert_eq!(accounts.escrows().len(), 2);
+
+        assert_eq!(
+            accounts.release_escrow(approved, "bob"),
+            Err(ApplicationError::EscrowDenied(approved))
+        );
+        accounts.release_escrow(approved, "carol").unwrap();
+        assert_eq!(accounts.balance_of("bob"), Ok(&30));
+        assert_eq!(
+            accounts.release_escrow(approved, "carol"),
+            Err(ApplicationError::EscrowNotFound(approved))
+        );
+
+        // nobody can release before the deadline, the recipient can refund until then
+        assert_eq!(
+            accounts.release_escrow(timed, "carol"),
+            Err(ApplicationError::EscrowDenied(timed))
+        );
+        clock.set(2_000);
+        assert_eq!(
+            accounts.refund_escrow(timed, "bob"),
+            Err(ApplicationError::EscrowDenied(timed))
+        );
+        let again = accounts
+            .open_escrow("alice", "bob", 5, Condition::Deadline(3_000))
+            .unwrap()
+            .meta()
+            .sequence;
+        accounts.release_escrow(timed, "anyone").unwrap();
+        accounts.refund_escrow(again, "bob").unwrap();
+        assert_eq!(accounts.balance_of("alice"), Ok(&50));
+        assert_eq!(accounts.balance_of("bob"), Ok(&50));
+        assert_eq!(accounts.balance_of(ESCROW_ACCOUNT), Ok(&0));
+        assert!(accounts.escrows().is_empty());
+
+        // escrows survive a replay
+        let mut replayed = Accounts::with_clock(clock);
+        for tx in accounts.journal() {
+            replayed.apply(tx).unwrap();
+        }
+        assert_eq!(replayed.state_hash(), accounts.state_hash());
+    }
+
+    #[test]
+    fn test_accounts_escrow_account_is_reserved() {
+        let mut accounts = Accounts::default();
+        accounts.open_account("alice").unwrap();
+        accounts.deposit("alice", 10).unwrap();
+        accounts
+            .open_escrow("alice", "alice", 10, Condition::Deadline(0))
+            .unwrap();
+        let reserved = || {
+            Err(ApplicationError::ReservedAccount(
+                ESCROW_ACCOUNT.to_string(),
+            ))
+        };
+        assert_eq!(accounts.open_account(ESCROW_ACCOUNT), reserved());
+        assert_eq!(accounts.withdraw(ESCROW_ACCOUNT, 1), reserved());
+        assert_eq!(accounts.send(ESCROW_ACCOUNT, "alice", 1), reserved());
+        assert_eq!(accounts.freeze(ESCROW_ACCOUNT), reserved());
+        assert_eq!(
+            accounts.open_escrow("alice", ESCROW_ACCOUNT, 1, Condition::Deadline(0)),
+            reserved()
+        );
+        assert_eq!(accounts.balance_of(ESCROW_ACCOUNT), Ok(&10));
+    }
+
+    #[test]
+    fn test_accounts_scheduled_transfers() {
+        let clock = ManualClock::new(DAY);
+        let mut accounts = Accounts::with_clock(clock.clone());
+        accounts.open_account("treasury").unwrap();
+        accounts.open_account("payroll").unwrap();
+        accounts.deposit("treasury", 15).unwrap();
+        let retry = RetryPolicy {
+            max_retries: 1,
+            delay: 100,
+        };
+        assert_eq!(
+            accounts.schedule_transfer("treasury", "nobody", 1, 0, Recurrence::Once, retry),
+            Err(ApplicationError::AccountNotFound("nobody".to_string()))
+        );
+        let daily = accounts
+            .schedule_transfer("treasury", "payroll", 10, 2 * DAY, Recurrence::Daily, retry)
+            .unwrap()
+            .meta()
+            .sequence;
+        let once = accounts
+            .schedule_transfer("treasury", "payroll", 5, 2 * DAY, Recurrence::Once, retry)
+            .unwrap()
+            .meta()
+            .sequence;
+        assert!(accounts.run_schedules().is_empty());
+
+        clock.set(2 * DAY);
+        let txs = accounts.run_schedules();
+        assert_eq!(txs.len(), 2);
+        assert_eq!(txs[0].meta().cause, Some(Cause::Schedule(daily)));
+        assert_eq!(accounts.balance_of("payroll"), Ok(&15));
+        assert_eq!(accounts.schedule(once), None);
+        assert_eq!(accounts.schedule(daily).map(|s| s.next), Some(3 * DAY));
+
+        // underfunded: retried once after the delay, then skipped until the next day
+        clock.set(3 * DAY);
+        assert!(matches!(
+            accounts.run_schedules().as_slice(),
+            [Tx::ScheduleFailed { id, .. }] if *id == daily
+        ));
+        assert_eq!(
+            accounts.schedule(daily).map(|s| s.next),
+            Some(3 * DAY + 100)
+        );
+        accounts.deposit("treasury", 5).unwrap();
+        clock.advance(100);
+        assert!(matches!(
+            accounts.run_schedules().as_slice(),
+            [Tx::ScheduleFailed { .. }]
+        ));
+        let schedule = accounts.schedule(daily).unwrap();
+        assert_eq!((schedule.runs, schedule.next), (2, 4 * DAY));
+
+        accounts.deposit("treasury", 15).unwrap();
+        clock.set(5 * DAY);
+        // the missed occurrence of day 4 is executed as well
+        assert_eq!(accounts.run_schedules().len(), 2);
+        assert_eq!(accounts.balance_of("treasury"), Ok(&0));
+
+        accounts.cancel_schedule(daily).unwrap();
+        assert_eq!(
+            accounts.cancel_schedule(daily),
+            Err(ApplicationError::ScheduleNotFound(daily))
+        );
+        clock.set(10 * DAY);
+        assert!(accounts.run_schedules().is_empty());
+
+        let mut replayed = Accounts::with_clock(clock);
+        for tx in accounts.journal() {
+            replayed.apply(tx).unwrap();
+        }
+        assert_eq!(replayed.state_hash(), accounts.state_hash());
+    }
+
+    #[test]
+    fn test_accounts_interest_accrues_daily_and_posts_whole_units() {
+        let clock = ManualClock::new(10 * DAY);
+        let mut accounts = Accounts::with_clock(clock.clone());
+        for account in ["alice", "HOUSE"] {
+            accounts.open_account(account).unwrap();
+        }
+        accounts.deposit("alice", 1_000).unwrap();
+        accounts.deposit("HOUSE", 100).unwrap();
+        assert_eq!(accounts.accrue_interest(), None);
+        accounts
+            .set_interest_schedule(InterestSchedule::new(
+                "HOUSE",
+                [(
+                    "UNIT".to_string(),
+                    Rates {
+                        interest_bps: 500,
+                        custody_fee_bps: 100,
+                        overdraft_bps: 0,
+                    },
+                )],
+            ))
+            .unwrap();
+
+        assert!(accounts.accrue_interest().is_some());
+        assert_eq!(accounts.accrue_interest(), None);
+        // a day of 5% on 1000 is a fraction of a unit, nothing to post
+        assert_eq!(accounts.accrued("alice", AccrualKind::Interest), 500_000);
+        assert_eq!(accounts.accrued("HOUSE", AccrualKind::Interest), 0);
+        assert_eq!(accounts.post_interest(), Ok(vec![]));
+
+        // the rest of the year, a whole year is exactly 5% and 1%
+        clock.advance(364 * DAY);
+        accounts.accrue_interest().unwrap();
+        let posted = accounts.post_interest().unwrap();
+        assert_eq!(posted.len(), 2);
+        assert_eq!(accounts.balance_of("alice"), Ok(&1_040));
+        assert_eq!(accounts.balance_of("HOUSE"), Ok(&60));
+        assert!(accounts.accruals().is_empty());
+
+        // the house account has to cover the interest
+        accounts.withdraw("HOUSE", 60).unwrap();
+        clock.advance(365 * DAY);
+        accounts.accrue_interest().unwrap();
+        assert_eq!(
+            accounts.post_interest(),
+            Err(ApplicationError::AccountUnderFunded(
+                "HOUSE".to_string(),
+                52
+            ))
+        );
+        assert_eq!(accounts.balance_of("alice"), Ok(&1_040));
+
+        let mut replayed = Accounts::with_clock(clock);
+        for tx in accounts.journal() {
+            replayed.apply(tx).unwrap();
+        }
+        assert_eq!(replayed.accruals(), accounts.accruals());
+        assert_eq!(replayed.interest_schedule(), accounts.interest_schedule());
+        assert_eq!(replayed.state_hash(), accounts.state_hash());
+    }
+
+    #[test]
+    fn test_accounts_credit_line() {
+        let clock = ManualClock::new(DAY);
+        let mut accounts = Accounts::with_clock(clock.clone());
+        accounts.open_account("alice").unwrap();
+        accounts.open_account("bob").unwrap();
+        accounts.deposit("alice", 10).unwrap();
+        assert_eq!(
+            accounts.withdraw("alice", 15),
+            Err(ApplicationError::AccountUnderFunded(
+                "alice".to_string(),
+                15
+            ))
+        );
+
+        accounts.set_credit_limit("alice", 20).unwrap();
+        accounts.withdraw("alice", 15).unwrap();
+        assert_eq!(accounts.balance_of("alice"), Ok(&0));
+        assert_eq!(accounts.debt_of("alice"), Ok(5));
+        assert_eq!(accounts.net_balance("alice"), Ok(-5));
+        assert_eq!(
+            accounts.send("alice", "bob", 16),
+            Err(ApplicationError::AccountUnderFunded(
+                "alice".to_string(),
+                16
+            ))
+        );
+        accounts.send("alice", "bob", 15).unwrap();
+        assert_eq!(accounts.available("alice"), Ok(0));
+        assert_eq!(
+            accounts.close("alice"),
+            Err(ApplicationError::AccountInDebt("alice".to_string(), 20))
+        );
+        let snapshot = Snapshot::capture(&accounts, &MatchingEngine::with_clock(clock.clone()), 0);
+        let restored = Accounts::restore(&snapshot, clock.clone());
+        assert_eq!(restored.debt_of("alice"), Ok(20));
+        assert_eq!(restored.state_hash(), accounts.state_hash());
+
+        // deposits pay back the debt first
+        clock.advance(1);
+        accounts.deposit("alice", 25).unwrap();
+        assert_eq!(accounts.balance_of("alice"), Ok(&5));
+        assert_eq!(accounts.debt_of("alice"), Ok(0));
+        assert_eq!(accounts.balance_at("alice", DAY), Some(-20));
+        let statement = accounts.statement("alice", DAY..=DAY + 1);
+        assert_eq!(statement.opening_balance, 0);
+        assert_eq!(statement.closing_balance, 5);
+        assert!(statement.to_csv().contains(",-15,-20\n"));
+
+        // a lower limit stops new borrowing
+        accounts.set_credit_limit("alice", 0).unwrap();
+        assert!(accounts.withdraw("alice", 6).is_err());
+        assert_eq!(
+            accounts.set_credit_limit(ESCROW_ACCOUNT, 1),
+            Err(ApplicationError::ReservedAccount(
+                ESCROW_ACCOUNT.to_string()
+            ))
+        );
+
+        let mut replayed = Accounts::with_clock(clock);
+        for tx in accounts.journal() {
+            replayed.apply(tx).unwrap();
+        }
+        assert_eq!(replayed.state_hash(), accounts.state_hash());
+    }
+
+    #[test]
+    fn test_accounts_positions_and_liquidation() {
+        let clock = ManualClock::new(DAY);
+        let mut accounts = Accounts::with_clock(clock.clone());
+        for account in ["alice", "bob", "house"] {
+            accounts.open_account(account).unwrap();
+        }
+        accounts.deposit("alice", 20).unwrap();
+        accounts.deposit("bob", 100).unwrap();
+        let fill = |side, notional| Fill {
+            symbol: "X/Y".to_string(),
+            side,
+            amount: 10,
+            price: 10,
+            notional,
+        };
+
+        // alice goes 10 short at 10
+        accounts.send("bob", "alice", 100).unwrap();
+        accounts
+            .fill("alice", fill(Side::Sell, 100), TxContext::default())
+            .unwrap();
+        accounts
+            .fill("bob", fill(Side::Buy, 100), TxContext::default())
+            .unwrap();
+        assert_eq!(
+            accounts.position("alice", "X/Y").map(|p| (p.size, p.cost)),
+            Some((-10, -100))
+        );
+        assert!(accounts
+            .fill("carol", fill(Side::Buy, 1), TxContext::default())
+            .is_err());
+
+        // buying back at 15 costs 150, alice has 120 and the house absorbs the rest
+        let tx = accounts
+            .liquidate("alice", "house", "X/Y", 15, 150)
+            .unwrap();
+        assert_eq!(
+            tx.leg(),
+            Some(Leg::Transfer {
+                sender: "alice".to_string(),
+                recipient: "house".to_string(),
+                amount: 120,
+            })
+        );
+        let position = accounts.position("alice", "X/Y").unwrap();
+        assert_eq!((position.size, position.realised_pnl), (0, -20));
+        assert_eq!(
+            accounts.position("house", "X/Y").map(|p| (p.size, p.cost)),
+            Some((-10, -120))
+        );
+        assert_eq!(
+            accounts.liquidate("alice", "house", "X/Y", 15, 150),
+            Err(ApplicationError::PositionNotFound(
+                "alice".to_string(),
+                "X/Y".to_string()
+            ))
+        );
+        assert!(accounts
+            .liquidate("house", "house", "X/Y", 15, 150)
+            .is_err());
+
+        let snapshot = Snapshot::capture(&accounts, &MatchingEngine::with_clock(clock.clone()), 0);
+        let restored = Accounts::restore(&snapshot, clock.clone());
+        assert_eq!(restored.positions(), accounts.positions());
+        assert_eq!(restored.state_hash(), accounts.state_hash());
+
+        let mut replayed = Accounts::with_clock(clock);
+        for tx in accounts.journal() {
+            replayed.apply(tx).unwrap();
+        }
+        assert_eq!(replayed.positions(), accounts.positions());
+        assert_eq!(replayed.state_hash(), accounts.state_hash());
+    }
 }
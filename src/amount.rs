@@ -0,0 +1,332 @@
+use std::fmt;
+
+use crate::errors::ApplicationError;
+
+/// Most decimals an [`Amount`] can have, 10^19 doesn't fit into a u64
+pub const MAX_SCALE: u32 = 18;
+
+/// A fixed-point decimal: `units` minor units of 10^-`scale` each, e.g. 1025 units at scale 2
+/// are 10.25. Balances, order amounts and prices are stored as minor units, this type is what
+/// they are parsed from and formatted as.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Amount {
+    units: u64,
+    scale: u32,
+}
+
+/// The amount of the quote asset paid for one whole unit of the base asset
+pub type Price = Amount;
+
+impl Amount {
+    /// `units` minor units at `scale` decimals
+    /// # Panics
+    /// `scale` is above [`MAX_SCALE`]
+    pub fn new(units: u64, scale: u32) -> Self {
+        assert!(scale <= MAX_SCALE, "scale {} is above {}", scale, MAX_SCALE);
+        Amount { units, scale }
+    }
+
+    /// The value in minor units
+    pub fn units(&self) -> u64 {
+        self.units
+    }
+
+    /// Number of decimals
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    /// Parses a non-negative decimal like "10.25" with at most `scale` decimals
+    /// # Errors
+    /// Not a number, too many decimals or too large
+    pub fn parse(s: &str, scale: u32) -> Result<Self, ApplicationError> {
+        let invalid = |reason: &str| ApplicationError::InvalidAmount(format!("'{}' {}", s, reason));
+        if scale > MAX_SCALE {
+            return Err(invalid("has an unsupported scale"));
+        }
+        let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+        let digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if whole.is_empty() || !digits(whole) || !digits(fraction) || s.ends_with('.') {
+            return Err(invalid("is not a number"));
+        }
+        if fraction.len() > scale as usize {
+            return Err(invalid(&format!("has more than {} decimals", scale)));
+        }
+        let padded = format!("{}{:0<width$}", whole, fraction, width = scale as usize);
+        padded
+            .parse()
+            .map(|units| Amount::new(units, scale))
+            .map_err(|_| invalid("is too large"))
+    }
+
+    /// The same value at `scale` decimals
+    /// # Errors
+    /// The value doesn't fit or would lose decimals
+    pub fn rescale(self, scale: u32) -> Result<Self, ApplicationError> {
+        let overflow = || ApplicationError::InvalidAmount(format!("{} doesn't fit", self));
+        if scale > MAX_SCALE {
+            return Err(overflow());
+        }
+        if scale >= self.scale {
+            let units = self
+                .units
+                .checked_mul(10u64.pow(scale - self.scale))
+                .ok_or_else(overflow)?;
+            Ok(Amount::new(units, scale))
+        } else {
+            let factor = 10u64.pow(self.scale - scale);
+            if !self.units.is_multiple_of(factor) {
+                return Err(ApplicationError::InvalidAmount(format!(
+                    "{} has more than {} decimals",
+                    self, scale
+                )));
+            }
+            Ok(Amount::new(self.units / factor, scale))
+        }
+    }
+
+    /// `self + other`
+    /// # Errors
+    /// The scales differ or the sum doesn't fit
+    pub fn checked_add(self, other: Amount) -> Result<Self, ApplicationError> {
+        let other = self.same_scale(other)?;
+        self.units
+            .checked_add(other.units)
+            .map(|units| Amount::new(units, self.scale))
+            .ok_or_else(|| {
+                ApplicationError::InvalidAmount(format!("{} + {} doesn't fit", self, other))
+            })
+    }
+
+    /// `self - other`
+    /// # Errors
+    /// The scales differ or the difference is negative
+    pub fn checked_sub(self, other: Amount) -> Result<Self, ApplicationError> {
+        let other = self.same_scale(other)?;
+        self.units
+            .checked_sub(other.units)
+            .map(|units| Amount::new(units, self.scale))
+            .ok_or_else(|| {
+                ApplicationError::InvalidAmount(format!("{} - {} is negative", self, other))
+            })
+    }
+
+    fn same_scale(self, other: Amount) -> Result<Amount, ApplicationError> {
+        if self.scale == other.scale {
+            Ok(other)
+        } else {
+            Err(ApplicationError::InvalidAmount(format!(
+                "{} and {} have different scales",
+                self, other
+            )))
+        }
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.scale == 0 {
+            return write!(f, "{}", self.units);
+        }
+        let factor = 10u64.pow(self.scale);
+        write!(
+            f,
+            "{}.{:0width$}",
+            self.units / factor,
+            self.units % factor,
+            width = self.scale as usize
+        )
+    }
+}
+
+/// Something that can be held and traded, with the number of decimals of its minor unit
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Asset {
+    /// E.g. "USD"
+    pub code: String,
+    /// E.g. 2 for cents
+    pub scale: u32,
+}
+
+impl Default for Asset {
+    /// Whole units without a name, what plain integers used to mean
+    fn default() -> Self {
+        Asset::new("UNIT", 0)
+    }
+}
+
+impl Asset {
+    /// Creates an asset with minor units of 10^-`scale`
+    /// # Panics
+    /// `scale` is above [`MAX_SCALE`]
+    pub fn new(code: &str, scale: u32) -> Self {
+        assert!(scale <= MAX_SCALE, "scale {} is above {}", scale, MAX_SCALE);
+        Asset {
+            code: code.to_string(),
+            scale,
+        }
+    }
+
+    /// `units` minor units of this asset
+    pub fn amount(&self, units: u64) -> Amount {
+        Amount::new(units, self.scale)
+    }
+
+    /// Parses a decimal like "10.25" into minor units of this asset
+    /// # Errors
+    /// See [`Amount::parse`]
+    pub fn parse(&self, s: &str) -> Result<u64, ApplicationError> {
+        Amount::parse(s, self.scale).map(|amount| amount.units())
+    }
+
+    /// `amount` in minor units of this asset
+    /// # Errors
+    /// `amount` has more decimals than the asset or doesn't fit
+    pub fn minor_units(&self, amount: Amount) -> Result<u64, ApplicationError> {
+        amount.rescale(self.scale).map(|amount| amount.units())
+    }
+}
+
+/// The pair of assets an order book trades. Order amounts are minor units of `base`, prices are
+/// minor units of `quote` per whole unit of `base` and balances are minor units of `quote`.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Market {
+    /// What is bought and sold
+    pub base: Asset,
+    /// What it is paid with
+    pub quote: Asset,
+}
+
+impl Market {
+    /// Creates the market for trading `base` against `quote`
+    pub fn new(base: Asset, quote: Asset) -> Self {
+        Market { base, quote }
+    }
+
+    /// Names the market, e.g. "BTC/USD"
+    pub fn symbol(&self) -> String {
+        format!("{}/{}", self.base.code, self.quote.code)
+    }
+
+    /// What `amount` minor units of the base asset cost at `price`, in minor units of the quote
+    /// asset. Fractions of a minor unit are rounded up.
+    /// # Errors
+    /// The result doesn't fit
+    pub fn notional(&self, price: u64, amount: u64) -> Result<u64, ApplicationError> {
+        self.fill_notional(0, price, amount)
+    }
+
+    /// What a fill of `amount` at `price` adds to the notional of an order whose earlier fills
+    /// sum up to `filled` (price * amount each). The order as a whole is rounded up once, so
+    /// filling it in small parts doesn't cost more than filling it at once.
+    /// # Errors
+    /// The result doesn't fit
+    pub fn fill_notional(
+        &self,
+        filled: u128,
+        price: u64,
+        amount: u64,
+    ) -> Result<u64, ApplicationError> {
+        let scale = 10u128.pow(self.base.scale);
+        let after = filled.saturating_add(price as u128 * amount as u128);
+        let units = after.div_ceil(scale) - filled.div_ceil(scale);
+        u64::try_from(units).map_err(|_| {
+            ApplicationError::InvalidAmount(format!(
+                "{} {} at {} {} doesn't fit",
+                self.base.amount(amount),
+                self.base.code,
+                self.quote.amount(price),
+                self.quote.code
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_Amount_parse_and_format() {
+        let amount = Amount::parse("10.25", 2).unwrap();
+        assert_eq!(amount.units(), 1025);
+        assert_eq!(amount.to_string(), "10.25");
+        assert_eq!(Amount::parse("10.2", 2).unwrap().to_string(), "10.20");
+        assert_eq!(Amount::parse("10", 2).unwrap().units(), 1000);
+        assert_eq!(Amount::parse("0.00000001", 8).unwrap().units(), 1);
+        assert_eq!(Amount::new(7, 0).to_string(), "7");
+        assert_eq!(Amount::new(5, 3).to_string(), "0.005");
+
+        for bad in ["", ".5", "5.", "-1", "1.2.3", "1e3", "ten"] {
+            assert!(Amount::parse(bad, 2).is_err(), "{}", bad);
+        }
+        assert_eq!(
+            Amount::parse("10.255", 2),
+            Err(ApplicationError::InvalidAmount(
+                "'10.255' has more than 2 decimals".to_string()
+            ))
+        );
+        assert!(Amount::parse("184467440737095516.16", 2).is_err());
+    }
+
+    #[test]
+    fn test_Amount_checked_arithmetic() {
+        let a = Amount::new(1025, 2);
+        let b = Amount::new(75, 2);
+        assert_eq!(a.checked_add(b), Ok(Amount::new(1100, 2)));
+        assert_eq!(a.checked_sub(b), Ok(Amount::new(950, 2)));
+        assert!(b.checked_sub(a).is_err());
+        assert!(Amount::new(u64::MAX, 2).checked_add(b).is_err());
+        assert!(a.checked_add(Amount::new(1, 3)).is_err());
+
+        assert_eq!(a.rescale(4), Ok(Amount::new(102_500, 4)));
+        assert_eq!(Amount::new(1000, 2).rescale(0), Ok(Amount::new(10, 0)));
+        assert!(a.rescale(1).is_err());
+        assert!(Amount::new(u64::MAX, 0).rescale(1).is_err());
+    }
+
+    #[test]
+    fn test_Asset_minor_units() {
+        let usd = Asset::new("USD", 2);
+        assert_eq!(usd.minor_units(Amount::new(1025, 2)), Ok(1025));
+        assert_eq!(usd.minor_units(Amount::new(10, 0)), Ok(1000));
+        assert_eq!(usd.minor_units(Amount::new(10_250, 3)), Ok(1025));
+        assert_eq!(
+            usd.minor_units(Amount::new(10_255, 3)),
+            Err(ApplicationError::InvalidAmount(
+                "10.255 has more than 2 decimals".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_Market_notional() {
+        let market = Market::new(Asset::new("BTC", 8), Asset::new("USD", 2));
+        let price = market.quote.parse("30000.50").unwrap();
+        let amount = market.base.parse("0.5").unwrap();
+        assert_eq!(
+            market.quote.amount(market.notional(price, amount).unwrap()),
+            Amount::parse("15000.25", 2).unwrap()
+        );
+        // one satoshi at a cent is a fraction of a cent
+        assert_eq!(market.notional(1, 1), Ok(1));
+        assert!(Market::default().notional(u64::MAX, 2).is_err());
+        assert_eq!(Market::default().notional(10, 2), Ok(20));
+        assert_eq!(market.symbol(), "BTC/USD");
+    }
+
+    #[test]
+    fn test_Market_fill_notional_rounds_the_order_once() {
+        let market = Market::new(Asset::new("BTC", 8), Asset::new("USD", 2));
+        // a third of a cent per fill: only the first of three costs a cent
+        let third = 33_333_333;
+        assert_eq!(market.fill_notional(0, 1, third), Ok(1));
+        assert_eq!(market.fill_notional(third as u128, 1, third), Ok(0));
+        assert_eq!(market.fill_notional(2 * third as u128, 1, third), Ok(0));
+        assert_eq!(market.notional(1, 3 * third), Ok(1));
+        assert_eq!(market.fill_notional(3 * third as u128, 1, third), Ok(1));
+    }
+}
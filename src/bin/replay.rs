@@ -0,0 +1,61 @@
+//! Replays a command journal and prints the outputs, or compares them with a recorded run.
+//!
+//! ```text
+//! replay <journal>              print one output per command
+//! replay <journal> <recorded>   exit with 1 at the first output that differs
+//! ```
+use learning_data_structures_and_borrowing_with_lending_in_rust_1::sequencer::{
+    diff_replay, parse_journal, Sequencer,
+};
+use std::{env, fs, process};
+
+fn read(path: &str) -> String {
+    fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("Couldn't read '{}': {}", path, e);
+        process::exit(2);
+    })
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (journal, recorded) = match args.as_slice() {
+        [journal] => (journal, None),
+        [journal, recorded] => (journal, Some(recorded)),
+        _ => {
+            eprintln!("Usage: replay <journal> [<recorded outputs>]");
+            process::exit(2);
+        }
+    };
+    let commands = parse_journal(&read(journal)).unwrap_or_else(|e| {
+        eprintln!("Invalid journal: {}", e);
+        process::exit(2);
+    });
+
+    let Some(recorded) = recorded else {
+        match Sequencer::new().run(commands) {
+            Ok(outputs) => outputs.iter().for_each(|o| println!("{}", o)),
+            Err(e) => {
+                eprintln!("Replay failed: {:?}", e);
+                process::exit(2);
+            }
+        }
+        return;
+    };
+    let recorded = read(recorded);
+    match diff_replay(commands, recorded.lines()) {
+        Ok(None) => println!("Outputs match the recorded run"),
+        Ok(Some(divergence)) => {
+            println!("Outputs differ at line {}", divergence.index + 1);
+            println!(
+                "- {}",
+                divergence.expected.as_deref().unwrap_or("<missing>")
+            );
+            println!("+ {}", divergence.actual.as_deref().unwrap_or("<missing>"));
+            process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Replay failed: {:?}", e);
+            process::exit(2);
+        }
+    }
+}
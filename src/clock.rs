@@ -0,0 +1,70 @@
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Milliseconds since the UNIX epoch
+pub type Timestamp = u64;
+
+/// One second in [`Timestamp`] units
+pub const SECOND: Timestamp = 1_000;
+/// One hour in [`Timestamp`] units
+pub const HOUR: Timestamp = 60 * 60 * SECOND;
+/// One day in [`Timestamp`] units
+pub const DAY: Timestamp = 24 * HOUR;
+
+/// A source of time. Injected into the types that need to know the current time so that tests
+/// (and replays) can control it.
+pub trait Clock: Debug {
+    /// The current point in time
+    fn now(&self) -> Timestamp;
+}
+
+/// A [`Clock`] backed by the operating system's wall clock
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Timestamp {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as Timestamp)
+            .unwrap_or(0)
+    }
+}
+
+/// A [`Clock`] that only moves when told to. Clones share the same time, so a caller can keep a
+/// handle to advance the clock after injecting it.
+#[derive(Debug, Default, Clone)]
+pub struct ManualClock {
+    now: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    /// Creates a new [`ManualClock`] starting at `now`
+    pub fn new(now: Timestamp) -> Self {
+        ManualClock {
+            now: Arc::new(AtomicU64::new(now)),
+        }
+    }
+
+    /// Sets the clock to `now`
+    pub fn set(&self, now: Timestamp) {
+        self.now.store(now, Ordering::SeqCst);
+    }
+
+    /// Moves the clock forward by `by`
+    pub fn advance(&self, by: Timestamp) {
+        self.now.fetch_add(by, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Timestamp {
+        self.now.load(Ordering::SeqCst)
+    }
+}
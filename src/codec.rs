@@ -0,0 +1,1520 @@
+use crate::{
+    accounting::AccountStatus,
+    amount::{Asset, Market, MAX_SCALE},
+    core::{MassCancel, Order, OrderState, OrderStatus, PartialOrder, Side},
+    errors::ApplicationError,
+    escrow::{Condition, Escrow},
+    fees::{FeeSchedule, FeeTier},
+    interest::{Accrual, AccrualKind, InterestSchedule, Rates},
+    limits::{Cap, Limits, Usage, Window},
+    margin::{Fill, MarginRequirements, Position},
+    risk::RiskLimits,
+    schedule::{Recurrence, RetryPolicy, Schedule},
+    tx::{Cause, Tx, TxId, TxMeta},
+};
+
+/// A minimal little-endian binary encoding for everything that goes to disk
+#[derive(Debug, Default)]
+pub struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    /// Creates an empty writer
+    pub fn new() -> Self {
+        Writer::default()
+    }
+
+    /// The encoded bytes
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn put_u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    pub fn put_u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn put_u64(&mut self, v: u64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn put_u128(&mut self, v: u128) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn put_i64(&mut self, v: i64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn put_i128(&mut self, v: i128) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn put_bool(&mut self, v: bool) {
+        self.put_u8(v as u8);
+    }
+
+    pub fn put_str(&mut self, v: &str) {
+        self.put_u32(v.len() as u32);
+        self.buf.extend_from_slice(v.as_bytes());
+    }
+
+    pub fn put<T: Encode>(&mut self, v: &T) {
+        v.encode(self);
+    }
+
+    pub fn put_option<T: Encode>(&mut self, v: &Option<T>) {
+        match v {
+            Some(v) => {
+                self.put_bool(true);
+                v.encode(self);
+            }
+            None => self.put_bool(false),
+        }
+    }
+
+    pub fn put_vec<T: Encode>(&mut self, v: &[T]) {
+        self.put_u32(v.len() as u32);
+        for item in v {
+            item.encode(self);
+        }
+    }
+}
+
+/// Reads what a [`Writer`] wrote
+#[derive(Debug)]
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Reads from the start of `buf`
+    pub fn new(buf: &'a [u8]) -> Self {
+        Reader { buf, pos: 0 }
+    }
+
+    /// Everything has been read
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.buf.len()
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], ApplicationError> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|end| *end <= self.buf.len())
+            .ok_or_else(|| corrupted("unexpected end of data"))?;
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ApplicationError> {
+        let mut a = [0; N];
+        a.copy_from_slice(self.take(N)?);
+        Ok(a)
+    }
+
+    pub fn get_u8(&mut self) -> Result<u8, ApplicationError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn get_u32(&mut self) -> Result<u32, ApplicationError> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    pub fn get_u64(&mut self) -> Result<u64, ApplicationError> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    pub fn get_u128(&mut self) -> Result<u128, ApplicationError> {
+        self.array().map(u128::from_le_bytes)
+    }
+
+    pub fn get_i64(&mut self) -> Result<i64, ApplicationError> {
+        self.array().map(i64::from_le_bytes)
+    }
+
+    pub fn get_i128(&mut self) -> Result<i128, ApplicationError> {
+        self.array().map(i128::from_le_bytes)
+    }
+
+    pub fn get_bool(&mut self) -> Result<bool, ApplicationError> {
+        match self.get_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(corrupted(&format!("invalid bool {}", other))),
+        }
+    }
+
+    pub fn get_string(&mut self) -> Result<String, ApplicationError> {
+        let len = self.get_u32()? as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| corrupted("invalid utf-8"))
+    }
+
+    pub fn get<T: Decode>(&mut self) -> Result<T, ApplicationError> {
+        T::decode(self)
+    }
+
+    pub fn get_option<T: Decode>(&mut self) -> Result<Option<T>, ApplicationError> {
+        if self.get_bool()? {
+            T::decode(self).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn get_vec<T: Decode>(&mut self) -> Result<Vec<T>, ApplicationError> {
+        let len = self.get_u32()? as usize;
+        (0..len).map(|_| T::decode(self)).collect()
+    }
+}
+
+/// Types that can be written with a [`Writer`]
+pub trait Encode {
+    fn encode(&self, w: &mut Writer);
+
+    /// Shorthand to encode into a fresh buffer
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer::new();
+        self.encode(&mut w);
+        w.into_bytes()
+    }
+}
+
+/// Types that can be read with a [`Reader`]
+pub trait Decode: Sized {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError>;
+
+    /// Shorthand to decode a complete buffer
+    fn from_bytes(bytes: &[u8]) -> Result<Self, ApplicationError> {
+        let mut r = Reader::new(bytes);
+        let v = Self::decode(&mut r)?;
+        if r.is_empty() {
+            Ok(v)
+        } else {
+            Err(corrupted("trailing bytes"))
+        }
+    }
+}
+
+fn corrupted(reason: &str) -> ApplicationError {
+    ApplicationError::Corrupted(reason.to_string())
+}
+
+fn invalid_tag(type_name: &str, tag: u8) -> ApplicationError {
+    corrupted(&format!("invalid {} tag {}", type_name, tag))
+}
+
+/// CRC-32 (IEEE) of `bytes`
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+impl Encode for u32 {
+    fn encode(&self, w: &mut Writer) {
+        w.put_u32(*self);
+    }
+}
+
+impl Decode for u32 {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        r.get_u32()
+    }
+}
+
+impl Encode for u64 {
+    fn encode(&self, w: &mut Writer) {
+        w.put_u64(*self);
+    }
+}
+
+impl Decode for u64 {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        r.get_u64()
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, w: &mut Writer) {
+        w.put_str(self);
+    }
+}
+
+impl Decode for String {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        r.get_string()
+    }
+}
+
+impl Encode for (String, u64) {
+    fn encode(&self, w: &mut Writer) {
+        w.put_str(&self.0);
+        w.put_u64(self.1);
+    }
+}
+
+impl Decode for (String, u64) {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok((r.get_string()?, r.get_u64()?))
+    }
+}
+
+impl Encode for (String, String) {
+    fn encode(&self, w: &mut Writer) {
+        w.put_str(&self.0);
+        w.put_str(&self.1);
+    }
+}
+
+impl Decode for (String, String) {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok((r.get_string()?, r.get_string()?))
+    }
+}
+
+impl Encode for AccountStatus {
+    fn encode(&self, w: &mut Writer) {
+        w.put_u8(match self {
+            AccountStatus::Open => 0,
+            AccountStatus::Frozen => 1,
+            AccountStatus::Closed => 2,
+        });
+    }
+}
+
+impl Decode for AccountStatus {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        match r.get_u8()? {
+            0 => Ok(AccountStatus::Open),
+            1 => Ok(AccountStatus::Frozen),
+            2 => Ok(AccountStatus::Closed),
+            tag => Err(invalid_tag("AccountStatus", tag)),
+        }
+    }
+}
+
+impl Encode for (String, AccountStatus) {
+    fn encode(&self, w: &mut Writer) {
+        w.put_str(&self.0);
+        w.put(&self.1);
+    }
+}
+
+impl Decode for (String, AccountStatus) {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok((r.get_string()?, r.get()?))
+    }
+}
+
+impl Encode for Asset {
+    fn encode(&self, w: &mut Writer) {
+        w.put_str(&self.code);
+        w.put_u32(self.scale);
+    }
+}
+
+impl Decode for Asset {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        let code = r.get_string()?;
+        let scale = r.get_u32()?;
+        if scale > MAX_SCALE {
+            return Err(corrupted(&format!(
+                "scale {} of {} is too large",
+                scale, code
+            )));
+        }
+        Ok(Asset { code, scale })
+    }
+}
+
+impl Encode for Market {
+    fn encode(&self, w: &mut Writer) {
+        w.put(&self.base);
+        w.put(&self.quote);
+    }
+}
+
+impl Decode for Market {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok(Market {
+            base: r.get()?,
+            quote: r.get()?,
+        })
+    }
+}
+
+impl Encode for Side {
+    fn encode(&self, w: &mut Writer) {
+        w.put_u8(match self {
+            Side::Buy => 0,
+            Side::Sell => 1,
+        });
+    }
+}
+
+impl Decode for Side {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        match r.get_u8()? {
+            0 => Ok(Side::Buy),
+            1 => Ok(Side::Sell),
+            tag => Err(invalid_tag("Side", tag)),
+        }
+    }
+}
+
+impl Encode for Order {
+    fn encode(&self, w: &mut Writer) {
+        w.put_u64(self.price);
+        w.put_u64(self.amount);
+        w.put(&self.side);
+        w.put_str(&self.signer);
+    }
+}
+
+impl Decode for Order {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok(Order {
+            price: r.get_u64()?,
+            amount: r.get_u64()?,
+            side: r.get()?,
+            signer: r.get_string()?,
+        })
+    }
+}
+
+impl Encode for PartialOrder {
+    fn encode(&self, w: &mut Writer) {
+        w.put_u64(self.price);
+        w.put_u64(self.amount);
+        w.put_u64(self.remaining);
+        w.put(&self.side);
+        w.put_str(&self.signer);
+        w.put_u64(self.ordinal);
+    }
+}
+
+impl Decode for PartialOrder {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok(PartialOrder {
+            price: r.get_u64()?,
+            amount: r.get_u64()?,
+            remaining: r.get_u64()?,
+            side: r.get()?,
+            signer: r.get_string()?,
+            ordinal: r.get_u64()?,
+        })
+    }
+}
+
+impl Encode for OrderState {
+    fn encode(&self, w: &mut Writer) {
+        w.put_u8(match self {
+            OrderState::New => 0,
+            OrderState::PartiallyFilled => 1,
+            OrderState::Filled => 2,
+            OrderState::Cancelled => 3,
+            OrderState::Expired => 4,
+            OrderState::Rejected => 5,
+        });
+    }
+}
+
+impl Decode for OrderState {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        match r.get_u8()? {
+            0 => Ok(OrderState::New),
+            1 => Ok(OrderState::PartiallyFilled),
+            2 => Ok(OrderState::Filled),
+            3 => Ok(OrderState::Cancelled),
+            4 => Ok(OrderState::Expired),
+            5 => Ok(OrderState::Rejected),
+            tag => Err(invalid_tag("OrderState", tag)),
+        }
+    }
+}
+
+impl Encode for OrderStatus {
+    fn encode(&self, w: &mut Writer) {
+        w.put_u64(self.ordinal);
+        w.put_str(&self.signer);
+        w.put(&self.side);
+        w.put_u64(self.price);
+        w.put_u64(self.amount);
+        w.put_u64(self.filled);
+        w.put_u128(self.filled_notional);
+        w.put(&self.state);
+    }
+}
+
+impl Decode for OrderStatus {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok(OrderStatus {
+            ordinal: r.get_u64()?,
+            signer: r.get_string()?,
+            side: r.get()?,
+            price: r.get_u64()?,
+            amount: r.get_u64()?,
+            filled: r.get_u64()?,
+            filled_notional: r.get_u128()?,
+            state: r.get()?,
+        })
+    }
+}
+
+impl Encode for MassCancel {
+    fn encode(&self, w: &mut Writer) {
+        match self {
+            MassCancel::Signer(signer) => {
+                w.put_u8(0);
+                w.put_str(signer);
+            }
+            MassCancel::SignerSide(signer, side) => {
+                w.put_u8(1);
+                w.put_str(signer);
+                w.put(side);
+            }
+            MassCancel::Market => w.put_u8(2),
+            MassCancel::Above(price) => {
+                w.put_u8(3);
+                w.put_u64(*price);
+            }
+            MassCancel::Below(price) => {
+                w.put_u8(4);
+                w.put_u64(*price);
+            }
+        }
+    }
+}
+
+impl Decode for MassCancel {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        match r.get_u8()? {
+            0 => Ok(MassCancel::Signer(r.get_string()?)),
+            1 => Ok(MassCancel::SignerSide(r.get_string()?, r.get()?)),
+            2 => Ok(MassCancel::Market),
+            3 => Ok(MassCancel::Above(r.get_u64()?)),
+            4 => Ok(MassCancel::Below(r.get_u64()?)),
+            tag => Err(invalid_tag("MassCancel", tag)),
+        }
+    }
+}
+
+impl Encode for Cause {
+    fn encode(&self, w: &mut Writer) {
+        match self {
+            Cause::Order(ordinal) => {
+                w.put_u8(0);
+                w.put_u64(*ordinal);
+            }
+            Cause::Trade {
+                taker_ordinal,
+                maker_ordinal,
+            } => {
+                w.put_u8(1);
+                w.put_u64(*taker_ordinal);
+                w.put_u64(*maker_ordinal);
+            }
+            Cause::Tx(id) => {
+                w.put_u8(2);
+                w.put_u128(id.0);
+            }
+            Cause::Schedule(id) => {
+                w.put_u8(3);
+                w.put_u64(*id);
+            }
+        }
+    }
+}
+
+impl Decode for Cause {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        match r.get_u8()? {
+            0 => Ok(Cause::Order(r.get_u64()?)),
+            1 => Ok(Cause::Trade {
+                taker_ordinal: r.get_u64()?,
+                maker_ordinal: r.get_u64()?,
+            }),
+            2 => Ok(Cause::Tx(TxId(r.get_u128()?))),
+            3 => Ok(Cause::Schedule(r.get_u64()?)),
+            tag => Err(invalid_tag("Cause", tag)),
+        }
+    }
+}
+
+impl Encode for Condition {
+    fn encode(&self, w: &mut Writer) {
+        match self {
+            Condition::Approval { arbiter } => {
+                w.put_u8(0);
+                w.put_str(arbiter);
+            }
+            Condition::Deadline(deadline) => {
+                w.put_u8(1);
+                w.put_u64(*deadline);
+            }
+            Condition::ApprovalBefore { arbiter, deadline } => {
+                w.put_u8(2);
+                w.put_str(arbiter);
+                w.put_u64(*deadline);
+            }
+        }
+    }
+}
+
+impl Decode for Condition {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        match r.get_u8()? {
+            0 => Ok(Condition::Approval {
+                arbiter: r.get_string()?,
+            }),
+            1 => Ok(Condition::Deadline(r.get_u64()?)),
+            2 => Ok(Condition::ApprovalBefore {
+                arbiter: r.get_string()?,
+                deadline: r.get_u64()?,
+            }),
+            tag => Err(invalid_tag("Condition", tag)),
+        }
+    }
+}
+
+impl Encode for Escrow {
+    fn encode(&self, w: &mut Writer) {
+        w.put_u64(self.id);
+        w.put_str(&self.sender);
+        w.put_str(&self.recipient);
+        w.put_u64(self.amount);
+        w.put(&self.condition);
+    }
+}
+
+impl Decode for Escrow {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok(Escrow {
+            id: r.get_u64()?,
+            sender: r.get_string()?,
+            recipient: r.get_string()?,
+            amount: r.get_u64()?,
+            condition: r.get()?,
+        })
+    }
+}
+
+impl Encode for Recurrence {
+    fn encode(&self, w: &mut Writer) {
+        w.put_u8(match self {
+            Recurrence::Once => 0,
+            Recurrence::Daily => 1,
+            Recurrence::Weekly => 2,
+            Recurrence::Monthly => 3,
+        });
+    }
+}
+
+impl Decode for Recurrence {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        match r.get_u8()? {
+            0 => Ok(Recurrence::Once),
+            1 => Ok(Recurrence::Daily),
+            2 => Ok(Recurrence::Weekly),
+            3 => Ok(Recurrence::Monthly),
+            tag => Err(invalid_tag("Recurrence", tag)),
+        }
+    }
+}
+
+impl Encode for RetryPolicy {
+    fn encode(&self, w: &mut Writer) {
+        w.put_u32(self.max_retries);
+        w.put_u64(self.delay);
+    }
+}
+
+impl Decode for RetryPolicy {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok(RetryPolicy {
+            max_retries: r.get_u32()?,
+            delay: r.get_u64()?,
+        })
+    }
+}
+
+impl Encode for Schedule {
+    fn encode(&self, w: &mut Writer) {
+        w.put_u64(self.id);
+        w.put_str(&self.sender);
+        w.put_str(&self.recipient);
+        w.put_u64(self.amount);
+        w.put_u64(self.start);
+        w.put(&self.recurrence);
+        w.put(&self.retry);
+        w.put_u64(self.runs);
+        w.put_u32(self.attempts);
+        w.put_u64(self.next);
+    }
+}
+
+impl Decode for Schedule {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok(Schedule {
+            id: r.get_u64()?,
+            sender: r.get_string()?,
+            recipient: r.get_string()?,
+            amount: r.get_u64()?,
+            start: r.get_u64()?,
+            recurrence: r.get()?,
+            retry: r.get()?,
+            runs: r.get_u64()?,
+            attempts: r.get_u32()?,
+            next: r.get_u64()?,
+        })
+    }
+}
+
+impl Encode for AccrualKind {
+    fn encode(&self, w: &mut Writer) {
+        w.put_u8(match self {
+            AccrualKind::Interest => 0,
+            AccrualKind::CustodyFee => 1,
+            AccrualKind::Overdraft => 2,
+        });
+    }
+}
+
+impl Decode for AccrualKind {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        match r.get_u8()? {
+            0 => Ok(AccrualKind::Interest),
+            1 => Ok(AccrualKind::CustodyFee),
+            2 => Ok(AccrualKind::Overdraft),
+            tag => Err(invalid_tag("AccrualKind", tag)),
+        }
+    }
+}
+
+impl Encode for Accrual {
+    fn encode(&self, w: &mut Writer) {
+        w.put_str(&self.account);
+        w.put(&self.kind);
+        w.put_u128(self.amount);
+    }
+}
+
+impl Decode for Accrual {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok(Accrual {
+            account: r.get_string()?,
+            kind: r.get()?,
+            amount: r.get_u128()?,
+        })
+    }
+}
+
+impl Encode for Fill {
+    fn encode(&self, w: &mut Writer) {
+        w.put_str(&self.symbol);
+        w.put(&self.side);
+        w.put_u64(self.amount);
+        w.put_u64(self.price);
+        w.put_u64(self.notional);
+    }
+}
+
+impl Decode for Fill {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok(Fill {
+            symbol: r.get_string()?,
+            side: r.get()?,
+            amount: r.get_u64()?,
+            price: r.get_u64()?,
+            notional: r.get_u64()?,
+        })
+    }
+}
+
+impl Encode for Position {
+    fn encode(&self, w: &mut Writer) {
+        w.put_str(&self.account);
+        w.put_str(&self.symbol);
+        w.put_i128(self.size);
+        w.put_i128(self.cost);
+        w.put_i128(self.realised_pnl);
+    }
+}
+
+impl Decode for Position {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok(Position {
+            account: r.get_string()?,
+            symbol: r.get_string()?,
+            size: r.get_i128()?,
+            cost: r.get_i128()?,
+            realised_pnl: r.get_i128()?,
+        })
+    }
+}
+
+impl Encode for MarginRequirements {
+    fn encode(&self, w: &mut Writer) {
+        w.put_str(&self.account);
+        w.put_u32(self.initial_bps);
+        w.put_u32(self.maintenance_bps);
+    }
+}
+
+impl Decode for MarginRequirements {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok(MarginRequirements {
+            account: r.get_string()?,
+            initial_bps: r.get_u32()?,
+            maintenance_bps: r.get_u32()?,
+        })
+    }
+}
+
+impl Encode for RiskLimits {
+    fn encode(&self, w: &mut Writer) {
+        w.put_option(&self.max_order_size);
+        w.put_option(&self.max_notional);
+        w.put_option(&self.max_open_orders.map(|max| max as u64));
+        w.put_option(&self.max_position);
+        w.put_option(&self.max_deviation_bps);
+        w.put_bool(self.buying_power);
+    }
+}
+
+impl Decode for RiskLimits {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok(RiskLimits {
+            max_order_size: r.get_option()?,
+            max_notional: r.get_option()?,
+            max_open_orders: r.get_option::<u64>()?.map(|max| max as usize),
+            max_position: r.get_option()?,
+            max_deviation_bps: r.get_option()?,
+            buying_power: r.get_bool()?,
+        })
+    }
+}
+
+impl Encode for (String, RiskLimits) {
+    fn encode(&self, w: &mut Writer) {
+        w.put_str(&self.0);
+        w.put(&self.1);
+    }
+}
+
+impl Decode for (String, RiskLimits) {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok((r.get_string()?, r.get()?))
+    }
+}
+
+impl Encode for (String, String, RiskLimits) {
+    fn encode(&self, w: &mut Writer) {
+        w.put_str(&self.0);
+        w.put_str(&self.1);
+        w.put(&self.2);
+    }
+}
+
+impl Decode for (String, String, RiskLimits) {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok((r.get_string()?, r.get_string()?, r.get()?))
+    }
+}
+
+impl Encode for Window {
+    fn encode(&self, w: &mut Writer) {
+        match self {
+            Window::Day => w.put_u8(0),
+            Window::Rolling(length) => {
+                w.put_u8(1);
+                w.put_u64(*length);
+            }
+        }
+    }
+}
+
+impl Decode for Window {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        match r.get_u8()? {
+            0 => Ok(Window::Day),
+            1 => Ok(Window::Rolling(r.get_u64()?)),
+            tag => Err(invalid_tag("Window", tag)),
+        }
+    }
+}
+
+impl Encode for Cap {
+    fn encode(&self, w: &mut Writer) {
+        w.put_u64(self.amount);
+        w.put(&self.window);
+    }
+}
+
+impl Decode for Cap {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok(Cap {
+            amount: r.get_u64()?,
+            window: r.get()?,
+        })
+    }
+}
+
+impl Encode for Limits {
+    fn encode(&self, w: &mut Writer) {
+        w.put_option(&self.withdrawal);
+        w.put_option(&self.max_transfer);
+        w.put_option(&self.daily_send);
+    }
+}
+
+impl Decode for Limits {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok(Limits {
+            withdrawal: r.get_option()?,
+            max_transfer: r.get_option()?,
+            daily_send: r.get_option()?,
+        })
+    }
+}
+
+impl Encode for (String, Limits) {
+    fn encode(&self, w: &mut Writer) {
+        w.put_str(&self.0);
+        w.put(&self.1);
+    }
+}
+
+impl Decode for (String, Limits) {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok((r.get_string()?, r.get()?))
+    }
+}
+
+impl Encode for (u64, u64) {
+    fn encode(&self, w: &mut Writer) {
+        w.put_u64(self.0);
+        w.put_u64(self.1);
+    }
+}
+
+impl Decode for (u64, u64) {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok((r.get_u64()?, r.get_u64()?))
+    }
+}
+
+impl Encode for Usage {
+    fn encode(&self, w: &mut Writer) {
+        w.put_vec(&self.withdrawals.iter().copied().collect::<Vec<_>>());
+        w.put_vec(&self.sends.iter().copied().collect::<Vec<_>>());
+    }
+}
+
+impl Decode for Usage {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok(Usage {
+            withdrawals: r.get_vec::<(u64, u64)>()?.into(),
+            sends: r.get_vec::<(u64, u64)>()?.into(),
+        })
+    }
+}
+
+impl Encode for (String, Usage) {
+    fn encode(&self, w: &mut Writer) {
+        w.put_str(&self.0);
+        w.put(&self.1);
+    }
+}
+
+impl Decode for (String, Usage) {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok((r.get_string()?, r.get()?))
+    }
+}
+
+impl Encode for FeeTier {
+    fn encode(&self, w: &mut Writer) {
+        w.put_u128(self.min_volume);
+        w.put_i64(self.maker_bps);
+        w.put_i64(self.taker_bps);
+    }
+}
+
+impl Decode for FeeTier {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok(FeeTier {
+            min_volume: r.get_u128()?,
+            maker_bps: r.get_i64()?,
+            taker_bps: r.get_i64()?,
+        })
+    }
+}
+
+impl Encode for FeeSchedule {
+    fn encode(&self, w: &mut Writer) {
+        w.put_str(&self.account);
+        w.put_vec(self.tiers());
+    }
+}
+
+impl Decode for FeeSchedule {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        let account = r.get_string()?;
+        Ok(FeeSchedule::new(&account, r.get_vec()?))
+    }
+}
+
+impl Encode for Rates {
+    fn encode(&self, w: &mut Writer) {
+        w.put_u32(self.interest_bps);
+        w.put_u32(self.custody_fee_bps);
+        w.put_u32(self.overdraft_bps);
+    }
+}
+
+impl Decode for Rates {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok(Rates {
+            interest_bps: r.get_u32()?,
+            custody_fee_bps: r.get_u32()?,
+            overdraft_bps: r.get_u32()?,
+        })
+    }
+}
+
+impl Encode for (String, Rates) {
+    fn encode(&self, w: &mut Writer) {
+        w.put_str(&self.0);
+        w.put(&self.1);
+    }
+}
+
+impl Decode for (String, Rates) {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok((r.get_string()?, r.get()?))
+    }
+}
+
+impl Encode for InterestSchedule {
+    fn encode(&self, w: &mut Writer) {
+        w.put_str(&self.account);
+        let rates: Vec<(String, Rates)> = self
+            .all_rates()
+            .into_iter()
+            .map(|(code, rates)| (code.to_string(), *rates))
+            .collect();
+        w.put_vec(&rates);
+    }
+}
+
+impl Decode for InterestSchedule {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        let account = r.get_string()?;
+        let rates: Vec<(String, Rates)> = r.get_vec()?;
+        Ok(InterestSchedule::new(&account, rates))
+    }
+}
+
+impl Encode for TxMeta {
+    fn encode(&self, w: &mut Writer) {
+        w.put_u128(self.id.0);
+        w.put_u64(self.sequence);
+        w.put_u64(self.timestamp);
+        w.put_option(&self.memo);
+        w.put_option(&self.cause);
+    }
+}
+
+impl Decode for TxMeta {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok(TxMeta {
+            id: TxId(r.get_u128()?),
+            sequence: r.get_u64()?,
+            timestamp: r.get_u64()?,
+            memo: r.get_option()?,
+            cause: r.get_option()?,
+        })
+    }
+}
+
+impl Encode for Tx {
+    fn encode(&self, w: &mut Writer) {
+        match self {
+            Tx::Deposit {
+                account,
+                amount,
+                meta,
+            } => {
+                w.put_u8(0);
+                w.put_str(account);
+                w.put_u64(*amount);
+                w.put(meta);
+            }
+            Tx::Withdraw {
+                account,
+                amount,
+                meta,
+            } => {
+                w.put_u8(1);
+                w.put_str(account);
+                w.put_u64(*amount);
+                w.put(meta);
+            }
+            Tx::Transfer {
+                sender,
+                recipient,
+                amount,
+                meta,
+            } => {
+                w.put_u8(2);
+                w.put_str(sender);
+                w.put_str(recipient);
+                w.put_u64(*amount);
+                w.put(meta);
+            }
+            Tx::StatusChange {
+                account,
+                status,
+                meta,
+            } => {
+                w.put_u8(3);
+                w.put_str(account);
+                w.put(status);
+                w.put(meta);
+            }
+            Tx::SubAccount {
+                account,
+                parent,
+                meta,
+            } => {
+                w.put_u8(4);
+                w.put_str(account);
+                w.put_str(parent);
+                w.put(meta);
+            }
+            Tx::EscrowOpened { escrow, meta } => {
+                w.put_u8(5);
+                w.put(escrow);
+                w.put(meta);
+            }
+            Tx::EscrowReleased {
+                id,
+                recipient,
+                amount,
+                meta,
+            } => {
+                w.put_u8(6);
+                w.put_u64(*id);
+                w.put_str(recipient);
+                w.put_u64(*amount);
+                w.put(meta);
+            }
+            Tx::EscrowRefunded {
+                id,
+                sender,
+                amount,
+                meta,
+            } => {
+                w.put_u8(7);
+                w.put_u64(*id);
+                w.put_str(sender);
+                w.put_u64(*amount);
+                w.put(meta);
+            }
+            Tx::ScheduleCreated { schedule, meta } => {
+                w.put_u8(8);
+                w.put(schedule);
+                w.put(meta);
+            }
+            Tx::ScheduleFailed { id, reason, meta } => {
+                w.put_u8(9);
+                w.put_u64(*id);
+                w.put_str(reason);
+                w.put(meta);
+            }
+            Tx::ScheduleCancelled { id, meta } => {
+                w.put_u8(10);
+                w.put_u64(*id);
+                w.put(meta);
+            }
+            Tx::InterestAccrued {
+                day,
+                accruals,
+                meta,
+            } => {
+                w.put_u8(11);
+                w.put_u64(*day);
+                w.put_vec(accruals);
+                w.put(meta);
+            }
+            Tx::AccrualPosted {
+                account,
+                house,
+                kind,
+                amount,
+                meta,
+            } => {
+                w.put_u8(12);
+                w.put_str(account);
+                w.put_str(house);
+                w.put(kind);
+                w.put_u64(*amount);
+                w.put(meta);
+            }
+            Tx::CreditLimitSet {
+                account,
+                limit,
+                meta,
+            } => {
+                w.put_u8(13);
+                w.put_str(account);
+                w.put_u64(*limit);
+                w.put(meta);
+            }
+            Tx::PositionFilled {
+                account,
+                fill,
+                meta,
+            } => {
+                w.put_u8(14);
+                w.put_str(account);
+                w.put(fill);
+                w.put(meta);
+            }
+            Tx::Liquidated {
+                account,
+                house,
+                fill,
+                meta,
+            } => {
+                w.put_u8(15);
+                w.put_str(account);
+                w.put_str(house);
+                w.put(fill);
+                w.put(meta);
+            }
+            Tx::LimitsSet {
+                account,
+                limits,
+                meta,
+            } => {
+                w.put_u8(16);
+                w.put_str(account);
+                w.put(limits);
+                w.put(meta);
+            }
+            Tx::InterestScheduleSet { schedule, meta } => {
+                w.put_u8(17);
+                w.put(schedule);
+                w.put(meta);
+            }
+            Tx::MarginRequirementsSet { requirements, meta } => {
+                w.put_u8(18);
+                w.put(requirements);
+                w.put(meta);
+            }
+            Tx::RiskLimitsSet {
+                account,
+                symbol,
+                limits,
+                meta,
+            } => {
+                w.put_u8(19);
+                w.put_option(account);
+                w.put_str(symbol);
+                w.put(limits);
+                w.put(meta);
+            }
+            Tx::MarketSet { market, meta } => {
+                w.put_u8(20);
+                w.put(market);
+                w.put(meta);
+            }
+            Tx::FeeScheduleSet { schedule, meta } => {
+                w.put_u8(21);
+                w.put(schedule);
+                w.put(meta);
+            }
+        }
+    }
+}
+
+impl Decode for Tx {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        match r.get_u8()? {
+            0 => Ok(Tx::Deposit {
+                account: r.get_string()?,
+                amount: r.get_u64()?,
+                meta: r.get()?,
+            }),
+            1 => Ok(Tx::Withdraw {
+                account: r.get_string()?,
+                amount: r.get_u64()?,
+                meta: r.get()?,
+            }),
+            2 => Ok(Tx::Transfer {
+                sender: r.get_string()?,
+                recipient: r.get_string()?,
+                amount: r.get_u64()?,
+                meta: r.get()?,
+            }),
+            3 => Ok(Tx::StatusChange {
+                account: r.get_string()?,
+                status: r.get()?,
+                meta: r.get()?,
+            }),
+            4 => Ok(Tx::SubAccount {
+                account: r.get_string()?,
+                parent: r.get_string()?,
+                meta: r.get()?,
+            }),
+            5 => Ok(Tx::EscrowOpened {
+                escrow: r.get()?,
+                meta: r.get()?,
+            }),
+            6 => Ok(Tx::EscrowReleased {
+                id: r.get_u64()?,
+                recipient: r.get_string()?,
+                amount: r.get_u64()?,
+                meta: r.get()?,
+            }),
+            7 => Ok(Tx::EscrowRefunded {
+                id: r.get_u64()?,
+                sender: r.get_string()?,
+                amount: r.get_u64()?,
+                meta: r.get()?,
+            }),
+            8 => Ok(Tx::ScheduleCreated {
+                schedule: r.get()?,
+                meta: r.get()?,
+            }),
+            9 => Ok(Tx::ScheduleFailed {
+                id: r.get_u64()?,
+                reason: r.get_string()?,
+                meta: r.get()?,
+            }),
+            10 => Ok(Tx::ScheduleCancelled {
+                id: r.get_u64()?,
+                meta: r.get()?,
+            }),
+            11 => Ok(Tx::InterestAccrued {
+                day: r.get_u64()?,
+                accruals: r.get_vec()?,
+                meta: r.get()?,
+            }),
+            12 => Ok(Tx::AccrualPosted {
+                account: r.get_string()?,
+                house: r.get_string()?,
+                kind: r.get()?,
+                amount: r.get_u64()?,
+                meta: r.get()?,
+            }),
+            13 => Ok(Tx::CreditLimitSet {
+                account: r.get_string()?,
+                limit: r.get_u64()?,
+                meta: r.get()?,
+            }),
+            14 => Ok(Tx::PositionFilled {
+                account: r.get_string()?,
+                fill: r.get()?,
+                meta: r.get()?,
+            }),
+            15 => Ok(Tx::Liquidated {
+                account: r.get_string()?,
+                house: r.get_string()?,
+                fill: r.get()?,
+                meta: r.get()?,
+            }),
+            16 => Ok(Tx::LimitsSet {
+                account: r.get_string()?,
+                limits: r.get()?,
+                meta: r.get()?,
+            }),
+            17 => Ok(Tx::InterestScheduleSet {
+                schedule: r.get()?,
+                meta: r.get()?,
+            }),
+            18 => Ok(Tx::MarginRequirementsSet {
+                requirements: r.get()?,
+                meta: r.get()?,
+            }),
+            19 => Ok(Tx::RiskLimitsSet {
+                account: r.get_option()?,
+                symbol: r.get_string()?,
+                limits: r.get()?,
+                meta: r.get()?,
+            }),
+            20 => Ok(Tx::MarketSet {
+                market: r.get()?,
+                meta: r.get()?,
+            }),
+            21 => Ok(Tx::FeeScheduleSet {
+                schedule: r.get()?,
+                meta: r.get()?,
+            }),
+            tag => Err(invalid_tag("Tx", tag)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tx::TxContext;
+
+    use super::*;
+
+    #[test]
+    fn test_crc32_known_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_codec_round_trip() {
+        let tx = Tx::Transfer {
+            sender: "ALICE".to_string(),
+            recipient: "BOB".to_string(),
+            amount: 42,
+            meta: TxMeta::new(
+                7,
+                1_000,
+                TxContext::caused_by(Cause::Trade {
+                    taker_ordinal: 2,
+                    maker_ordinal: 1,
+                })
+                .with_memo("settlement"),
+            ),
+        };
+        assert_eq!(Tx::from_bytes(&tx.to_bytes()), Ok(tx));
+
+        let order = Order {
+            price: 10,
+            amount: 2,
+            side: Side::Sell,
+            signer: "ALICE".to_string(),
+        };
+        assert_eq!(Order::from_bytes(&order.to_bytes()), Ok(order));
+
+        let liquidated = Tx::Liquidated {
+            account: "ALICE".to_string(),
+            house: "LIQUIDATION".to_string(),
+            fill: Fill {
+                symbol: "BTC/USD".to_string(),
+                side: Side::Sell,
+                amount: 3,
+                price: 90,
+                notional: 270,
+            },
+            meta: TxMeta::new(8, 1_000, TxContext::default()),
+        };
+        assert_eq!(Tx::from_bytes(&liquidated.to_bytes()), Ok(liquidated));
+
+        let limits_set = Tx::LimitsSet {
+            account: "ALICE".to_string(),
+            limits: Limits {
+                withdrawal: Some(Cap {
+                    amount: 100,
+                    window: Window::Rolling(3_600),
+                }),
+                max_transfer: None,
+                daily_send: Some(50),
+            },
+            meta: TxMeta::new(9, 1_000, TxContext::default()),
+        };
+        assert_eq!(Tx::from_bytes(&limits_set.to_bytes()), Ok(limits_set));
+
+        let schedule_set = Tx::InterestScheduleSet {
+            schedule: InterestSchedule::new(
+                "HOUSE",
+                [(
+                    "USD".to_string(),
+                    Rates {
+                        interest_bps: 200,
+                        custody_fee_bps: 10,
+                        overdraft_bps: 1_000,
+                    },
+                )],
+            ),
+            meta: TxMeta::new(10, 1_000, TxContext::default()),
+        };
+        assert_eq!(Tx::from_bytes(&schedule_set.to_bytes()), Ok(schedule_set));
+
+        let margin_set = Tx::MarginRequirementsSet {
+            requirements: MarginRequirements::new("LIQUIDATION", 1_000, 500),
+            meta: TxMeta::new(11, 1_000, TxContext::default()),
+        };
+        assert_eq!(Tx::from_bytes(&margin_set.to_bytes()), Ok(margin_set));
+
+        let risk_limits_set = Tx::RiskLimitsSet {
+            account: Some("ALICE".to_string()),
+            symbol: "BTC/USD".to_string(),
+            limits: RiskLimits {
+                max_open_orders: Some(3),
+                max_deviation_bps: Some(500),
+                buying_power: false,
+                ..RiskLimits::default()
+            },
+            meta: TxMeta::new(12, 1_000, TxContext::default()),
+        };
+        assert_eq!(
+            Tx::from_bytes(&risk_limits_set.to_bytes()),
+            Ok(risk_limits_set)
+        );
+
+        let market_set = Tx::MarketSet {
+            market: Market::new(Asset::new("BTC", 8), Asset::new("USD", 2)),
+            meta: TxMeta::new(13, 1_000, TxContext::default()),
+        };
+        assert_eq!(Tx::from_bytes(&market_set.to_bytes()), Ok(market_set));
+
+        let fee_schedule_set = Tx::FeeScheduleSet {
+            schedule: FeeSchedule::new(
+                "FEES",
+                vec![FeeTier {
+                    min_volume: 1_000,
+                    maker_bps: -2,
+                    taker_bps: 5,
+                }],
+            ),
+            meta: TxMeta::new(14, 1_000, TxContext::default()),
+        };
+        assert_eq!(
+            Tx::from_bytes(&fee_schedule_set.to_bytes()),
+            Ok(fee_schedule_set)
+        );
+        let mut bytes = Asset::new("BTC", 8).to_bytes();
+        bytes[7] = 19;
+        assert!(Asset::from_bytes(&bytes).is_err());
+
+        let selector = MassCancel::SignerSide("BOB".to_string(), Side::Buy);
+        assert_eq!(MassCancel::from_bytes(&selector.to_bytes()), Ok(selector));
+    }
+
+    #[test]
+    fn test_codec_rejects_bad_input() {
+        assert!(Tx::from_bytes(&[9]).is_err());
+        assert!(Order::from_bytes(&[1, 2, 3]).is_err());
+        let mut bytes = Side::Buy.to_bytes();
+        bytes.push(0);
+        assert_eq!(
+            Side::from_bytes(&bytes),
+            Err(ApplicationError::Corrupted("trailing bytes".to_string()))
+        );
+    }
+}
@@ -1,5 +1,13 @@
+mod history;
 mod matching;
+mod registry;
+mod stats;
 mod types;
 
+pub use history::{
+    HistoryPage, HistoryQuery, HistoryRecord, OrderHistory, Retention, DEFAULT_HISTORY_CAPACITY,
+};
 pub use matching::MatchingEngine;
+pub use registry::{OrderRegistry, OrderState, OrderStatus};
+pub use stats::{MarketStatistics, Ticker, DEFAULT_TAPE_CAPACITY};
 pub use types::*;
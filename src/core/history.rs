@@ -0,0 +1,443 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    ops::RangeInclusive,
+};
+
+use crate::clock::Timestamp;
+
+use super::{Order, Receipt, Side};
+
+/// Default number of records kept by an [`OrderHistory`]
+pub const DEFAULT_HISTORY_CAPACITY: usize = 100_000;
+
+/// Limits on how much an [`OrderHistory`] keeps around. The oldest records are dropped first.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Retention {
+    /// Max number of records, `None` for no limit
+    pub max_records: Option<usize>,
+    /// Max age of a record relative to the newest one, `None` for no limit
+    pub max_age: Option<Timestamp>,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Retention {
+            max_records: Some(DEFAULT_HISTORY_CAPACITY),
+            max_age: None,
+        }
+    }
+}
+
+/// Everything that happened when an [`Order`] was processed
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct HistoryRecord {
+    /// When the order was processed
+    pub timestamp: Timestamp,
+    /// The order as it was submitted
+    pub order: Order,
+    /// The receipt that was issued for it
+    pub receipt: Receipt,
+}
+
+impl HistoryRecord {
+    /// The order's sequence number
+    pub fn ordinal(&self) -> u64 {
+        self.receipt.ordinal
+    }
+
+    /// Whether the order with sequence number `ordinal` took part, either as the submitted order or as a match
+    pub fn involves(&self, ordinal: u64) -> bool {
+        self.ordinal() == ordinal || self.receipt.matches.iter().any(|m| m.ordinal == ordinal)
+    }
+
+    /// Whether `signer` took part, either by submitting the order or as a match
+    pub fn involves_signer(&self, signer: &str) -> bool {
+        self.order.signer == signer || self.receipt.matches.iter().any(|m| m.signer == signer)
+    }
+}
+
+/// Filters for [`OrderHistory::query`]. Fields that are `None` don't filter; all others have to match.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct HistoryQuery {
+    /// Records the order with this sequence number took part in
+    pub ordinal: Option<u64>,
+    /// Records the signer took part in
+    pub signer: Option<String>,
+    /// Side of the submitted order
+    pub side: Option<Side>,
+    /// Limit price of the submitted order
+    pub prices: Option<RangeInclusive<u64>>,
+    /// Processing time of the submitted order
+    pub timestamps: Option<RangeInclusive<Timestamp>>,
+}
+
+impl HistoryQuery {
+    fn matches(&self, record: &HistoryRecord) -> bool {
+        self.ordinal.map(|o| record.involves(o)).unwrap_or(true)
+            && self
+                .signer
+                .as_ref()
+                .map(|s| record.involves_signer(s))
+                .unwrap_or(true)
+            && self
+                .side
+                .as_ref()
+                .map(|s| &record.order.side == s)
+                .unwrap_or(true)
+            && self
+                .prices
+                .as_ref()
+                .map(|p| p.contains(&record.order.price))
+                .unwrap_or(true)
+            && self
+                .timestamps
+                .as_ref()
+                .map(|t| t.contains(&record.timestamp))
+                .unwrap_or(true)
+    }
+}
+
+/// One page of [`OrderHistory::query`] results
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct HistoryPage<'a> {
+    /// Matching records, ordered by sequence number
+    pub records: Vec<&'a HistoryRecord>,
+    /// Pass this as `after` to fetch the next page, `None` if this was the last one
+    pub next: Option<u64>,
+}
+
+/// A bounded store of [`HistoryRecord`]s with indexes for the common lookups.
+///
+/// Records are kept in ordinal order, so lookups by ordinal are a binary search. Every other index
+/// maps its key to the set of ordinals of matching records.
+#[derive(Clone, Debug, Default)]
+pub struct OrderHistory {
+    retention: Retention,
+
+    /// All records, oldest first
+    records: VecDeque<HistoryRecord>,
+
+    /// Order ordinal -> records the order took part in
+    by_participant: BTreeMap<u64, BTreeSet<u64>>,
+    /// Signer -> records the signer took part in
+    by_signer: BTreeMap<String, BTreeSet<u64>>,
+    /// Side -> records of orders submitted on that side
+    by_side: BTreeMap<Side, BTreeSet<u64>>,
+    /// Limit price -> records of orders at that price
+    by_price: BTreeMap<u64, BTreeSet<u64>>,
+    /// (timestamp, ordinal) of every record
+    by_time: BTreeSet<(Timestamp, u64)>,
+}
+
+impl OrderHistory {
+    /// Creates an empty history that keeps records according to `retention`
+    pub fn new(retention: Retention) -> Self {
+        OrderHistory {
+            retention,
+            ..Default::default()
+        }
+    }
+
+    /// Number of records currently kept
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// No records are kept
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// All records, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &HistoryRecord> {
+        self.records.iter()
+    }
+
+    /// Adds a record and drops whatever falls outside of the retention limits.
+    /// Records are expected in ordinal order.
+    pub fn push(&mut self, record: HistoryRecord) {
+        let ordinal = record.ordinal();
+        self.for_each_key(&record, |history, key| match key {
+            IndexKey::Participant(o) => {
+                history.by_participant.entry(o).or_default().insert(ordinal);
+            }
+            IndexKey::Signer(s) => {
+                history
+                    .by_signer
+                    .entry(s.to_string())
+                    .or_default()
+                    .insert(ordinal);
+            }
+        });
+        self.by_side
+            .entry(record.order.side.clone())
+            .or_default()
+            .insert(ordinal);
+        self.by_price
+            .entry(record.order.price)
+            .or_default()
+            .insert(ordinal);
+        self.by_time.insert((record.timestamp, ordinal));
+
+        let newest = record.timestamp;
+        self.records.push_back(record);
+        self.enforce_retention(newest);
+    }
+
+    /// The record of the order with sequence number `ordinal`
+    pub fn get(&self, ordinal: u64) -> Option<&HistoryRecord> {
+        self.records
+            .binary_search_by_key(&ordinal, |r| r.ordinal())
+            .ok()
+            .map(|i| &self.records[i])
+    }
+
+    /// Every record the order with sequence number `ordinal` took part in, oldest first
+    pub fn involving(&self, ordinal: u64) -> impl Iterator<Item = &HistoryRecord> {
+        self.by_participant
+            .get(&ordinal)
+            .into_iter()
+            .flatten()
+            .filter_map(|o| self.get(*o))
+    }
+
+    /// Returns up to `limit` records matching `query` with an ordinal greater than `after`.
+    pub fn query(&self, query: &HistoryQuery, after: Option<u64>, limit: usize) -> HistoryPage<'_> {
+        let after = after.unwrap_or(0);
+        let mut records: Vec<&HistoryRecord> = vec![];
+        let mut next = None;
+        for ordinal in self.candidates(query).into_iter().filter(|o| *o > after) {
+            if let Some(record) = self.get(ordinal).filter(|r| query.matches(r)) {
+                if records.len() == limit {
+                    next = records.last().map(|r| r.ordinal());
+                    break;
+                }
+                records.push(record);
+            }
+        }
+        HistoryPage { records, next }
+    }
+
+    /// Narrows the search down using the most specific index available
+    fn candidates(&self, query: &HistoryQuery) -> BTreeSet<u64> {
+        if let Some(ordinal) = query.ordinal {
+            self.by_participant
+                .get(&ordinal)
+                .cloned()
+                .unwrap_or_default()
+        } else if let Some(signer) = &query.signer {
+            self.by_signer.get(signer).cloned().unwrap_or_default()
+        } else if let Some(prices) = &query.prices {
+            self.by_price
+                .range(prices.clone())
+                .flat_map(|(_, o)| o.iter().copied())
+                .collect()
+        } else if let Some(timestamps) = &query.timestamps {
+            self.by_time
+                .range((*timestamps.start(), u64::MIN)..=(*timestamps.end(), u64::MAX))
+                .map(|(_, o)| *o)
+                .collect()
+        } else if let Some(side) = &query.side {
+            self.by_side.get(side).cloned().unwrap_or_default()
+        } else {
+            self.records.iter().map(|r| r.ordinal()).collect()
+        }
+    }
+
+    fn enforce_retention(&mut self, newest: Timestamp) {
+        let cutoff = self.retention.max_age.map(|age| newest.saturating_sub(age));
+        loop {
+            let too_many = self
+                .retention
+                .max_records
+                .map(|max| self.records.len() > max)
+                .unwrap_or(false);
+            let too_old = match (self.records.front(), cutoff) {
+                (Some(r), Some(cutoff)) => r.timestamp < cutoff,
+                _ => false,
+            };
+            if !(too_many || too_old) {
+                break;
+            }
+            if let Some(record) = self.records.pop_front() {
+                self.unindex(&record);
+            }
+        }
+    }
+
+    fn unindex(&mut self, record: &HistoryRecord) {
+        let ordinal = record.ordinal();
+        self.for_each_key(record, |history, key| match key {
+            IndexKey::Participant(o) => remove_from(&mut history.by_participant, &o, ordinal),
+            IndexKey::Signer(s) => remove_from(&mut history.by_signer, s, ordinal),
+        });
+        if let Some(set) = self.by_side.get_mut(&record.order.side) {
+            set.remove(&ordinal);
+        }
+        if let Some(set) = self.by_price.get_mut(&record.order.price) {
+            set.remove(&ordinal);
+            if set.is_empty() {
+                self.by_price.remove(&record.order.price);
+            }
+        }
+        self.by_time.remove(&(record.timestamp, ordinal));
+    }
+
+    /// Calls `f` for the participant and signer keys a record is indexed under
+    fn for_each_key<'r, F>(&mut self, record: &'r HistoryRecord, mut f: F)
+    where
+        F: FnMut(&mut Self, IndexKey<'r>),
+    {
+        f(self, IndexKey::Participant(record.ordinal()));
+        f(self, IndexKey::Signer(&record.order.signer));
+        for m in &record.receipt.matches {
+            f(self, IndexKey::Participant(m.ordinal));
+            f(self, IndexKey::Signer(&m.signer));
+        }
+    }
+}
+
+enum IndexKey<'a> {
+    Participant(u64),
+    Signer(&'a str),
+}
+
+fn remove_from<K, Q>(index: &mut BTreeMap<K, BTreeSet<u64>>, key: &Q, ordinal: u64)
+where
+    K: std::borrow::Borrow<Q> + Ord,
+    Q: Ord + ?Sized,
+{
+    if let Some(set) = index.get_mut(key) {
+        set.remove(&ordinal);
+        if set.is_empty() {
+            index.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use crate::core::PartialOrder;
+
+    use super::*;
+
+    fn record(
+        ordinal: u64,
+        timestamp: Timestamp,
+        signer: &str,
+        side: Side,
+        price: u64,
+    ) -> HistoryRecord {
+        HistoryRecord {
+            timestamp,
+            order: Order {
+                price,
+                amount: 1,
+                side,
+                signer: signer.to_string(),
+            },
+            receipt: Receipt {
+                ordinal,
+                matches: vec![],
+                fees: vec![],
+            },
+        }
+    }
+
+    #[test]
+    fn test_OrderHistory_push_enforces_retention() {
+        let mut history = OrderHistory::new(Retention {
+            max_records: Some(2),
+            max_age: None,
+        });
+        for ordinal in 1..=3 {
+            history.push(record(ordinal, ordinal, "ALICE", Side::Buy, 10));
+        }
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.get(1), None);
+        assert!(history.get(3).is_some());
+        assert_eq!(history.by_signer["ALICE"].len(), 2);
+
+        let mut history = OrderHistory::new(Retention {
+            max_records: None,
+            max_age: Some(10),
+        });
+        history.push(record(1, 0, "ALICE", Side::Buy, 10));
+        history.push(record(2, 5, "BOB", Side::Buy, 10));
+        history.push(record(3, 12, "ALICE", Side::Buy, 10));
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.get(1), None);
+        assert!(history.get(2).is_some());
+    }
+
+    #[test]
+    fn test_OrderHistory_involving_finds_matches() {
+        let mut history = OrderHistory::default();
+        history.push(record(1, 1, "ALICE", Side::Sell, 10));
+        let mut bob = record(2, 2, "BOB", Side::Buy, 10);
+        bob.receipt.matches.push(PartialOrder {
+            price: 10,
+            amount: 1,
+            remaining: 0,
+            side: Side::Sell,
+            signer: "ALICE".to_string(),
+            ordinal: 1,
+        });
+        history.push(bob);
+        history.push(record(3, 3, "CHARLIE", Side::Buy, 9));
+
+        let ordinals: Vec<u64> = history.involving(1).map(|r| r.ordinal()).collect();
+        assert_eq!(ordinals, vec![1, 2]);
+
+        let alice = HistoryQuery {
+            signer: Some("ALICE".to_string()),
+            ..Default::default()
+        };
+        let page = history.query(&alice, None, 10);
+        assert_eq!(page.records.len(), 2);
+        assert_eq!(page.next, None);
+    }
+
+    #[test]
+    fn test_OrderHistory_query_filters_and_paginates() {
+        let mut history = OrderHistory::default();
+        for ordinal in 1..=10 {
+            let side = if ordinal % 2 == 0 {
+                Side::Buy
+            } else {
+                Side::Sell
+            };
+            history.push(record(ordinal, ordinal * 100, "ALICE", side, ordinal));
+        }
+
+        let buys_in_range = HistoryQuery {
+            side: Some(Side::Buy),
+            prices: Some(3..=9),
+            ..Default::default()
+        };
+        let page = history.query(&buys_in_range, None, 2);
+        let ordinals: Vec<u64> = page.records.iter().map(|r| r.ordinal()).collect();
+        assert_eq!(ordinals, vec![4, 6]);
+        assert_eq!(page.next, Some(6));
+
+        let page = history.query(&buys_in_range, page.next, 2);
+        let ordinals: Vec<u64> = page.records.iter().map(|r| r.ordinal()).collect();
+        assert_eq!(ordinals, vec![8]);
+        assert_eq!(page.next, None);
+
+        let by_time = HistoryQuery {
+            timestamps: Some(250..=500),
+            ..Default::default()
+        };
+        let ordinals: Vec<u64> = history
+            .query(&by_time, None, 10)
+            .records
+            .iter()
+            .map(|r| r.ordinal())
+            .collect();
+        assert_eq!(ordinals, vec![3, 4, 5]);
+    }
+}
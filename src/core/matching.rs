@@ -1,16 +1,19 @@
-use std::{
-    collections::{BTreeMap, BinaryHeap},
-    thread::current,
-};
+use std::collections::{BTreeMap, BinaryHeap};
 
 use crate::{
-    core::{Order, Receipt, Side},
+    clock::{Clock, SystemClock, Timestamp},
+    codec::Writer,
+    core::{
+        CancelReceipt, HistoryRecord, MarketStatistics, MassCancel, Order, OrderHistory,
+        OrderRegistry, OrderState, OrderStatus, Receipt, Side, Trade,
+    },
     errors::ApplicationError,
+    state_hash::StateHash,
 };
 
 use super::PartialOrder;
 
-#[derive(Default, Debug)]
+#[derive(Debug)]
 pub struct MatchingEngine {
     /// The last sequence number
     pub ordinal: u64,
@@ -20,42 +23,125 @@ pub struct MatchingEngine {
     /// The "Ask" or "Sell" side of the order book. Ordered by ordinal number.
     pub asks: BTreeMap<u64, BinaryHeap<PartialOrder>>,
 
-    /// Previous matches for record keeping
-    pub history: Vec<Receipt>,
+    /// Previous orders and their matches for record keeping
+    pub history: OrderHistory,
+
+    /// Last price, volume and a tape of recent trades
+    pub statistics: MarketStatistics,
+
+    /// Status of every order, open or not
+    pub orders: OrderRegistry,
+
+    /// Timestamps the trades
+    clock: Box<dyn Clock>,
+
+    /// Digest of every resting order, kept up to date with every change to the book
+    book_hash: StateHash,
+}
+
+impl Default for MatchingEngine {
+    fn default() -> Self {
+        MatchingEngine::new()
+    }
 }
 
 impl MatchingEngine {
     /// Creates a new [`MatchingEngine`] with an ordinal of 0 and empty books
     pub fn new() -> Self {
+        MatchingEngine::with_clock(SystemClock)
+    }
+
+    /// Creates a new [`MatchingEngine`] that uses the provided [`Clock`] to timestamp trades
+    pub fn with_clock(clock: impl Clock + 'static) -> Self {
         MatchingEngine {
             ordinal: 0,
             bids: BTreeMap::new(),
             asks: BTreeMap::new(),
-            history: Vec::new(),
+            history: OrderHistory::default(),
+            statistics: MarketStatistics::default(),
+            orders: OrderRegistry::default(),
+            clock: Box::new(clock),
+            book_hash: StateHash::default(),
         }
     }
 
+    /// Picks up from a snapshot: the last `ordinal`, the resting orders of both sides of the
+    /// `book` and the status of every `open` order. History and statistics start out empty.
+    pub fn restore(
+        ordinal: u64,
+        book: impl IntoIterator<Item = PartialOrder>,
+        open: impl IntoIterator<Item = OrderStatus>,
+        clock: impl Clock + 'static,
+    ) -> Self {
+        let mut engine = MatchingEngine::with_clock(clock);
+        engine.ordinal = ordinal;
+        for order in book {
+            engine
+                .book_hash
+                .add(&resting_entry(&order, order.remaining));
+            let side = match order.side {
+                Side::Buy => &mut engine.bids,
+                Side::Sell => &mut engine.asks,
+            };
+            side.entry(order.price).or_default().push(order);
+        }
+        for status in open {
+            engine.orders.restore(status);
+        }
+        engine
+    }
+
+    /// Order-independent digest of every resting order on both sides of the book. Covers
+    /// ordinal, signer, side, price and remaining units of each order.
+    pub fn book_hash(&self) -> StateHash {
+        self.book_hash
+    }
+
+    /// Replaces the [`Clock`] that timestamps trades from now on, e.g. after a replay
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) {
+        self.clock = Box::new(clock);
+    }
+
     /// Processes an [`Order`] and returns a [`Receipt`]
     /// This includes matching the order to whatever is in the current books and adding the remainder (if any) to the book for future matching.
     pub fn process(&mut self, order: Order) -> Result<Receipt, ApplicationError> {
+        let timestamp = self.clock.now();
+        self.process_at(order, timestamp)
+    }
+
+    /// Like [`MatchingEngine::process`], but the trades happen at `timestamp` instead of now,
+    /// e.g. to replay them or to record the same time elsewhere
+    /// # Errors
+    /// See [`MatchingEngine::process`]
+    pub fn process_at(
+        &mut self,
+        order: Order,
+        timestamp: Timestamp,
+    ) -> Result<Receipt, ApplicationError> {
         // Increment the ordinal number for this order
         self.ordinal += 1;
         let ordinal = self.ordinal;
 
         let original_amount = order.amount;
+        let submitted = order.clone();
+        let side = order.side.clone();
         let mut partial = order.into_partial_order(ordinal, original_amount);
 
         // Orders are matched to the opposite side
         let receipt = match &partial.side {
             Side::Buy => {
-                // Implement this side of the matching!
+                // Fetch all orders in the expected price range, cheapest first
                 let orderbook_entry = self.asks.range_mut(u64::MIN..=partial.price);
                 let receipt = MatchingEngine::match_order(&partial, orderbook_entry, ordinal)?;
                 let matched_amount: u64 = receipt.matches.iter().map(|m| m.amount).sum();
+
+                // The order wasn't fully matched
                 if matched_amount < original_amount {
                     partial.amount = original_amount - matched_amount;
                     partial.remaining = original_amount - matched_amount;
                     let price = partial.price;
+                    self.book_hash
+                        .add(&resting_entry(&partial, partial.remaining));
                     let bids = self.bids.entry(price).or_insert(vec![].into());
                     bids.push(partial);
                 }
@@ -63,8 +149,8 @@ impl MatchingEngine {
                 receipt
             }
             Side::Sell => {
-                // Fetch all orders in the expected price range from this side of the orderbook
-                let orderbook_entry = self.bids.range_mut(partial.price..=u64::MAX);
+                // Fetch all orders in the expected price range, highest bid first
+                let orderbook_entry = self.bids.range_mut(partial.price..=u64::MAX).rev();
 
                 let receipt = MatchingEngine::match_order(&partial, orderbook_entry, ordinal)?;
                 let matched_amount: u64 = receipt.matches.iter().map(|m| m.amount).sum();
@@ -72,7 +158,10 @@ impl MatchingEngine {
                 // The order wasn't fully matched
                 if matched_amount < original_amount {
                     partial.amount = original_amount - matched_amount;
+                    partial.remaining = original_amount - matched_amount;
                     let price = partial.price;
+                    self.book_hash
+                        .add(&resting_entry(&partial, partial.remaining));
                     let asks = self.asks.entry(price).or_insert(vec![].into());
                     asks.push(partial);
                 }
@@ -84,11 +173,162 @@ impl MatchingEngine {
         self.asks.retain(|_, orders| !orders.is_empty());
         self.bids.retain(|_, orders| !orders.is_empty());
 
+        // Every match is a print on the tape and a fill for both orders
+        self.orders.insert(ordinal, &submitted);
+        for m in &receipt.matches {
+            self.book_hash
+                .remove(&resting_entry(m, m.remaining + m.amount));
+            if m.remaining > 0 {
+                self.book_hash.add(&resting_entry(m, m.remaining));
+            }
+            self.orders.fill(m.ordinal, m.amount, m.price);
+            self.orders.fill(ordinal, m.amount, m.price);
+            self.statistics.record(Trade {
+                timestamp,
+                price: m.price,
+                amount: m.amount,
+                taker_side: side.clone(),
+                maker_ordinal: m.ordinal,
+                taker_ordinal: ordinal,
+            });
+        }
+
         // Keep a log of matches
-        self.history.push(receipt.clone());
+        self.history.push(HistoryRecord {
+            timestamp,
+            order: submitted,
+            receipt: receipt.clone(),
+        });
         Ok(receipt)
     }
 
+    /// The matches [`MatchingEngine::process`] would make for `order` right now, without changing
+    /// the book
+    /// # Errors
+    /// See [`MatchingEngine::process`]
+    pub fn preview(&self, order: &Order) -> Result<Vec<PartialOrder>, ApplicationError> {
+        let partial = order
+            .clone()
+            .into_partial_order(self.ordinal + 1, order.amount);
+        // Match against a copy of the price levels in range
+        let mut levels: Vec<(u64, BinaryHeap<PartialOrder>)> = match &partial.side {
+            Side::Buy => self
+                .asks
+                .range(u64::MIN..=partial.price)
+                .map(|(price, orders)| (*price, orders.clone()))
+                .collect(),
+            Side::Sell => self
+                .bids
+                .range(partial.price..=u64::MAX)
+                .rev()
+                .map(|(price, orders)| (*price, orders.clone()))
+                .collect(),
+        };
+        let orderbook_entry = levels.iter_mut().map(|(price, orders)| (&*price, orders));
+        let receipt = MatchingEngine::match_order(&partial, orderbook_entry, partial.ordinal)?;
+        Ok(receipt.matches)
+    }
+
+    /// Records an [`Order`] that failed validation before reaching the book and returns the
+    /// ordinal it was registered under.
+    pub fn reject(&mut self, order: Order) -> u64 {
+        self.ordinal += 1;
+        self.orders.reject(self.ordinal, &order);
+        self.ordinal
+    }
+
+    /// Removes an open order from the book on request
+    /// # Errors
+    /// There is no open order with that ordinal
+    pub fn cancel(&mut self, ordinal: u64) -> Result<PartialOrder, ApplicationError> {
+        self.remove(ordinal, OrderState::Cancelled)
+    }
+
+    /// Removes an open order from the book because it ran out of time
+    /// # Errors
+    /// There is no open order with that ordinal
+    pub fn expire(&mut self, ordinal: u64) -> Result<PartialOrder, ApplicationError> {
+        self.remove(ordinal, OrderState::Expired)
+    }
+
+    /// Removes every open order selected by `selector` from the book in one go
+    pub fn mass_cancel(&mut self, selector: &MassCancel) -> CancelReceipt {
+        let mut cancelled = vec![];
+        for book in [&mut self.bids, &mut self.asks] {
+            for orders in book.values_mut() {
+                orders.retain(|o| {
+                    let selected = selector.matches(o);
+                    if selected {
+                        cancelled.push(o.clone());
+                    }
+                    !selected
+                });
+            }
+            book.retain(|_, orders| !orders.is_empty());
+        }
+        cancelled.sort_by_key(|o| o.ordinal);
+        for o in &cancelled {
+            self.book_hash.remove(&resting_entry(o, o.remaining));
+            self.orders.close(o.ordinal, OrderState::Cancelled);
+        }
+        CancelReceipt { cancelled }
+    }
+
+    /// Cancels whichever of the orders in `ordinals` are still open and skips the rest
+    pub fn cancel_many(&mut self, ordinals: impl IntoIterator<Item = u64>) -> CancelReceipt {
+        let cancelled = ordinals
+            .into_iter()
+            .filter_map(|ordinal| self.cancel(ordinal).ok())
+            .collect();
+        CancelReceipt { cancelled }
+    }
+
+    /// The status of the order with sequence number `ordinal`
+    pub fn order_status(&self, ordinal: u64) -> Option<&OrderStatus> {
+        self.orders.status(ordinal)
+    }
+
+    /// All open orders of `signer`, oldest first
+    pub fn open_orders(&self, signer: &str) -> Vec<&OrderStatus> {
+        self.orders.open_orders(signer)
+    }
+
+    /// Takes an open order out of the book and moves it into the final `state`
+    fn remove(
+        &mut self,
+        ordinal: u64,
+        state: OrderState,
+    ) -> Result<PartialOrder, ApplicationError> {
+        let (side, price) = self
+            .orders
+            .status(ordinal)
+            .filter(|s| s.state.is_open())
+            .map(|s| (s.side.clone(), s.price))
+            .ok_or(ApplicationError::OrderNotFound(ordinal))?;
+        let book = match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+
+        let mut removed = None;
+        if let Some(orders) = book.get_mut(&price) {
+            orders.retain(|o| {
+                if o.ordinal == ordinal {
+                    removed = Some(o.clone());
+                }
+                o.ordinal != ordinal
+            });
+            if orders.is_empty() {
+                book.remove(&price);
+            }
+        }
+        let removed = removed.ok_or(ApplicationError::OrderNotFound(ordinal))?;
+        self.book_hash
+            .remove(&resting_entry(&removed, removed.remaining));
+        self.orders.close(ordinal, state);
+        Ok(removed)
+    }
+
     /// Matches an order to the provided order book side.
     /// # Parameters
     /// - `order`: the order to match to the book
@@ -116,41 +356,56 @@ impl MatchingEngine {
                     //   a. is there anything left from the match? split the Order into two and put one back into the orderbook entry
                     //   b. if nothing is left, add the full order to your matches and continue from 1
                     let mut self_order: BinaryHeap<PartialOrder> = BinaryHeap::new();
-                    'inner: while remaining_amount > 0 {
+                    while remaining_amount > 0 {
                         match orderbook_entry.pop() {
-                            Some(current) => {
+                            Some(mut current) => {
                                 if current.signer != order.signer {
                                     let matched_amount =
                                         std::cmp::min(remaining_amount, current.remaining);
-                                    matches.push(PartialOrder {
-                                        price: current.price,
-                                        amount: current.amount,
-                                        side: current.side.clone(),
-                                        signer: current.signer.clone(),
-                                        ordinal: current.ordinal,
-                                        remaining: current.remaining - matched_amount,
-                                    });
-
-                                    remaining_amount -= current.remaining;
+                                    let price = current.price;
+                                    matches.push(PartialOrder::take_from(
+                                        &mut current,
+                                        matched_amount,
+                                        price,
+                                    ));
+                                    remaining_amount -= matched_amount;
+
+                                    // put back what's left of a partially matched order
+                                    if current.remaining > 0 {
+                                        orderbook_entry.push(current);
+                                    }
                                 } else {
                                     self_order.push(current);
                                 }
                             }
-                            None => {
-                                orderbook_entry.append(&mut self_order);
-                                break 'inner;
-                            }
+                            None => break,
                         }
                     }
+                    orderbook_entry.append(&mut self_order);
                 }
                 // Nothing left to match with
                 None => break 'outer,
             }
         }
-        Ok(Receipt { ordinal, matches })
+        Ok(Receipt {
+            ordinal,
+            matches,
+            fees: vec![],
+        })
     }
 }
 
+/// What the [`StateHash`] covers of a resting order with `remaining` units
+fn resting_entry(order: &PartialOrder, remaining: u64) -> Vec<u8> {
+    let mut w = Writer::new();
+    w.put_u64(order.ordinal);
+    w.put_str(&order.signer);
+    w.put(&order.side);
+    w.put_u64(order.price);
+    w.put_u64(remaining);
+    w.into_bytes()
+}
+
 #[cfg(test)]
 mod tests {
     // reduce the warnings for naming tests
@@ -158,6 +413,8 @@ mod tests {
 
     use std::assert_eq;
 
+    use crate::{clock::ManualClock, core::Retention};
+
     use super::*;
 
     #[test]
@@ -430,4 +687,343 @@ mod tests {
         assert_eq!(receipt.ordinal, matching_engine.ordinal);
         assert_eq!(matching_engine.ordinal, 3);
     }
+
+    #[test]
+    fn test_MatchingEngine_process_partially_fills_resting_order() {
+        let mut matching_engine = MatchingEngine::new();
+        matching_engine
+            .process(Order {
+                price: 10,
+                amount: 3,
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+            })
+            .unwrap();
+
+        let bob_receipt = matching_engine
+            .process(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Buy,
+                signer: "BOB".to_string(),
+            })
+            .unwrap();
+        assert_eq!(
+            bob_receipt.matches,
+            vec![PartialOrder {
+                price: 10,
+                amount: 1,
+                remaining: 2,
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+                ordinal: 1
+            }]
+        );
+
+        // The rest of Alice's order stays in the book
+        let alice_ask = matching_engine.asks.get(&10).unwrap().peek().unwrap();
+        assert_eq!(alice_ask.remaining, 2);
+        assert!(matching_engine.bids.is_empty());
+    }
+
+    #[test]
+    fn test_MatchingEngine_process_sell_matches_highest_bid_first() {
+        let mut matching_engine = MatchingEngine::new();
+        for (price, signer) in [(10, "ALICE"), (12, "CHARLIE")] {
+            matching_engine
+                .process(Order {
+                    price,
+                    amount: 1,
+                    side: Side::Buy,
+                    signer: signer.to_string(),
+                })
+                .unwrap();
+        }
+
+        let bob_receipt = matching_engine
+            .process(Order {
+                price: 9,
+                amount: 1,
+                side: Side::Sell,
+                signer: "BOB".to_string(),
+            })
+            .unwrap();
+        assert_eq!(bob_receipt.matches[0].signer, "CHARLIE");
+        assert_eq!(bob_receipt.matches[0].price, 12);
+    }
+
+    #[test]
+    fn test_MatchingEngine_process_updates_statistics() {
+        let clock = ManualClock::new(1_000);
+        let mut matching_engine = MatchingEngine::with_clock(clock.clone());
+        matching_engine
+            .process(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+            })
+            .unwrap();
+        matching_engine
+            .process(Order {
+                price: 12,
+                amount: 1,
+                side: Side::Sell,
+                signer: "CHARLIE".to_string(),
+            })
+            .unwrap();
+        assert_eq!(matching_engine.statistics.last_trade(), None);
+
+        clock.advance(500);
+        matching_engine
+            .process(Order {
+                price: 12,
+                amount: 2,
+                side: Side::Buy,
+                signer: "BOB".to_string(),
+            })
+            .unwrap();
+
+        assert_eq!(
+            matching_engine.statistics.last_trade(),
+            Some(&Trade {
+                timestamp: 1_500,
+                price: 12,
+                amount: 1,
+                taker_side: Side::Buy,
+                maker_ordinal: 2,
+                taker_ordinal: 3,
+            })
+        );
+        let ticker = matching_engine.statistics.ticker(clock.now());
+        assert_eq!(ticker.vwap, Some(11));
+        assert_eq!(ticker.volume_24h, 2);
+        assert_eq!(ticker.high_24h, Some(12));
+        assert_eq!(ticker.low_24h, Some(10));
+        assert_eq!(
+            matching_engine
+                .statistics
+                .trades_between(1_500, 1_501)
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_MatchingEngine_process_keeps_history() {
+        let mut matching_engine = MatchingEngine::new();
+        matching_engine.history = OrderHistory::new(Retention {
+            max_records: Some(2),
+            max_age: None,
+        });
+        for (amount, signer) in [(2, "ALICE"), (1, "CHARLIE")] {
+            matching_engine
+                .process(Order {
+                    price: 10,
+                    amount,
+                    side: Side::Sell,
+                    signer: signer.to_string(),
+                })
+                .unwrap();
+        }
+        matching_engine
+            .process(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Buy,
+                signer: "BOB".to_string(),
+            })
+            .unwrap();
+
+        assert_eq!(matching_engine.history.len(), 2);
+        assert_eq!(matching_engine.history.get(1), None);
+
+        // Alice's order record is gone, but the match is still on file
+        let alice: Vec<u64> = matching_engine
+            .history
+            .involving(1)
+            .map(|r| r.ordinal())
+            .collect();
+        assert_eq!(alice, vec![3]);
+        let bob = matching_engine.history.get(3).unwrap();
+        assert_eq!(bob.order.signer, "BOB");
+        assert_eq!(bob.receipt.matches[0].signer, "ALICE");
+    }
+
+    #[test]
+    fn test_MatchingEngine_order_status_and_open_orders() {
+        let mut matching_engine = MatchingEngine::new();
+        for (price, amount) in [(10, 3), (11, 1)] {
+            matching_engine
+                .process(Order {
+                    price,
+                    amount,
+                    side: Side::Sell,
+                    signer: "ALICE".to_string(),
+                })
+                .unwrap();
+        }
+        matching_engine
+            .process(Order {
+                price: 10,
+                amount: 2,
+                side: Side::Buy,
+                signer: "BOB".to_string(),
+            })
+            .unwrap();
+
+        let alice = matching_engine.order_status(1).unwrap();
+        assert_eq!(alice.state, OrderState::PartiallyFilled);
+        assert_eq!(alice.filled, 2);
+        assert_eq!(alice.average_fill_price(), Some(10));
+        assert_eq!(
+            matching_engine.order_status(3).unwrap().state,
+            OrderState::Filled
+        );
+        let open: Vec<u64> = matching_engine
+            .open_orders("ALICE")
+            .iter()
+            .map(|s| s.ordinal)
+            .collect();
+        assert_eq!(open, vec![1, 2]);
+        assert!(matching_engine.open_orders("BOB").is_empty());
+    }
+
+    #[test]
+    fn test_MatchingEngine_cancel_removes_order() {
+        let mut matching_engine = MatchingEngine::new();
+        for signer in ["ALICE", "CHARLIE"] {
+            matching_engine
+                .process(Order {
+                    price: 10,
+                    amount: 1,
+                    side: Side::Buy,
+                    signer: signer.to_string(),
+                })
+                .unwrap();
+        }
+
+        let cancelled = matching_engine.cancel(1).unwrap();
+        assert_eq!(cancelled.signer, "ALICE");
+        assert_eq!(matching_engine.bids.get(&10).unwrap().len(), 1);
+        assert_eq!(
+            matching_engine.order_status(1).unwrap().state,
+            OrderState::Cancelled
+        );
+        assert_eq!(
+            matching_engine.cancel(1),
+            Err(ApplicationError::OrderNotFound(1))
+        );
+
+        matching_engine.expire(2).unwrap();
+        assert!(matching_engine.bids.is_empty());
+        assert_eq!(
+            matching_engine.order_status(2).unwrap().state,
+            OrderState::Expired
+        );
+
+        let rejected = matching_engine.reject(Order {
+            price: 10,
+            amount: 1,
+            side: Side::Buy,
+            signer: "BOB".to_string(),
+        });
+        assert_eq!(rejected, 3);
+        assert_eq!(
+            matching_engine.order_status(3).unwrap().state,
+            OrderState::Rejected
+        );
+    }
+
+    #[test]
+    fn test_MatchingEngine_mass_cancel() {
+        let mut matching_engine = MatchingEngine::new();
+        for (price, side, signer) in [
+            (9, Side::Buy, "ALICE"),
+            (10, Side::Buy, "BOB"),
+            (12, Side::Sell, "ALICE"),
+            (13, Side::Sell, "BOB"),
+        ] {
+            matching_engine
+                .process(Order {
+                    price,
+                    amount: 1,
+                    side,
+                    signer: signer.to_string(),
+                })
+                .unwrap();
+        }
+
+        let receipt =
+            matching_engine.mass_cancel(&MassCancel::SignerSide("ALICE".to_string(), Side::Sell));
+        assert_eq!(receipt.cancelled.len(), 1);
+        assert_eq!(receipt.cancelled[0].ordinal, 3);
+        assert_eq!(
+            matching_engine.order_status(3).unwrap().state,
+            OrderState::Cancelled
+        );
+
+        let receipt = matching_engine.mass_cancel(&MassCancel::Above(9));
+        let ordinals: Vec<u64> = receipt.cancelled.iter().map(|o| o.ordinal).collect();
+        assert_eq!(ordinals, vec![2, 4]);
+        assert_eq!(receipt.amount(), 2);
+        assert!(matching_engine.asks.is_empty());
+
+        let receipt = matching_engine.cancel_many([1, 2]);
+        assert_eq!(receipt.cancelled.len(), 1);
+        assert!(matching_engine.bids.is_empty());
+        assert_eq!(
+            matching_engine.mass_cancel(&MassCancel::Market),
+            CancelReceipt::default()
+        );
+    }
+
+    fn recomputed_book_hash(engine: &MatchingEngine) -> StateHash {
+        let entries: Vec<Vec<u8>> = engine
+            .bids
+            .values()
+            .chain(engine.asks.values())
+            .flatten()
+            .map(|o| resting_entry(o, o.remaining))
+            .collect();
+        StateHash::of(entries.iter().map(Vec::as_slice))
+    }
+
+    #[test]
+    fn test_MatchingEngine_book_hash_tracks_every_change() {
+        let mut matching_engine = MatchingEngine::new();
+        assert_eq!(matching_engine.book_hash(), StateHash::default());
+
+        for (signer, side, price, amount) in [
+            ("ALICE", Side::Sell, 10, 3),
+            ("ALICE", Side::Sell, 11, 2),
+            ("BOB", Side::Buy, 9, 1),
+            // partially fills ALICE@10
+            ("BOB", Side::Buy, 10, 1),
+            // skips its own order, fills the rest and rests the remainder
+            ("ALICE", Side::Buy, 11, 1),
+            ("CAROL", Side::Buy, 12, 5),
+        ] {
+            matching_engine
+                .process(Order {
+                    price,
+                    amount,
+                    side,
+                    signer: signer.to_string(),
+                })
+                .unwrap();
+            assert_eq!(
+                matching_engine.book_hash(),
+                recomputed_book_hash(&matching_engine)
+            );
+        }
+
+        matching_engine.cancel(3).unwrap();
+        assert_eq!(
+            matching_engine.book_hash(),
+            recomputed_book_hash(&matching_engine)
+        );
+        matching_engine.mass_cancel(&MassCancel::Market);
+        assert_eq!(matching_engine.book_hash(), StateHash::default());
+    }
 }
@@ -0,0 +1,256 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use super::{Order, Side, DEFAULT_HISTORY_CAPACITY};
+
+/// Lifecycle of an order. `New` and `PartiallyFilled` orders are open, everything else is final.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OrderState {
+    /// Accepted and resting in the book without any fills
+    New,
+    /// Some, but not all, units have been traded
+    PartiallyFilled,
+    /// All units have been traded
+    Filled,
+    /// Removed from the book on request
+    Cancelled,
+    /// Removed from the book because it ran out of time
+    Expired,
+    /// Never made it into the book
+    Rejected,
+}
+
+impl OrderState {
+    /// Whether the order can still trade
+    pub fn is_open(&self) -> bool {
+        matches!(self, OrderState::New | OrderState::PartiallyFilled)
+    }
+}
+
+/// The current state of an order, including everything that has been filled so far
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct OrderStatus {
+    /// Sequence number
+    pub ordinal: u64,
+    /// Signer of the order
+    pub signer: String,
+    /// Buy or sell side of the book
+    pub side: Side,
+    /// Limit price
+    pub price: u64,
+    /// Number of units ordered
+    pub amount: u64,
+    /// Number of units traded so far
+    pub filled: u64,
+    /// Sum of price * units over all fills
+    pub filled_notional: u128,
+    /// Where the order is in its lifecycle
+    pub state: OrderState,
+}
+
+impl OrderStatus {
+    /// Number of units that have not been traded (yet)
+    pub fn remaining(&self) -> u64 {
+        self.amount - self.filled
+    }
+
+    /// Average price over all fills, `None` if nothing was filled
+    pub fn average_fill_price(&self) -> Option<u64> {
+        self.filled_notional
+            .checked_div(self.filled as u128)
+            .map(|p| p as u64)
+    }
+}
+
+/// Tracks the [`OrderStatus`] of every order a [`super::MatchingEngine`] has seen, with an index
+/// of open orders by signer. Orders in a final state are forgotten oldest first once there are
+/// more than `capacity` of them.
+#[derive(Clone, Debug)]
+pub struct OrderRegistry {
+    orders: BTreeMap<u64, OrderStatus>,
+    /// Signer -> ordinals of their open orders
+    open_by_signer: BTreeMap<String, BTreeSet<u64>>,
+    /// Ordinals of orders in a final state, oldest first
+    closed: VecDeque<u64>,
+    capacity: usize,
+}
+
+impl Default for OrderRegistry {
+    fn default() -> Self {
+        OrderRegistry::new(DEFAULT_HISTORY_CAPACITY)
+    }
+}
+
+impl OrderRegistry {
+    /// Creates an empty registry that remembers up to `capacity` closed orders
+    pub fn new(capacity: usize) -> Self {
+        OrderRegistry {
+            orders: BTreeMap::new(),
+            open_by_signer: BTreeMap::new(),
+            closed: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Registers a new order as `New` with nothing filled yet
+    pub fn insert(&mut self, ordinal: u64, order: &Order) {
+        self.open_by_signer
+            .entry(order.signer.clone())
+            .or_default()
+            .insert(ordinal);
+        self.orders.insert(
+            ordinal,
+            OrderStatus {
+                ordinal,
+                signer: order.signer.clone(),
+                side: order.side.clone(),
+                price: order.price,
+                amount: order.amount,
+                filled: 0,
+                filled_notional: 0,
+                state: OrderState::New,
+            },
+        );
+    }
+
+    /// Registers an order with a known status, e.g. one restored from a snapshot
+    pub fn restore(&mut self, status: OrderStatus) {
+        if status.state.is_open() {
+            self.open_by_signer
+                .entry(status.signer.clone())
+                .or_default()
+                .insert(status.ordinal);
+        } else {
+            self.closed.push_back(status.ordinal);
+        }
+        self.orders.insert(status.ordinal, status);
+    }
+
+    /// Registers an order that never made it into the book
+    pub fn reject(&mut self, ordinal: u64, order: &Order) {
+        self.insert(ordinal, order);
+        self.close(ordinal, OrderState::Rejected);
+    }
+
+    /// Adds a fill of `amount` units at `price` to an open order
+    pub fn fill(&mut self, ordinal: u64, amount: u64, price: u64) {
+        let filled = match self.orders.get_mut(&ordinal) {
+            Some(status) if status.state.is_open() => {
+                status.filled += amount;
+                status.filled_notional += amount as u128 * price as u128;
+                status.state = OrderState::PartiallyFilled;
+                status.remaining() == 0
+            }
+            _ => false,
+        };
+        if filled {
+            self.close(ordinal, OrderState::Filled);
+        }
+    }
+
+    /// Moves an open order into a final `state`
+    pub fn close(&mut self, ordinal: u64, state: OrderState) {
+        let Some(status) = self.orders.get_mut(&ordinal) else {
+            return;
+        };
+        if !status.state.is_open() {
+            return;
+        }
+        status.state = state;
+        let signer = status.signer.clone();
+        if let Some(open) = self.open_by_signer.get_mut(&signer) {
+            open.remove(&ordinal);
+            if open.is_empty() {
+                self.open_by_signer.remove(&signer);
+            }
+        }
+
+        self.closed.push_back(ordinal);
+        while self.closed.len() > self.capacity {
+            if let Some(oldest) = self.closed.pop_front() {
+                self.orders.remove(&oldest);
+            }
+        }
+    }
+
+    /// The status of the order with sequence number `ordinal`
+    pub fn status(&self, ordinal: u64) -> Option<&OrderStatus> {
+        self.orders.get(&ordinal)
+    }
+
+    /// All open orders, oldest first
+    pub fn open(&self) -> Vec<&OrderStatus> {
+        let mut open: Vec<&OrderStatus> = self
+            .open_by_signer
+            .values()
+            .flatten()
+            .filter_map(|o| self.orders.get(o))
+            .collect();
+        open.sort_by_key(|s| s.ordinal);
+        open
+    }
+
+    /// All open orders of `signer`, oldest first
+    pub fn open_orders(&self, signer: &str) -> Vec<&OrderStatus> {
+        self.open_by_signer
+            .get(signer)
+            .into_iter()
+            .flatten()
+            .filter_map(|o| self.orders.get(o))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    fn order(signer: &str, amount: u64) -> Order {
+        Order {
+            price: 10,
+            amount,
+            side: Side::Buy,
+            signer: signer.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_OrderRegistry_fill_moves_through_states() {
+        let mut registry = OrderRegistry::default();
+        registry.insert(1, &order("ALICE", 3));
+        assert_eq!(registry.status(1).unwrap().state, OrderState::New);
+
+        registry.fill(1, 1, 10);
+        registry.fill(1, 1, 13);
+        let status = registry.status(1).unwrap();
+        assert_eq!(status.state, OrderState::PartiallyFilled);
+        assert_eq!(status.filled, 2);
+        assert_eq!(status.remaining(), 1);
+        assert_eq!(status.average_fill_price(), Some(11));
+        assert_eq!(registry.open_orders("ALICE").len(), 1);
+
+        registry.fill(1, 1, 10);
+        assert_eq!(registry.status(1).unwrap().state, OrderState::Filled);
+        assert!(registry.open_orders("ALICE").is_empty());
+
+        // final states are final
+        registry.close(1, OrderState::Cancelled);
+        assert_eq!(registry.status(1).unwrap().state, OrderState::Filled);
+    }
+
+    #[test]
+    fn test_OrderRegistry_close_forgets_oldest_closed_orders() {
+        let mut registry = OrderRegistry::new(1);
+        registry.insert(1, &order("ALICE", 1));
+        registry.reject(2, &order("ALICE", 1));
+        registry.insert(3, &order("BOB", 1));
+        assert_eq!(registry.status(2).unwrap().state, OrderState::Rejected);
+
+        registry.close(1, OrderState::Cancelled);
+        assert_eq!(registry.status(2), None);
+        assert_eq!(registry.status(1).unwrap().state, OrderState::Cancelled);
+        assert_eq!(registry.open_orders("BOB")[0].ordinal, 3);
+    }
+}
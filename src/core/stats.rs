@@ -0,0 +1,287 @@
+use std::collections::VecDeque;
+
+use crate::clock::{Timestamp, DAY};
+
+use super::Trade;
+
+/// Default number of prints kept on the tape
+pub const DEFAULT_TAPE_CAPACITY: usize = 1_000;
+
+/// A snapshot of a market's statistics at a given point in time.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Ticker {
+    /// Price of the most recent trade
+    pub last_price: Option<u64>,
+    /// Size of the most recent trade
+    pub last_amount: Option<u64>,
+    /// Volume weighted average price since the session started
+    pub vwap: Option<u64>,
+    /// Highest price over the last 24 hours
+    pub high_24h: Option<u64>,
+    /// Lowest price over the last 24 hours
+    pub low_24h: Option<u64>,
+    /// Price of the first trade in the last 24 hours
+    pub open_24h: Option<u64>,
+    /// Units traded over the last 24 hours
+    pub volume_24h: u128,
+    /// Difference between the last price and the 24 hour open
+    pub change_24h: i128,
+}
+
+/// Keeps track of last price, session VWAP, rolling 24h statistics and a bounded tape of recent
+/// trades for a single market.
+///
+/// The rolling window uses two monotonic queues (one for the highs, one for the lows) so that
+/// extremes are available without rescanning every trade in the window.
+#[derive(Debug, Clone)]
+pub struct MarketStatistics {
+    /// The most recent trade
+    last: Option<Trade>,
+
+    /// Sum of price * amount since the session started
+    session_notional: u128,
+    /// Sum of amounts since the session started
+    session_volume: u128,
+
+    /// All trades of (at least) the last 24 hours, oldest first
+    window: VecDeque<Trade>,
+    /// Sum of the amounts in `window`
+    window_volume: u128,
+    /// Candidates for the window's high: prices strictly decreasing from front to back
+    highs: VecDeque<(Timestamp, u64)>,
+    /// Candidates for the window's low: prices strictly increasing from front to back
+    lows: VecDeque<(Timestamp, u64)>,
+
+    /// Recent trades, oldest first
+    tape: VecDeque<Trade>,
+    /// Max number of trades on the tape
+    tape_capacity: usize,
+}
+
+impl Default for MarketStatistics {
+    fn default() -> Self {
+        MarketStatistics::new(DEFAULT_TAPE_CAPACITY)
+    }
+}
+
+impl MarketStatistics {
+    /// Creates empty statistics with a tape that keeps the latest `tape_capacity` trades
+    pub fn new(tape_capacity: usize) -> Self {
+        MarketStatistics {
+            last: None,
+            session_notional: 0,
+            session_volume: 0,
+            window: VecDeque::new(),
+            window_volume: 0,
+            highs: VecDeque::new(),
+            lows: VecDeque::new(),
+            tape: VecDeque::with_capacity(tape_capacity),
+            tape_capacity,
+        }
+    }
+
+    /// Adds a [`Trade`] to the statistics. Trades are expected in timestamp order.
+    pub fn record(&mut self, trade: Trade) {
+        self.evict(trade.timestamp);
+
+        self.session_notional += trade.price as u128 * trade.amount as u128;
+        self.session_volume += trade.amount as u128;
+
+        while matches!(self.highs.back(), Some((_, p)) if *p <= trade.price) {
+            self.highs.pop_back();
+        }
+        self.highs.push_back((trade.timestamp, trade.price));
+        while matches!(self.lows.back(), Some((_, p)) if *p >= trade.price) {
+            self.lows.pop_back();
+        }
+        self.lows.push_back((trade.timestamp, trade.price));
+
+        self.window_volume += trade.amount as u128;
+        self.window.push_back(trade.clone());
+
+        if self.tape_capacity > 0 {
+            if self.tape.len() == self.tape_capacity {
+                self.tape.pop_front();
+            }
+            self.tape.push_back(trade.clone());
+        }
+        self.last = Some(trade);
+    }
+
+    /// Resets the session VWAP, e.g. at the start of a trading day
+    pub fn reset_session(&mut self) {
+        self.session_notional = 0;
+        self.session_volume = 0;
+    }
+
+    /// The most recent trade, if any
+    pub fn last_trade(&self) -> Option<&Trade> {
+        self.last.as_ref()
+    }
+
+    /// Volume weighted average price since the session started
+    pub fn vwap(&self) -> Option<u64> {
+        self.session_notional
+            .checked_div(self.session_volume)
+            .map(|p| p as u64)
+    }
+
+    /// Computes the [`Ticker`] as seen at `now`. Trades older than 24 hours are ignored.
+    pub fn ticker(&self, now: Timestamp) -> Ticker {
+        let is_current = |ts: &Timestamp| ts.saturating_add(DAY) > now;
+
+        let stale = self.window.partition_point(|t| !is_current(&t.timestamp));
+        let stale_volume: u128 = self
+            .window
+            .iter()
+            .take(stale)
+            .map(|t| t.amount as u128)
+            .sum();
+        let open_24h = self.window.get(stale).map(|t| t.price);
+        let high_24h = self
+            .highs
+            .iter()
+            .find(|(ts, _)| is_current(ts))
+            .map(|(_, p)| *p);
+        let low_24h = self
+            .lows
+            .iter()
+            .find(|(ts, _)| is_current(ts))
+            .map(|(_, p)| *p);
+
+        let last_price = self.last.as_ref().map(|t| t.price);
+        let change_24h = match (last_price, open_24h) {
+            (Some(last), Some(open)) => last as i128 - open as i128,
+            _ => 0,
+        };
+
+        Ticker {
+            last_price,
+            last_amount: self.last.as_ref().map(|t| t.amount),
+            vwap: self.vwap(),
+            high_24h,
+            low_24h,
+            open_24h,
+            volume_24h: self.window_volume - stale_volume,
+            change_24h,
+        }
+    }
+
+    /// All trades on the tape, oldest first
+    pub fn tape(&self) -> impl Iterator<Item = &Trade> {
+        self.tape.iter()
+    }
+
+    /// Trades on the tape with `from <= timestamp < to`, oldest first
+    pub fn trades_between(&self, from: Timestamp, to: Timestamp) -> impl Iterator<Item = &Trade> {
+        let start = self.tape.partition_point(|t| t.timestamp < from);
+        let end = self.tape.partition_point(|t| t.timestamp < to).max(start);
+        self.tape.range(start..end)
+    }
+
+    /// Drops everything from the rolling window that's older than 24 hours before `now`
+    fn evict(&mut self, now: Timestamp) {
+        let is_stale = |ts: &Timestamp| ts.saturating_add(DAY) <= now;
+        while matches!(self.window.front(), Some(t) if is_stale(&t.timestamp)) {
+            if let Some(t) = self.window.pop_front() {
+                self.window_volume -= t.amount as u128;
+            }
+        }
+        while matches!(self.highs.front(), Some((ts, _)) if is_stale(ts)) {
+            self.highs.pop_front();
+        }
+        while matches!(self.lows.front(), Some((ts, _)) if is_stale(ts)) {
+            self.lows.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use crate::{clock::HOUR, core::Side};
+
+    use super::*;
+
+    fn trade(timestamp: Timestamp, price: u64, amount: u64) -> Trade {
+        Trade {
+            timestamp,
+            price,
+            amount,
+            taker_side: Side::Buy,
+            maker_ordinal: 1,
+            taker_ordinal: 2,
+        }
+    }
+
+    #[test]
+    fn test_MarketStatistics_ticker_empty() {
+        let stats = MarketStatistics::default();
+        assert_eq!(stats.ticker(0), Ticker::default());
+        assert_eq!(stats.last_trade(), None);
+    }
+
+    #[test]
+    fn test_MarketStatistics_ticker_last_and_vwap() {
+        let mut stats = MarketStatistics::default();
+        stats.record(trade(1, 10, 1));
+        stats.record(trade(2, 20, 3));
+
+        let ticker = stats.ticker(2);
+        assert_eq!(ticker.last_price, Some(20));
+        assert_eq!(ticker.last_amount, Some(3));
+        // (10 * 1 + 20 * 3) / 4
+        assert_eq!(ticker.vwap, Some(17));
+        assert_eq!(ticker.high_24h, Some(20));
+        assert_eq!(ticker.low_24h, Some(10));
+        assert_eq!(ticker.volume_24h, 4);
+        assert_eq!(ticker.change_24h, 10);
+
+        stats.reset_session();
+        assert_eq!(stats.vwap(), None);
+    }
+
+    #[test]
+    fn test_MarketStatistics_ticker_rolls_24h_window() {
+        let mut stats = MarketStatistics::default();
+        stats.record(trade(0, 30, 1));
+        stats.record(trade(HOUR, 5, 2));
+        stats.record(trade(2 * HOUR, 10, 4));
+
+        let ticker = stats.ticker(DAY + 30 * 60 * 1000);
+        assert_eq!(ticker.high_24h, Some(10));
+        assert_eq!(ticker.low_24h, Some(5));
+        assert_eq!(ticker.open_24h, Some(5));
+        assert_eq!(ticker.volume_24h, 6);
+        assert_eq!(ticker.change_24h, 5);
+
+        // recording a new trade evicts old ones for good
+        stats.record(trade(DAY + 3 * HOUR, 7, 1));
+        let ticker = stats.ticker(DAY + 3 * HOUR);
+        assert_eq!(ticker.high_24h, Some(7));
+        assert_eq!(ticker.low_24h, Some(7));
+        assert_eq!(ticker.volume_24h, 1);
+        assert_eq!(ticker.change_24h, 0);
+        // VWAP covers the whole session
+        assert_eq!(ticker.vwap, Some(87 / 8));
+
+        assert_eq!(stats.ticker(3 * DAY).volume_24h, 0);
+        assert_eq!(stats.ticker(3 * DAY).high_24h, None);
+    }
+
+    #[test]
+    fn test_MarketStatistics_tape_is_bounded_and_queryable() {
+        let mut stats = MarketStatistics::new(3);
+        for ts in 1..=5 {
+            stats.record(trade(ts, 10, ts));
+        }
+        let tape: Vec<u64> = stats.tape().map(|t| t.timestamp).collect();
+        assert_eq!(tape, vec![3, 4, 5]);
+
+        let range: Vec<u64> = stats.trades_between(2, 5).map(|t| t.timestamp).collect();
+        assert_eq!(range, vec![3, 4]);
+        assert_eq!(stats.trades_between(5, 2).count(), 0);
+    }
+}
@@ -1,5 +1,11 @@
 use std::cmp::Reverse;
 
+use crate::{
+    amount::{Amount, Market, Price},
+    clock::Timestamp,
+    errors::ApplicationError,
+};
+
 /// Simplified side of a position as well as order.
 #[derive(Clone, PartialOrd, PartialEq, Eq, Debug, Ord)]
 pub enum Side {
@@ -10,7 +16,7 @@ pub enum Side {
 }
 
 /// An order for a specified symbol to buy or sell an amount at a given price.
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Order {
     /// Max/min price (depending on the side)
     pub price: u64,
@@ -23,6 +29,23 @@ pub struct Order {
 }
 
 impl Order {
+    /// An order to trade `amount` of the base asset of `market` at `price` in its quote asset
+    /// # Errors
+    /// The price or amount has more decimals than its asset or doesn't fit
+    pub fn new(
+        market: &Market,
+        side: Side,
+        signer: &str,
+        price: Price,
+        amount: Amount,
+    ) -> Result<Self, ApplicationError> {
+        Ok(Order {
+            price: market.quote.minor_units(price)?,
+            amount: market.base.minor_units(amount)?,
+            side,
+            signer: signer.to_string(),
+        })
+    }
 
     /// Convert an [`Order`] into a [`PartialOrder`] with the added parameters
     pub fn into_partial_order(self, ordinal: u64, remaining: u64) -> PartialOrder {
@@ -44,7 +67,7 @@ impl Order {
 }
 
 /// A position represents an unfilled order that is kept in the system for later filling.
-#[derive(Clone, PartialEq, Debug, Eq, Ord)]
+#[derive(Clone, PartialEq, Debug, Eq)]
 pub struct PartialOrder {
     /// Price per unit
     pub price: u64,
@@ -62,8 +85,14 @@ pub struct PartialOrder {
 
 impl PartialOrd for PartialOrder {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PartialOrder {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         // this reverses the comparison to create a min heap
-        Reverse(self.ordinal).partial_cmp(&Reverse(other.ordinal))
+        Reverse(self.ordinal).cmp(&Reverse(other.ordinal))
     }
 }
 
@@ -75,10 +104,23 @@ pub struct Receipt {
 
     /// Matches that happened immediately
     pub matches: Vec<PartialOrder>,
+
+    /// Fees for each of the `matches`, in the same order
+    pub fees: Vec<FillFee>,
+}
+
+/// Fees charged for one match. Positive amounts are paid to the fee account, negative amounts are rebates paid out of it.
+#[derive(Clone, PartialOrd, PartialEq, Eq, Debug)]
+pub struct FillFee {
+    /// Sequence number of the resting order
+    pub maker_ordinal: u64,
+    /// Fee of the resting order's signer
+    pub maker_fee: i128,
+    /// Fee of the incoming order's signer
+    pub taker_fee: i128,
 }
 
 impl PartialOrder {
-    
     /// Splits one [`PartialOrder`] into two by taking a defined `take` amount
     pub fn take_from(pos: &mut PartialOrder, take: u64, price: u64) -> PartialOrder {
         pos.remaining -= take;
@@ -88,3 +130,62 @@ impl PartialOrder {
         new
     }
 }
+
+/// A single print on the tape: a taker order matched (part of) a resting maker order.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Trade {
+    /// When the match happened
+    pub timestamp: Timestamp,
+    /// Execution price (the maker's price)
+    pub price: u64,
+    /// Number of units traded
+    pub amount: u64,
+    /// Side of the incoming (taker) order
+    pub taker_side: Side,
+    /// Sequence number of the resting order
+    pub maker_ordinal: u64,
+    /// Sequence number of the incoming order
+    pub taker_ordinal: u64,
+}
+
+/// Selects the open orders to remove with [`super::MatchingEngine::mass_cancel`]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MassCancel {
+    /// Every order of a signer
+    Signer(String),
+    /// Every order of a signer on one side of the book
+    SignerSide(String, Side),
+    /// Every order in the market
+    Market,
+    /// Every order priced strictly above the given price
+    Above(u64),
+    /// Every order priced strictly below the given price
+    Below(u64),
+}
+
+impl MassCancel {
+    /// Whether `order` is selected
+    pub fn matches(&self, order: &PartialOrder) -> bool {
+        match self {
+            MassCancel::Signer(signer) => &order.signer == signer,
+            MassCancel::SignerSide(signer, side) => &order.signer == signer && &order.side == side,
+            MassCancel::Market => true,
+            MassCancel::Above(price) => order.price > *price,
+            MassCancel::Below(price) => order.price < *price,
+        }
+    }
+}
+
+/// A receipt issued for removing one or more orders from the book at once
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct CancelReceipt {
+    /// The removed orders, as they were in the book
+    pub cancelled: Vec<PartialOrder>,
+}
+
+impl CancelReceipt {
+    /// Total number of units that were removed from the book
+    pub fn amount(&self) -> u64 {
+        self.cancelled.iter().map(|o| o.remaining).sum()
+    }
+}
@@ -0,0 +1,429 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    accounting::Accounts,
+    errors::ApplicationError,
+    tx::{Leg, Tx, TxContext, TxId},
+};
+
+/// Contra account for money entering or leaving the platform unless configured otherwise
+pub const DEFAULT_EXTERNAL_ACCOUNT: &str = "EXTERNAL";
+
+/// Classification of an account in the [`ChartOfAccounts`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, PartialOrd, Ord)]
+pub enum AccountType {
+    /// Something the platform owns
+    Asset,
+    /// Something the platform owes, e.g. customer balances
+    Liability,
+    /// The owners' stake
+    Equity,
+    /// Income, e.g. trading fees
+    Revenue,
+    /// The outside world that deposits come from and withdrawals go to
+    External,
+}
+
+impl AccountType {
+    /// Whether a positive balance is a debit (`true`) or a credit (`false`) balance
+    pub fn is_debit_normal(&self) -> bool {
+        matches!(self, AccountType::Asset | AccountType::External)
+    }
+}
+
+/// Assigns an [`AccountType`] to account names. Accounts that aren't listed are customer
+/// balances, i.e. liabilities.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ChartOfAccounts {
+    /// Contra account for deposits and withdrawals
+    pub external: String,
+    types: BTreeMap<String, AccountType>,
+}
+
+impl Default for ChartOfAccounts {
+    fn default() -> Self {
+        ChartOfAccounts::new(DEFAULT_EXTERNAL_ACCOUNT)
+    }
+}
+
+impl ChartOfAccounts {
+    /// Creates a chart that books deposits and withdrawals against `external`
+    pub fn new(external: &str) -> Self {
+        let mut types = BTreeMap::new();
+        types.insert(external.to_string(), AccountType::External);
+        ChartOfAccounts {
+            external: external.to_string(),
+            types,
+        }
+    }
+
+    /// Classifies `account` as `account_type`
+    pub fn with(mut self, account: &str, account_type: AccountType) -> Self {
+        self.types.insert(account.to_string(), account_type);
+        self
+    }
+
+    /// The type of `account`
+    pub fn type_of(&self, account: &str) -> AccountType {
+        self.types
+            .get(account)
+            .copied()
+            .unwrap_or(AccountType::Liability)
+    }
+
+    /// The balanced [`Posting`] that records `tx`
+    pub fn posting(&self, tx: &Tx) -> Posting {
+        let Some(leg) = tx.leg() else {
+            // nothing moved
+            return Posting {
+                tx_id: tx.meta().id,
+                lines: vec![],
+            };
+        };
+        let (debit, credit, amount) = match leg {
+            Leg::Deposit { account, amount } => (self.external.clone(), account, amount),
+            Leg::Withdraw { account, amount } => (account, self.external.clone(), amount),
+            Leg::Transfer {
+                sender,
+                recipient,
+                amount,
+            } => (sender, recipient, amount),
+        };
+        Posting {
+            tx_id: tx.meta().id,
+            lines: vec![
+                PostingLine {
+                    account: debit,
+                    amount: amount as i128,
+                },
+                PostingLine {
+                    account: credit,
+                    amount: -(amount as i128),
+                },
+            ],
+        }
+    }
+}
+
+/// One side of a [`Posting`]. Positive amounts are debits, negative amounts are credits.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PostingLine {
+    /// The account that's debited or credited
+    pub account: String,
+    /// Debit (positive) or credit (negative)
+    pub amount: i128,
+}
+
+/// A set of debits and credits that sums to zero
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Posting {
+    /// The transaction that was posted
+    pub tx_id: TxId,
+    /// Debits and credits
+    pub lines: Vec<PostingLine>,
+}
+
+impl Posting {
+    /// Creates a posting from arbitrary lines
+    /// # Errors
+    /// The lines don't sum to zero
+    pub fn new(tx_id: TxId, lines: Vec<PostingLine>) -> Result<Self, ApplicationError> {
+        let sum: i128 = lines.iter().map(|l| l.amount).sum();
+        if sum != 0 {
+            return Err(ApplicationError::UnbalancedPosting(sum));
+        }
+        Ok(Posting { tx_id, lines })
+    }
+}
+
+/// One account's row in a [`TrialBalance`]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TrialBalanceRow {
+    /// Classification of the account
+    pub account_type: AccountType,
+    /// Sum of all debits
+    pub debits: u128,
+    /// Sum of all credits
+    pub credits: u128,
+}
+
+impl TrialBalanceRow {
+    /// Balance in the account's normal direction, e.g. what a liability account owes
+    pub fn balance(&self) -> i128 {
+        let net = self.debits as i128 - self.credits as i128;
+        if self.account_type.is_debit_normal() {
+            net
+        } else {
+            -net
+        }
+    }
+}
+
+/// Debits and credits per account over a set of postings
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct TrialBalance {
+    /// Rows by account name
+    pub rows: BTreeMap<String, TrialBalanceRow>,
+}
+
+impl TrialBalance {
+    /// Sums up `postings`
+    pub fn new<'a>(
+        chart: &ChartOfAccounts,
+        postings: impl IntoIterator<Item = &'a Posting>,
+    ) -> Self {
+        let mut rows: BTreeMap<String, TrialBalanceRow> = BTreeMap::new();
+        for line in postings.into_iter().flat_map(|p| p.lines.iter()) {
+            let row = rows
+                .entry(line.account.clone())
+                .or_insert_with(|| TrialBalanceRow {
+                    account_type: chart.type_of(&line.account),
+                    debits: 0,
+                    credits: 0,
+                });
+            if line.amount >= 0 {
+                row.debits += line.amount as u128;
+            } else {
+                row.credits += line.amount.unsigned_abs();
+            }
+        }
+        TrialBalance { rows }
+    }
+
+    /// Posts every transaction in `journal` and sums them up
+    pub fn from_journal<'a>(
+        chart: &ChartOfAccounts,
+        journal: impl IntoIterator<Item = &'a Tx>,
+    ) -> Self {
+        let postings: Vec<Posting> = journal.into_iter().map(|tx| chart.posting(tx)).collect();
+        TrialBalance::new(chart, &postings)
+    }
+
+    /// Total of all debits
+    pub fn total_debits(&self) -> u128 {
+        self.rows.values().map(|r| r.debits).sum()
+    }
+
+    /// Total of all credits
+    pub fn total_credits(&self) -> u128 {
+        self.rows.values().map(|r| r.credits).sum()
+    }
+
+    /// Debits equal credits, i.e. no money was created or destroyed
+    pub fn is_balanced(&self) -> bool {
+        self.total_debits() == self.total_credits()
+    }
+
+    /// Checks that every customer balance in `accounts` matches the books. An account that owes
+    /// something on its credit line has a negative balance in the books.
+    /// # Errors
+    /// The first account whose balance differs from the books
+    pub fn reconcile(&self, accounts: &Accounts) -> Result<(), ApplicationError> {
+        for (name, row) in &self.rows {
+            if row.account_type == AccountType::External {
+                continue;
+            }
+            let balance = accounts.net_balance(name).unwrap_or(0);
+            if row.balance() != balance {
+                return Err(ApplicationError::Unreconciled(
+                    name.clone(),
+                    row.balance() - balance,
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// [`Accounts`] with double-entry bookkeeping on top: every transaction is also recorded as a
+/// balanced [`Posting`] according to a [`ChartOfAccounts`].
+#[derive(Debug)]
+pub struct DoubleEntryAccounts {
+    accounts: Accounts,
+    chart: ChartOfAccounts,
+    postings: Vec<Posting>,
+}
+
+impl DoubleEntryAccounts {
+    /// Wraps `accounts` and posts everything in its journal so far
+    pub fn new(accounts: Accounts, chart: ChartOfAccounts) -> Self {
+        let postings = accounts
+            .journal()
+            .iter()
+            .map(|tx| chart.posting(tx))
+            .collect();
+        DoubleEntryAccounts {
+            accounts,
+            chart,
+            postings,
+        }
+    }
+
+    /// The underlying single-entry balances
+    pub fn accounts(&self) -> &Accounts {
+        &self.accounts
+    }
+
+    /// Unwraps the underlying [`Accounts`]
+    pub fn into_accounts(self) -> Accounts {
+        self.accounts
+    }
+
+    /// All postings, oldest first
+    pub fn postings(&self) -> &[Posting] {
+        &self.postings
+    }
+
+    /// See [`Accounts::open_account`]
+    pub fn open_account(&mut self, account: &str) -> Result<Tx, ApplicationError> {
+        let tx = self.accounts.open_account(account)?;
+        Ok(self.post(tx))
+    }
+
+    /// See [`Accounts::deposit_with`]
+    pub fn deposit(
+        &mut self,
+        signer: &str,
+        amount: u64,
+        context: TxContext,
+    ) -> Result<Tx, ApplicationError> {
+        let tx = self.accounts.deposit_with(signer, amount, context)?;
+        Ok(self.post(tx))
+    }
+
+    /// See [`Accounts::withdraw_with`]
+    pub fn withdraw(
+        &mut self,
+        signer: &str,
+        amount: u64,
+        context: TxContext,
+    ) -> Result<Tx, ApplicationError> {
+        let tx = self.accounts.withdraw_with(signer, amount, context)?;
+        Ok(self.post(tx))
+    }
+
+    /// See [`Accounts::send_with`]
+    pub fn send(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: u64,
+        context: TxContext,
+    ) -> Result<Tx, ApplicationError> {
+        let tx = self
+            .accounts
+            .send_with(sender, recipient, amount, context)?;
+        Ok(self.post(tx))
+    }
+
+    /// See [`Accounts::execute_batch`]
+    pub fn execute_batch(
+        &mut self,
+        legs: Vec<Leg>,
+        context: TxContext,
+    ) -> Result<Vec<Tx>, ApplicationError> {
+        let txs = self.accounts.execute_batch(legs, context)?;
+        Ok(txs.into_iter().map(|tx| self.post(tx)).collect())
+    }
+
+    /// Debits and credits per account
+    pub fn trial_balance(&self) -> TrialBalance {
+        TrialBalance::new(&self.chart, &self.postings)
+    }
+
+    fn post(&mut self, tx: Tx) -> Tx {
+        self.postings.push(self.chart.posting(&tx));
+        tx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use crate::clock::ManualClock;
+
+    use super::*;
+
+    #[test]
+    fn test_Posting_new_requires_balance() {
+        let line = |account: &str, amount| PostingLine {
+            account: account.to_string(),
+            amount,
+        };
+        assert!(Posting::new(TxId(1), vec![line("a", 5), line("b", -5)]).is_ok());
+        assert_eq!(
+            Posting::new(TxId(1), vec![line("a", 5), line("b", -4)]),
+            Err(ApplicationError::UnbalancedPosting(1))
+        );
+    }
+
+    #[test]
+    fn test_DoubleEntryAccounts_trial_balance() {
+        let chart = ChartOfAccounts::default().with("FEES", AccountType::Revenue);
+        let mut ledger =
+            DoubleEntryAccounts::new(Accounts::with_clock(ManualClock::default()), chart);
+        ledger.open_account("ALICE").unwrap();
+        ledger.open_account("FEES").unwrap();
+        ledger.deposit("ALICE", 100, TxContext::default()).unwrap();
+        ledger
+            .send("ALICE", "FEES", 3, TxContext::default())
+            .unwrap();
+        ledger.withdraw("ALICE", 7, TxContext::default()).unwrap();
+        assert!(ledger
+            .withdraw("ALICE", 1_000, TxContext::default())
+            .is_err());
+
+        let trial_balance = ledger.trial_balance();
+        assert!(trial_balance.is_balanced());
+        assert_eq!(trial_balance.total_debits(), 110);
+        assert_eq!(trial_balance.rows["ALICE"].balance(), 90);
+        assert_eq!(
+            trial_balance.rows["ALICE"].account_type,
+            AccountType::Liability
+        );
+        assert_eq!(trial_balance.rows["FEES"].balance(), 3);
+        // the outside world put in 100 and got 7 back
+        assert_eq!(trial_balance.rows[DEFAULT_EXTERNAL_ACCOUNT].balance(), 93);
+        assert_eq!(trial_balance.reconcile(ledger.accounts()), Ok(()));
+        // opening an account posts nothing
+        assert!(ledger.postings()[0].lines.is_empty());
+        assert_eq!(ledger.postings().len(), 5);
+    }
+
+    #[test]
+    fn test_TrialBalance_from_journal_matches_existing_accounts() {
+        let mut accounts = Accounts::with_clock(ManualClock::default());
+        accounts.open_account("ALICE").unwrap();
+        accounts.open_account("BOB").unwrap();
+        accounts.deposit("ALICE", 50).unwrap();
+        accounts.send("ALICE", "BOB", 20).unwrap();
+
+        let chart = ChartOfAccounts::default();
+        let trial_balance = TrialBalance::from_journal(&chart, accounts.journal());
+        assert!(trial_balance.is_balanced());
+        assert_eq!(trial_balance.reconcile(&accounts), Ok(()));
+
+        // wrapping existing accounts picks up their history
+        let ledger = DoubleEntryAccounts::new(accounts, chart);
+        assert_eq!(ledger.trial_balance(), trial_balance);
+    }
+
+    #[test]
+    fn test_TrialBalance_reconciles_overdrawn_accounts() {
+        let mut accounts = Accounts::with_clock(ManualClock::default());
+        accounts.open_account("ALICE").unwrap();
+        accounts.open_account("BOB").unwrap();
+        accounts.set_credit_limit("ALICE", 50).unwrap();
+        accounts.deposit("ALICE", 10).unwrap();
+        accounts.send("ALICE", "BOB", 40).unwrap();
+        assert_eq!(accounts.net_balance("ALICE"), Ok(-30));
+
+        let trial_balance =
+            TrialBalance::from_journal(&ChartOfAccounts::default(), accounts.journal());
+        assert!(trial_balance.is_balanced());
+        assert_eq!(trial_balance.rows["ALICE"].balance(), -30);
+        assert_eq!(trial_balance.reconcile(&accounts), Ok(()));
+    }
+}
@@ -1,12 +1,107 @@
+use crate::{limits::Limit, risk::Rejection};
+
 /// An application-specific error type
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum ApplicationError {
     /// Account wasn't found
     AccountNotFound(String),
 
-    /// Not enough currency in the account (underflow)
+    /// An account with this name was opened before
+    AccountExists(String),
+
+    /// The account is frozen and can't send funds or trade
+    AccountFrozen(String),
+
+    /// The account isn't frozen
+    AccountNotFrozen(String),
+
+    /// The account was closed
+    AccountClosed(String),
+
+    /// The account can't be closed while it holds funds (the balance)
+    AccountNotEmpty(String, u64),
+
+    /// The account can't be closed while it owes something (the debt)
+    AccountInDebt(String, u64),
+
+    /// The account can't be closed while it has orders in the book (the number of orders)
+    AccountHasOpenOrders(String, usize),
+
+    /// The account can't be closed while it has sub-accounts that aren't closed (their number)
+    AccountHasSubAccounts(String, usize),
+
+    /// The account can't be closed while it holds a position (account, symbol)
+    AccountHasPosition(String, String),
+
+    /// Both accounts have to belong to the same entity (sender, recipient)
+    NotSameEntity(String, String),
+
+    /// Not enough currency in the account and its credit line (underflow)
     AccountUnderFunded(String, u64),
 
     /// Too much currency in the account (overflow)
     AccountOverFunded(String, u64),
+
+    /// The operation would go over a limit of the account (the limit, the remaining allowance)
+    LimitExceeded(String, Limit, u64),
+
+    /// An amount couldn't be parsed, converted or calculated (the reason)
+    InvalidAmount(String),
+
+    /// The ledger holds amounts of its asset already, so the asset can't change (its code)
+    AssetInUse(String),
+
+    /// The market can't change while orders rest in the book (their number)
+    BookNotEmpty(usize),
+
+    /// The idempotency key was used before for a different request (the key)
+    IdempotencyKeyReused(String),
+
+    /// The account is reserved for internal use
+    ReservedAccount(String),
+
+    /// No open escrow with this id
+    EscrowNotFound(u64),
+
+    /// The escrow's condition doesn't allow this account to release or refund it now (the id)
+    EscrowDenied(u64),
+
+    /// No active scheduled transfer with this id
+    ScheduleNotFound(u64),
+
+    /// The account has no open position in the market (account, symbol)
+    PositionNotFound(String, String),
+
+    /// The account's equity wouldn't cover the margin its positions require (the requirement)
+    InsufficientMargin(String, u64),
+
+    /// A pre-trade risk check rejected the order of the account (the reason)
+    OrderRejected(String, Rejection),
+
+    /// No open order with this ordinal
+    OrderNotFound(u64),
+
+    /// No active session with this id
+    SessionNotFound(u64),
+
+    /// A transaction was applied out of sequence
+    OutOfSequence(u64),
+
+    /// Debits and credits of a posting don't cancel out (the sum)
+    UnbalancedPosting(i128),
+
+    /// An account's balance doesn't match the books (the difference)
+    Unreconciled(String, i128),
+
+    /// Persisted data couldn't be decoded (the reason)
+    Corrupted(String),
+
+    /// Reading or writing persisted data failed (the I/O error)
+    Storage(String),
+}
+
+impl From<std::io::Error> for ApplicationError {
+    fn from(e: std::io::Error) -> Self {
+        ApplicationError::Storage(e.to_string())
+    }
 }
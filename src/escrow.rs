@@ -0,0 +1,90 @@
+use crate::clock::Timestamp;
+
+/// Holds the funds of every open escrow. Reserved, it can't be opened or used directly.
+pub const ESCROW_ACCOUNT: &str = "ESCROW";
+
+/// Who may release or refund an escrow, and when
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Condition {
+    /// The arbiter releases or refunds it
+    Approval { arbiter: String },
+    /// Anyone can release it once the deadline is reached. Until then the recipient can refund it.
+    Deadline(Timestamp),
+    /// The arbiter can release it before the deadline and refund it at any time. Once the
+    /// deadline is reached, anyone can refund it.
+    ApprovalBefore {
+        arbiter: String,
+        deadline: Timestamp,
+    },
+}
+
+/// Funds on their way from `sender` to `recipient` that wait for a [`Condition`]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Escrow {
+    /// Sequence number of the transaction that opened it
+    pub id: u64,
+    pub sender: String,
+    pub recipient: String,
+    pub amount: u64,
+    pub condition: Condition,
+}
+
+impl Escrow {
+    /// Whether `by` may pay the funds out to the recipient at `now`
+    pub fn can_release(&self, by: &str, now: Timestamp) -> bool {
+        match &self.condition {
+            Condition::Approval { arbiter } => by == arbiter,
+            Condition::Deadline(deadline) => now >= *deadline,
+            Condition::ApprovalBefore { arbiter, deadline } => by == arbiter && now < *deadline,
+        }
+    }
+
+    /// Whether `by` may pay the funds back to the sender at `now`
+    pub fn can_refund(&self, by: &str, now: Timestamp) -> bool {
+        match &self.condition {
+            Condition::Approval { arbiter } => by == arbiter,
+            Condition::Deadline(deadline) => by == self.recipient && now < *deadline,
+            Condition::ApprovalBefore { arbiter, deadline } => by == arbiter || now >= *deadline,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    fn escrow(condition: Condition) -> Escrow {
+        Escrow {
+            id: 1,
+            sender: "ALICE".to_string(),
+            recipient: "BOB".to_string(),
+            amount: 10,
+            condition,
+        }
+    }
+
+    #[test]
+    fn test_Escrow_conditions() {
+        let approval = escrow(Condition::Approval {
+            arbiter: "CAROL".to_string(),
+        });
+        assert!(approval.can_release("CAROL", 0) && approval.can_refund("CAROL", 0));
+        assert!(!approval.can_release("BOB", 0) && !approval.can_refund("ALICE", 0));
+
+        let deadline = escrow(Condition::Deadline(100));
+        assert!(!deadline.can_release("BOB", 99) && deadline.can_release("ALICE", 100));
+        assert!(deadline.can_refund("BOB", 99) && !deadline.can_refund("BOB", 100));
+        assert!(!deadline.can_refund("ALICE", 0));
+
+        let both = escrow(Condition::ApprovalBefore {
+            arbiter: "CAROL".to_string(),
+            deadline: 100,
+        });
+        assert!(both.can_release("CAROL", 99) && !both.can_release("CAROL", 100));
+        assert!(both.can_refund("CAROL", 0) && !both.can_refund("ALICE", 99));
+        assert!(both.can_refund("ALICE", 100));
+    }
+}
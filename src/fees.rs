@@ -0,0 +1,174 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::clock::{Timestamp, DAY};
+
+/// Basis points in one unit
+pub const BPS: i128 = 10_000;
+
+/// Account that collects fees unless configured otherwise
+pub const DEFAULT_FEE_ACCOUNT: &str = "FEES";
+
+/// Trading volume that counts towards a [`FeeTier`]
+pub const VOLUME_WINDOW: Timestamp = 30 * DAY;
+
+/// Fee rates that apply from a given trading volume upwards
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FeeTier {
+    /// Minimum 30-day notional volume (price * amount) to qualify
+    pub min_volume: u128,
+    /// Rate for resting orders in basis points, negative for a rebate
+    pub maker_bps: i64,
+    /// Rate for incoming orders in basis points
+    pub taker_bps: i64,
+}
+
+/// Maker/taker fee rates by volume tier and the account that fees are collected in
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FeeSchedule {
+    /// Receives fees and pays out rebates
+    pub account: String,
+    /// Sorted by `min_volume`
+    tiers: Vec<FeeTier>,
+}
+
+impl Default for FeeSchedule {
+    /// No fees at all
+    fn default() -> Self {
+        FeeSchedule::new(DEFAULT_FEE_ACCOUNT, vec![])
+    }
+}
+
+impl FeeSchedule {
+    /// Creates a new schedule. Volume below the lowest tier doesn't pay fees.
+    pub fn new(account: &str, mut tiers: Vec<FeeTier>) -> Self {
+        tiers.sort_by_key(|t| t.min_volume);
+        FeeSchedule {
+            account: account.to_string(),
+            tiers,
+        }
+    }
+
+    /// Every tier, ordered by minimum volume
+    pub fn tiers(&self) -> &[FeeTier] {
+        &self.tiers
+    }
+
+    /// The tier for a 30-day volume of `volume`
+    pub fn tier(&self, volume: u128) -> Option<&FeeTier> {
+        self.tiers.iter().rev().find(|t| t.min_volume <= volume)
+    }
+
+    /// The maker fee for a fill of `notional` by a signer with a 30-day volume of `volume`
+    pub fn maker_fee(&self, volume: u128, notional: u64) -> i128 {
+        self.tier(volume)
+            .map(|t| fee(notional, t.maker_bps))
+            .unwrap_or(0)
+    }
+
+    /// The taker fee for a fill of `notional` by a signer with a 30-day volume of `volume`
+    pub fn taker_fee(&self, volume: u128, notional: u64) -> i128 {
+        self.tier(volume)
+            .map(|t| fee(notional, t.taker_bps))
+            .unwrap_or(0)
+    }
+}
+
+/// Applies a rate of `bps` to `notional`. Fees are rounded up, rebates are rounded towards zero.
+pub fn fee(notional: u64, bps: i64) -> i128 {
+    let raw = notional as i128 * bps as i128;
+    if raw > 0 {
+        (raw + BPS - 1) / BPS
+    } else {
+        raw / BPS
+    }
+}
+
+/// Rolling 30-day notional volume per signer
+#[derive(Clone, Debug, Default)]
+pub struct VolumeTracker {
+    signers: BTreeMap<String, SignerVolume>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct SignerVolume {
+    /// (timestamp, notional) of every fill in the window, oldest first
+    fills: VecDeque<(Timestamp, u128)>,
+    total: u128,
+}
+
+impl VolumeTracker {
+    /// Adds a fill of `notional` at `now` to the signer's volume
+    pub fn record(&mut self, signer: &str, now: Timestamp, notional: u64) {
+        let volume = self.signers.entry(signer.to_string()).or_default();
+        volume.fills.push_back((now, notional as u128));
+        volume.total += notional as u128;
+    }
+
+    /// The signer's notional volume over the 30 days before `now`
+    pub fn volume(&mut self, signer: &str, now: Timestamp) -> u128 {
+        match self.signers.get_mut(signer) {
+            Some(volume) => {
+                while matches!(volume.fills.front(), Some((ts, _)) if ts.saturating_add(VOLUME_WINDOW) <= now)
+                {
+                    if let Some((_, notional)) = volume.fills.pop_front() {
+                        volume.total -= notional;
+                    }
+                }
+                volume.total
+            }
+            None => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_fee_rounding() {
+        assert_eq!(fee(100, 10), 1);
+        assert_eq!(fee(1, 10), 1);
+        assert_eq!(fee(1, -10), 0);
+        assert_eq!(fee(1_500, -10), -1);
+        assert_eq!(fee(1_000, 0), 0);
+    }
+
+    #[test]
+    fn test_FeeSchedule_tier() {
+        let schedule = FeeSchedule::new(
+            "FEES",
+            vec![
+                FeeTier {
+                    min_volume: 1_000,
+                    maker_bps: -1,
+                    taker_bps: 5,
+                },
+                FeeTier {
+                    min_volume: 0,
+                    maker_bps: 2,
+                    taker_bps: 10,
+                },
+            ],
+        );
+        assert_eq!(schedule.tier(999).unwrap().taker_bps, 10);
+        assert_eq!(schedule.tier(1_000).unwrap().taker_bps, 5);
+        assert_eq!(schedule.taker_fee(0, 10_000), 10);
+        assert_eq!(schedule.maker_fee(5_000, 10_000), -1);
+        assert_eq!(FeeSchedule::default().taker_fee(0, 10_000), 0);
+    }
+
+    #[test]
+    fn test_VolumeTracker_volume_rolls_off() {
+        let mut volumes = VolumeTracker::default();
+        volumes.record("ALICE", 0, 100);
+        volumes.record("ALICE", DAY, 50);
+        assert_eq!(volumes.volume("ALICE", DAY), 150);
+        assert_eq!(volumes.volume("ALICE", VOLUME_WINDOW), 50);
+        assert_eq!(volumes.volume("ALICE", VOLUME_WINDOW + DAY), 0);
+        assert_eq!(volumes.volume("BOB", 0), 0);
+    }
+}
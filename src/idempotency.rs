@@ -0,0 +1,99 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::clock::{Timestamp, DAY};
+
+/// How long an [`IdempotencyCache`] remembers a key unless configured otherwise
+pub const DEFAULT_IDEMPOTENCY_WINDOW: Timestamp = DAY;
+
+/// Remembers the outcome of requests by their client-supplied key for a while, so that a retried
+/// request can be answered with the original outcome instead of being executed again
+#[derive(Clone, Debug)]
+pub struct IdempotencyCache<T> {
+    /// How long a key is remembered
+    window: Timestamp,
+    /// When each remembered request happened and its outcome
+    entries: BTreeMap<String, (Timestamp, T)>,
+    /// Keys in the order they were remembered, oldest first
+    expiry: VecDeque<(Timestamp, String)>,
+}
+
+impl<T> Default for IdempotencyCache<T> {
+    fn default() -> Self {
+        IdempotencyCache::new(DEFAULT_IDEMPOTENCY_WINDOW)
+    }
+}
+
+impl<T> IdempotencyCache<T> {
+    /// Creates an empty cache that remembers keys for `window`
+    pub fn new(window: Timestamp) -> Self {
+        IdempotencyCache {
+            window,
+            entries: BTreeMap::new(),
+            expiry: VecDeque::new(),
+        }
+    }
+
+    /// How long a key is remembered
+    pub fn window(&self) -> Timestamp {
+        self.window
+    }
+
+    /// Changes how long keys are remembered, including the ones remembered already
+    pub fn set_window(&mut self, window: Timestamp) {
+        self.window = window;
+    }
+
+    /// The outcome of the request with `key`, if it happened within the window before `now`
+    pub fn get(&mut self, key: &str, now: Timestamp) -> Option<&T> {
+        self.expire(now);
+        self.entries.get(key).map(|(_, value)| value)
+    }
+
+    /// Remembers `value` as the outcome of the request with `key` at `now`
+    pub fn insert(&mut self, key: &str, now: Timestamp, value: T) {
+        self.expire(now);
+        self.entries.insert(key.to_string(), (now, value));
+        self.expiry.push_back((now, key.to_string()));
+    }
+
+    /// Forgets every key that is older than the window at `now`
+    fn expire(&mut self, now: Timestamp) {
+        while let Some((timestamp, _)) = self.expiry.front() {
+            if timestamp.saturating_add(self.window) > now {
+                break;
+            }
+            if let Some((timestamp, key)) = self.expiry.pop_front() {
+                // the key may have been remembered again after it expired once
+                if self.entries.get(&key).map(|(ts, _)| *ts) == Some(timestamp) {
+                    self.entries.remove(&key);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_IdempotencyCache_forgets_keys_after_the_window() {
+        let mut cache = IdempotencyCache::new(10);
+        cache.insert("a", 0, 1);
+        cache.insert("b", 5, 2);
+        assert_eq!(cache.get("a", 9), Some(&1));
+        assert_eq!(cache.get("a", 10), None);
+        assert_eq!(cache.get("b", 10), Some(&2));
+
+        // a key that expired can be used again
+        cache.insert("a", 12, 3);
+        assert_eq!(cache.get("a", 14), Some(&3));
+        assert_eq!(cache.get("b", 15), None);
+
+        cache.set_window(100);
+        assert_eq!(cache.get("a", 100), Some(&3));
+    }
+}
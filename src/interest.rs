@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+
+use crate::fees::BPS;
+
+/// Account that pays interest and collects custody fees unless configured otherwise
+pub const DEFAULT_INTEREST_ACCOUNT: &str = "INTEREST";
+
+/// Annual rates are divided by this to accrue them daily
+pub const DAYS_PER_YEAR: u64 = 365;
+
+/// Accrued amounts are kept in fractions of 1 / `ACCRUAL_SCALE` of a minor unit, which is exactly
+/// what a rate of one basis point accrues on one minor unit in one day. Accrual never rounds,
+/// posting moves the whole minor units and carries the fraction over to the next period.
+pub const ACCRUAL_SCALE: u128 = BPS as u128 * DAYS_PER_YEAR as u128;
+
+/// What an amount accrues as
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum AccrualKind {
+    /// Paid to the account on its balance
+    Interest,
+    /// Charged to the account on its balance
+    CustodyFee,
+    /// Charged to the account on what it owes
+    Overdraft,
+}
+
+impl AccrualKind {
+    /// Whether the account pays it to the house account
+    pub fn is_charge(&self) -> bool {
+        !matches!(self, AccrualKind::Interest)
+    }
+}
+
+/// Annual rates of an asset in basis points
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Rates {
+    /// Paid on positive balances
+    pub interest_bps: u32,
+    /// Charged on positive balances
+    pub custody_fee_bps: u32,
+    /// Charged on negative balances, i.e. what an account owes on its credit line
+    pub overdraft_bps: u32,
+}
+
+impl Rates {
+    /// The rate of `kind`
+    pub fn bps(&self, kind: AccrualKind) -> u32 {
+        match kind {
+            AccrualKind::Interest => self.interest_bps,
+            AccrualKind::CustodyFee => self.custody_fee_bps,
+            AccrualKind::Overdraft => self.overdraft_bps,
+        }
+    }
+}
+
+/// Interest, custody fee and overdraft rates by asset and the account they are posted against
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct InterestSchedule {
+    /// Pays interest and collects custody fees
+    pub account: String,
+    /// By asset code
+    rates: BTreeMap<String, Rates>,
+}
+
+impl Default for InterestSchedule {
+    /// No interest or fees at all
+    fn default() -> Self {
+        InterestSchedule::new(DEFAULT_INTEREST_ACCOUNT, [])
+    }
+}
+
+impl InterestSchedule {
+    /// Creates a new schedule from the rates of each asset code. Assets without rates don't
+    /// accrue anything.
+    pub fn new(account: &str, rates: impl IntoIterator<Item = (String, Rates)>) -> Self {
+        InterestSchedule {
+            account: account.to_string(),
+            rates: rates.into_iter().collect(),
+        }
+    }
+
+    /// The rates of the asset with `code`
+    pub fn rates(&self, code: &str) -> Option<&Rates> {
+        self.rates.get(code)
+    }
+
+    /// Every asset code with rates and its rates, ordered by code
+    pub fn all_rates(&self) -> Vec<(&str, &Rates)> {
+        self.rates
+            .iter()
+            .map(|(code, rates)| (code.as_str(), rates))
+            .collect()
+    }
+}
+
+/// An amount of a balance accrued as `kind`, in fractions of [`ACCRUAL_SCALE`]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Accrual {
+    pub account: String,
+    pub kind: AccrualKind,
+    pub amount: u128,
+}
+
+/// What `balance` accrues in `days` at an annual rate of `bps`, in fractions of [`ACCRUAL_SCALE`]
+pub fn accrual(balance: u64, bps: u32, days: u64) -> u128 {
+    balance as u128 * bps as u128 * days as u128
+}
+
+/// The whole minor units of an `accrued` amount. Amounts beyond a u64 are capped, the rest stays
+/// accrued.
+pub fn whole_units(accrued: u128) -> u64 {
+    u64::try_from(accrued / ACCRUAL_SCALE).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_accrual_is_exact() {
+        // 5% on 1000 minor units a day is 0.1369... minor units, a year of it is exactly 50
+        let daily = accrual(1_000, 500, 1);
+        assert_eq!(whole_units(daily), 0);
+        assert_eq!(whole_units(daily * 365), 50);
+        assert_eq!(accrual(1_000, 500, 365), daily * 365);
+        assert_eq!(whole_units(ACCRUAL_SCALE * 3 - 1), 2);
+        assert_eq!(whole_units(u128::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn test_InterestSchedule_rates_by_asset() {
+        let rates = Rates {
+            interest_bps: 200,
+            custody_fee_bps: 10,
+            overdraft_bps: 1_000,
+        };
+        let schedule = InterestSchedule::new("HOUSE", [("USD".to_string(), rates)]);
+        assert_eq!(
+            schedule.rates("USD").map(|r| r.bps(AccrualKind::Interest)),
+            Some(200)
+        );
+        assert_eq!(schedule.rates("BTC"), None);
+        assert_eq!(
+            InterestSchedule::default().account,
+            DEFAULT_INTEREST_ACCOUNT
+        );
+    }
+}
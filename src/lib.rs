@@ -0,0 +1,23 @@
+pub mod accounting;
+pub mod amount;
+pub mod clock;
+pub mod codec;
+pub mod core;
+pub mod double_entry;
+pub mod errors;
+pub mod escrow;
+pub mod fees;
+pub mod idempotency;
+pub mod interest;
+pub mod limits;
+pub mod margin;
+pub mod risk;
+pub mod schedule;
+pub mod sequencer;
+pub mod session;
+pub mod snapshot;
+pub mod state_hash;
+pub mod statement;
+pub mod trading_platform;
+pub mod tx;
+pub mod wal;
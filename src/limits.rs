@@ -0,0 +1,337 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::clock::{Timestamp, DAY};
+
+/// The longest [`Window`]. Withdrawals are kept around this long, so any limit set later sees
+/// all of the usage in its window.
+pub const MAX_WINDOW: Timestamp = 365 * DAY;
+
+/// The period that a [`Cap`] applies to
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Window {
+    /// The current calendar day (UTC), resets at midnight
+    Day,
+    /// The given duration up to now, at most [`MAX_WINDOW`]
+    Rolling(Timestamp),
+}
+
+impl Window {
+    /// The first timestamp that falls into the window at `now`
+    pub fn start(&self, now: Timestamp) -> Timestamp {
+        match self {
+            Window::Day => now - now % DAY,
+            Window::Rolling(length) => (now + 1).saturating_sub((*length).min(MAX_WINDOW)),
+        }
+    }
+}
+
+/// A maximum total amount per [`Window`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Cap {
+    /// Max total
+    pub amount: u64,
+    /// The period the total is taken over
+    pub window: Window,
+}
+
+/// Velocity controls for a single account. `None` means no limit.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Limits {
+    /// Max total withdrawn per window
+    pub withdrawal: Option<Cap>,
+    /// Max amount of a single `send`
+    pub max_transfer: Option<u64>,
+    /// Max total sent per calendar day
+    pub daily_send: Option<u64>,
+}
+
+/// The limit that an operation would exceed, with its configured value
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Limit {
+    /// Total withdrawals in the window
+    Withdrawal(u64),
+    /// Size of a single transfer
+    MaxTransfer(u64),
+    /// Total sent today
+    DailySend(u64),
+}
+
+/// What an account withdrew and sent recently
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Usage {
+    /// (timestamp, amount) of every withdrawal that may still count, oldest first
+    pub withdrawals: VecDeque<(Timestamp, u64)>,
+    /// (timestamp, amount) of every send that may still count, oldest first
+    pub sends: VecDeque<(Timestamp, u64)>,
+}
+
+impl Usage {
+    /// Total withdrawn since the start of `window` at `now`
+    pub fn withdrawn(&self, window: Window, now: Timestamp) -> u64 {
+        total_since(&self.withdrawals, window.start(now))
+    }
+
+    /// Total sent since the start of the calendar day at `now`
+    pub fn sent_today(&self, now: Timestamp) -> u64 {
+        total_since(&self.sends, Window::Day.start(now))
+    }
+}
+
+/// Total of the `entries` at or after `start`
+fn total_since(entries: &VecDeque<(Timestamp, u64)>, start: Timestamp) -> u64 {
+    entries
+        .iter()
+        .filter(|(ts, _)| *ts >= start)
+        .fold(0, |total, (_, amount)| total.saturating_add(*amount))
+}
+
+/// Drops the `entries` that no window of `length` at `now` reaches anymore
+fn prune(entries: &mut VecDeque<(Timestamp, u64)>, length: Timestamp, now: Timestamp) {
+    while matches!(entries.front(), Some((ts, _)) if ts.saturating_add(length) <= now) {
+        entries.pop_front();
+    }
+}
+
+/// Limits and recent usage of every account
+#[derive(Clone, Debug, Default)]
+pub struct LimitTracker {
+    limits: BTreeMap<String, Limits>,
+    usage: BTreeMap<String, Usage>,
+}
+
+impl LimitTracker {
+    /// The limits of `account`, no limits unless they were set
+    pub fn limits(&self, account: &str) -> Option<&Limits> {
+        self.limits.get(account)
+    }
+
+    /// Replaces the limits of `account`
+    pub fn set_limits(&mut self, account: &str, limits: Limits) {
+        self.limits.insert(account.to_string(), limits);
+    }
+
+    /// The recent usage of `account`
+    pub fn usage(&self, account: &str) -> Option<&Usage> {
+        self.usage.get(account)
+    }
+
+    /// Every account with limits and its limits, ordered by account name
+    pub fn all_limits(&self) -> Vec<(&str, &Limits)> {
+        self.limits
+            .iter()
+            .map(|(account, limits)| (account.as_str(), limits))
+            .collect()
+    }
+
+    /// Every account that withdrew or sent something recently and its usage, ordered by account
+    /// name
+    pub fn all_usage(&self) -> Vec<(&str, &Usage)> {
+        self.usage
+            .iter()
+            .map(|(account, usage)| (account.as_str(), usage))
+            .collect()
+    }
+
+    /// Replaces the recent usage of `account`, e.g. with the one of a snapshot
+    pub fn restore_usage(&mut self, account: &str, usage: Usage) {
+        self.usage.insert(account.to_string(), usage);
+    }
+
+    /// Whether `account` may withdraw `amount` at `now`
+    /// # Errors
+    /// The exceeded limit and the remaining allowance
+    pub fn check_withdrawal(
+        &self,
+        account: &str,
+        amount: u64,
+        now: Timestamp,
+    ) -> Result<(), (Limit, u64)> {
+        let Some(cap) = self.limits(account).and_then(|l| l.withdrawal) else {
+            return Ok(());
+        };
+        let used = self
+            .usage(account)
+            .map(|u| u.withdrawn(cap.window, now))
+            .unwrap_or(0);
+        check(Limit::Withdrawal(cap.amount), cap.amount, used, amount)
+    }
+
+    /// Whether `account` may send `amount` at `now`
+    /// # Errors
+    /// The exceeded limit and the remaining allowance
+    pub fn check_send(
+        &self,
+        account: &str,
+        amount: u64,
+        now: Timestamp,
+    ) -> Result<(), (Limit, u64)> {
+        let Some(limits) = self.limits(account) else {
+            return Ok(());
+        };
+        if let Some(max) = limits.max_transfer {
+            check(Limit::MaxTransfer(max), max, 0, amount)?;
+        }
+        if let Some(cap) = limits.daily_send {
+            let used = self.usage(account).map(|u| u.sent_today(now)).unwrap_or(0);
+            check(Limit::DailySend(cap), cap, used, amount)?;
+        }
+        Ok(())
+    }
+
+    /// Counts a withdrawal of `amount` at `now` against the limits of `account`
+    pub fn record_withdrawal(&mut self, account: &str, amount: u64, now: Timestamp) {
+        let usage = self.usage.entry(account.to_string()).or_default();
+        prune(&mut usage.withdrawals, MAX_WINDOW, now);
+        usage.withdrawals.push_back((now, amount));
+    }
+
+    /// Counts a send of `amount` at `now` against the limits of `account`
+    pub fn record_send(&mut self, account: &str, amount: u64, now: Timestamp) {
+        let usage = self.usage.entry(account.to_string()).or_default();
+        prune(&mut usage.sends, DAY, now);
+        usage.sends.push_back((now, amount));
+    }
+}
+
+/// Fails with the remaining allowance if `amount` on top of `used` goes over `cap`
+fn check(limit: Limit, cap: u64, used: u64, amount: u64) -> Result<(), (Limit, u64)> {
+    let remaining = cap.saturating_sub(used);
+    if amount > remaining {
+        Err((limit, remaining))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_Window_start() {
+        assert_eq!(Window::Day.start(DAY + 5), DAY);
+        assert_eq!(Window::Day.start(DAY), DAY);
+        assert_eq!(Window::Rolling(10).start(100), 91);
+        assert_eq!(Window::Rolling(DAY).start(5), 0);
+        assert_eq!(
+            Window::Rolling(2 * MAX_WINDOW).start(MAX_WINDOW * 3),
+            MAX_WINDOW * 2 + 1
+        );
+    }
+
+    #[test]
+    fn test_LimitTracker_withdrawal_windows() {
+        let mut tracker = LimitTracker::default();
+        let rolling = Cap {
+            amount: 100,
+            window: Window::Rolling(DAY),
+        };
+        tracker.set_limits(
+            "ALICE",
+            Limits {
+                withdrawal: Some(rolling),
+                ..Limits::default()
+            },
+        );
+        let now = DAY - 10;
+        tracker.record_withdrawal("ALICE", 60, now);
+        assert_eq!(
+            tracker.check_withdrawal("ALICE", 41, now),
+            Err((Limit::Withdrawal(100), 40))
+        );
+        assert_eq!(tracker.check_withdrawal("ALICE", 40, now), Ok(()));
+        // a new calendar day doesn't reset a rolling window
+        assert!(tracker.check_withdrawal("ALICE", 41, DAY + 1).is_err());
+        assert_eq!(tracker.check_withdrawal("ALICE", 100, now + DAY), Ok(()));
+
+        // but it resets a daily one
+        tracker.set_limits(
+            "ALICE",
+            Limits {
+                withdrawal: Some(Cap {
+                    window: Window::Day,
+                    ..rolling
+                }),
+                ..Limits::default()
+            },
+        );
+        assert_eq!(tracker.check_withdrawal("ALICE", 100, DAY + 1), Ok(()));
+        // accounts without limits can withdraw anything
+        assert_eq!(tracker.check_withdrawal("BOB", u64::MAX, now), Ok(()));
+    }
+
+    #[test]
+    fn test_LimitTracker_counts_usage_from_before_the_limit() {
+        let mut tracker = LimitTracker::default();
+        tracker.record_withdrawal("ALICE", 100, 0);
+        tracker.record_withdrawal("ALICE", 100, 2 * DAY);
+        tracker.set_limits(
+            "ALICE",
+            Limits {
+                withdrawal: Some(Cap {
+                    amount: 250,
+                    window: Window::Rolling(7 * DAY),
+                }),
+                ..Limits::default()
+            },
+        );
+        assert_eq!(
+            tracker.check_withdrawal("ALICE", 100, 2 * DAY),
+            Err((Limit::Withdrawal(250), 50))
+        );
+        // a shorter limit in between doesn't forget what the longer one needs
+        tracker.set_limits(
+            "ALICE",
+            Limits {
+                withdrawal: Some(Cap {
+                    amount: 250,
+                    window: Window::Day,
+                }),
+                ..Limits::default()
+            },
+        );
+        tracker.record_withdrawal("ALICE", 10, 3 * DAY);
+        tracker.set_limits(
+            "ALICE",
+            Limits {
+                withdrawal: Some(Cap {
+                    amount: 250,
+                    window: Window::Rolling(7 * DAY),
+                }),
+                ..Limits::default()
+            },
+        );
+        assert_eq!(
+            tracker.check_withdrawal("ALICE", 100, 3 * DAY),
+            Err((Limit::Withdrawal(250), 40))
+        );
+        assert_eq!(tracker.check_withdrawal("ALICE", 100, 7 * DAY), Ok(()));
+    }
+
+    #[test]
+    fn test_LimitTracker_send_limits() {
+        let mut tracker = LimitTracker::default();
+        tracker.set_limits(
+            "ALICE",
+            Limits {
+                max_transfer: Some(50),
+                daily_send: Some(80),
+                ..Limits::default()
+            },
+        );
+        assert_eq!(
+            tracker.check_send("ALICE", 51, 0),
+            Err((Limit::MaxTransfer(50), 50))
+        );
+        tracker.record_send("ALICE", 50, 0);
+        assert_eq!(
+            tracker.check_send("ALICE", 31, 1),
+            Err((Limit::DailySend(80), 30))
+        );
+        assert_eq!(tracker.check_send("ALICE", 30, 1), Ok(()));
+        assert_eq!(tracker.check_send("ALICE", 50, DAY), Ok(()));
+    }
+}
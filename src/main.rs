@@ -1,9 +1,10 @@
-use std::io;
-mod accounting;
-mod core;
-mod errors;
-mod tx;
-use crate::accounting::Accounts;
+use learning_data_structures_and_borrowing_with_lending_in_rust_1::{
+    amount::Amount, clock::SystemClock, trading_platform::TradingPlatform, wal::FsyncPolicy,
+};
+use std::{env, io, process};
+
+/// Where the ledger is persisted unless a path is given as the first argument
+const DEFAULT_LOG: &str = "ledger.wal";
 
 fn read_from_stdin(label: &str) -> String {
     let mut buffer = String::new();
@@ -17,28 +18,54 @@ fn read_from_stdin(label: &str) -> String {
 fn main() {
     println!("Hello, accounting world!");
 
-    let mut ledger = Accounts::new();
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| DEFAULT_LOG.to_string());
+    let mut ledger =
+        TradingPlatform::open(&path, FsyncPolicy::Always, SystemClock).unwrap_or_else(|e| {
+            eprintln!("Couldn't open the ledger at '{}': {:?}", path, e);
+            process::exit(2);
+        });
+    println!("Persisting to '{}'", path);
     loop {
         let input = read_from_stdin(
-            "Choose operation [deposit, withdraw, send, print, quit], confirm with return:",
+            "Choose operation [open, deposit, withdraw, send, print, journal, quit], confirm with return:",
         );
         match input.as_str() {
+            "open" => {
+                let account = read_from_stdin("Account:");
+                match ledger.open_account(&account) {
+                    Ok(tx) => println!("Opened account '{}': {:?}", account, tx),
+                    Err(e) => eprintln!("Open failed: {:?}", e),
+                }
+            }
             "deposit" => {
                 let account = read_from_stdin("Account:");
 
-                let raw_amount = read_from_stdin("Amount:").parse();
+                let raw_amount =
+                    Amount::parse(&read_from_stdin("Amount:"), ledger.market().quote.scale);
                 if let Ok(amount) = raw_amount {
-                    let _ = ledger.deposit(&account, amount);
-                    println!("Deposited {} into account '{}'", amount, account)
+                    match ledger.deposit_amount(&account, amount) {
+                        Ok(tx) => {
+                            println!("Deposited {} into account '{}': {:?}", amount, account, tx)
+                        }
+                        Err(e) => eprintln!("Deposit failed: {:?}", e),
+                    }
                 } else {
                     eprintln!("Not a number: '{:?}'", raw_amount);
                 }
             }
             "withdraw" => {
                 let account = read_from_stdin("Account:");
-                let raw_amount = read_from_stdin("Amount:").parse();
+                let raw_amount =
+                    Amount::parse(&read_from_stdin("Amount:"), ledger.market().quote.scale);
                 if let Ok(amount) = raw_amount {
-                    let _ = ledger.withdraw(&account, amount);
+                    match ledger.withdraw_amount(&account, amount) {
+                        Ok(tx) => {
+                            println!("Withdrew {} from account '{}': {:?}", amount, account, tx)
+                        }
+                        Err(e) => eprintln!("Withdrawal failed: {:?}", e),
+                    }
                 } else {
                     eprintln!("Not a number: '{:?}'", raw_amount);
                 }
@@ -46,9 +73,16 @@ fn main() {
             "send" => {
                 let sender = read_from_stdin("Sender Account:");
                 let recipient = read_from_stdin("Recipient Account:");
-                let raw_amount = read_from_stdin("Amount:").parse();
+                let raw_amount =
+                    Amount::parse(&read_from_stdin("Amount:"), ledger.market().quote.scale);
                 if let Ok(amount) = raw_amount {
-                    let _ = ledger.send(&sender, &recipient, amount);
+                    match ledger.send_amount(&sender, &recipient, amount) {
+                        Ok(tx) => println!(
+                            "Sent {} from '{}' to '{}': {:?}",
+                            amount, sender, recipient, tx
+                        ),
+                        Err(e) => eprintln!("Send failed: {:?}", e),
+                    }
                 } else {
                     eprintln!("Not a number: '{:?}'", raw_amount);
                 }
@@ -56,6 +90,11 @@ fn main() {
             "print" => {
                 println!("The ledger: {:?}", ledger);
             }
+            "journal" => {
+                for tx in ledger.journal() {
+                    println!("{:?}", tx);
+                }
+            }
             "quit" => {
                 println!("Quitting...");
                 break;
@@ -0,0 +1,192 @@
+use crate::{amount::Market, core::Side, errors::ApplicationError, fees::BPS};
+
+/// Account that takes over liquidated positions unless configured otherwise
+pub const DEFAULT_LIQUIDATION_ACCOUNT: &str = "LIQUIDATION";
+
+/// How much equity an account needs for its positions, as a share of what they are worth at the
+/// mark price, and the account that takes over the positions of accounts that fall short
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MarginRequirements {
+    /// Takes over liquidated positions
+    pub account: String,
+    /// Needed to increase a position, in basis points
+    pub initial_bps: u32,
+    /// Needed to keep a position, below it the position is liquidated. In basis points.
+    pub maintenance_bps: u32,
+}
+
+impl MarginRequirements {
+    /// Creates new requirements, e.g. 1000 and 500 basis points for 10x leverage that is
+    /// liquidated at half of the initial margin
+    pub fn new(account: &str, initial_bps: u32, maintenance_bps: u32) -> Self {
+        MarginRequirements {
+            account: account.to_string(),
+            initial_bps,
+            maintenance_bps,
+        }
+    }
+
+    /// The equity needed to open positions worth `exposure`, rounded up
+    pub fn initial(&self, exposure: u128) -> u128 {
+        requirement(exposure, self.initial_bps)
+    }
+
+    /// The equity needed to keep positions worth `exposure`, rounded up
+    pub fn maintenance(&self, exposure: u128) -> u128 {
+        requirement(exposure, self.maintenance_bps)
+    }
+}
+
+fn requirement(exposure: u128, bps: u32) -> u128 {
+    (exposure * bps as u128).div_ceil(BPS as u128)
+}
+
+/// One side of a trade in the market `symbol`: `amount` minor units of the base asset bought or
+/// sold at `price` for `notional` minor units of the quote asset
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Fill {
+    pub symbol: String,
+    pub side: Side,
+    pub amount: u64,
+    pub price: u64,
+    pub notional: u64,
+}
+
+/// What an account holds of the base asset of a market, as the sum of its fills
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Position {
+    pub account: String,
+    /// The market, see [`Market::symbol`]
+    pub symbol: String,
+    /// Minor units of the base asset, negative for a short position
+    pub size: i128,
+    /// Minor units of the quote asset paid for the open size, negative for what a short
+    /// position received
+    pub cost: i128,
+    /// Profit (or loss) of the fills that reduced the position so far
+    pub realised_pnl: i128,
+}
+
+impl Position {
+    /// A position without any fills
+    pub fn new(account: &str, symbol: &str) -> Self {
+        Position {
+            account: account.to_string(),
+            symbol: symbol.to_string(),
+            size: 0,
+            cost: 0,
+            realised_pnl: 0,
+        }
+    }
+
+    /// Average price of the open size in minor units of the quote asset per whole unit of the base
+    /// asset of `market`, rounded down. `None` while there is no open size.
+    pub fn entry_price(&self, market: &Market) -> Option<u64> {
+        let price = (self.cost.unsigned_abs() * 10u128.pow(market.base.scale))
+            .checked_div(self.size.unsigned_abs())?;
+        Some(u64::try_from(price).unwrap_or(u64::MAX))
+    }
+
+    /// What the open size is worth at `mark`, negative for a short position. Without a mark price
+    /// it's valued at cost.
+    /// # Errors
+    /// The value doesn't fit
+    pub fn value(&self, mark: Option<u64>, market: &Market) -> Result<i128, ApplicationError> {
+        let Some(mark) = mark else {
+            return Ok(self.cost);
+        };
+        let size = u64::try_from(self.size.unsigned_abs()).map_err(|_| {
+            ApplicationError::InvalidAmount(format!("position of {} doesn't fit", self.size))
+        })?;
+        let value = market.notional(mark, size)? as i128;
+        Ok(if self.size < 0 { -value } else { value })
+    }
+
+    /// Profit (or loss) of the open size if it was closed at `mark`
+    /// # Errors
+    /// See [`Position::value`]
+    pub fn unrealised_pnl(
+        &self,
+        mark: Option<u64>,
+        market: &Market,
+    ) -> Result<i128, ApplicationError> {
+        Ok(self.value(mark, market)? - self.cost)
+    }
+
+    /// Adds a fill that bought or sold `amount` for `notional`. The part that reduces the position
+    /// realises its share of the cost, the rest opens it further or in the other direction.
+    /// Returns the realised profit (or loss).
+    pub fn fill(&mut self, side: Side, amount: u64, notional: u64) -> i128 {
+        let (amount, notional) = (amount as i128, notional as i128);
+        let (direction, paid) = match side {
+            Side::Buy => (1, notional),
+            Side::Sell => (-1, -notional),
+        };
+        let closed = if self.size.signum() == -direction {
+            amount.min(self.size.abs())
+        } else {
+            0
+        };
+        let mut realised = 0;
+        let mut paid_closed = 0;
+        if closed > 0 {
+            let cost_closed = self.cost * closed / self.size.abs();
+            paid_closed = paid * closed / amount;
+            realised = -(cost_closed + paid_closed);
+            self.cost -= cost_closed;
+            self.size += direction * closed;
+        }
+        if closed < amount {
+            self.size += direction * (amount - closed);
+            self.cost += paid - paid_closed;
+        }
+        self.realised_pnl += realised;
+        realised
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use crate::amount::Asset;
+
+    use super::*;
+
+    #[test]
+    fn test_Position_fill_realises_pnl() {
+        let market = Market::new(Asset::new("BTC", 2), Asset::new("USD", 0));
+        let mut position = Position::new("ALICE", &market.symbol());
+
+        // 2 BTC at 100, then 2 at 200: entry at 150
+        assert_eq!(position.fill(Side::Buy, 200, 200), 0);
+        assert_eq!(position.fill(Side::Buy, 200, 400), 0);
+        assert_eq!(position.entry_price(&market), Some(150));
+        assert_eq!(position.unrealised_pnl(Some(120), &market), Ok(-120));
+        assert_eq!(position.unrealised_pnl(None, &market), Ok(0));
+
+        // sell 1 at 180 realises 30, then 5 at 160 closes the rest and goes 2 short
+        assert_eq!(position.fill(Side::Sell, 100, 180), 30);
+        assert_eq!(position.fill(Side::Sell, 500, 800), 30);
+        assert_eq!((position.size, position.cost), (-200, -320));
+        assert_eq!(position.entry_price(&market), Some(160));
+        assert_eq!(position.value(Some(150), &market), Ok(-300));
+        assert_eq!(position.unrealised_pnl(Some(150), &market), Ok(20));
+
+        // buying back below the entry realises a profit on a short
+        assert_eq!(position.fill(Side::Buy, 200, 300), 20);
+        assert_eq!((position.size, position.cost), (0, 0));
+        assert_eq!(position.realised_pnl, 80);
+        assert_eq!(position.entry_price(&market), None);
+    }
+
+    #[test]
+    fn test_MarginRequirements_round_up() {
+        let margin = MarginRequirements::new(DEFAULT_LIQUIDATION_ACCOUNT, 1_000, 500);
+        assert_eq!(margin.initial(1_000), 100);
+        assert_eq!(margin.maintenance(1_000), 50);
+        assert_eq!(margin.maintenance(1), 1);
+        assert_eq!(margin.initial(0), 0);
+    }
+}
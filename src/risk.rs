@@ -0,0 +1,305 @@
+use std::fmt::Debug;
+
+use crate::{
+    amount::Market,
+    core::{Order, OrderStatus, Side},
+    fees::BPS,
+};
+
+/// Pre-trade limits of an account or a market. `None` means no limit.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RiskLimits {
+    /// Max amount of a single order
+    pub max_order_size: Option<u64>,
+    /// Max notional (price * amount) of a single order
+    pub max_notional: Option<u64>,
+    /// Max number of orders in the book at once
+    pub max_open_orders: Option<usize>,
+    /// Max size of the position, long or short, if every open order was filled
+    pub max_position: Option<u64>,
+    /// Max distance of the price from the reference price in basis points
+    pub max_deviation_bps: Option<u32>,
+    /// Whether buys, including the open ones, have to be covered by the balance and credit line.
+    /// On by default.
+    pub buying_power: bool,
+}
+
+impl Default for RiskLimits {
+    /// No limits, but buys have to be covered
+    fn default() -> Self {
+        RiskLimits {
+            max_order_size: None,
+            max_notional: None,
+            max_open_orders: None,
+            max_position: None,
+            max_deviation_bps: None,
+            buying_power: true,
+        }
+    }
+}
+
+/// Why a [`RiskCheck`] rejected an order, with the limit it would exceed
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rejection {
+    /// The amount is above the max order size
+    OrderSize(u64),
+    /// The notional is above the max notional
+    Notional(u64),
+    /// The signer has the max number of open orders already
+    OpenOrders(usize),
+    /// The position could grow beyond the max position
+    Position(u64),
+    /// The price is too far from the reference price (the reference price)
+    PriceDeviation(u64),
+    /// The buys of the signer would cost more than it has available (what it has available)
+    BuyingPower(u128),
+}
+
+/// What a [`RiskCheck`] gets to see of the signer and the market besides the order
+#[derive(Debug)]
+pub struct RiskContext<'a> {
+    /// Limits of the signer in the market
+    pub limits: &'a RiskLimits,
+    pub market: &'a Market,
+    /// What the order costs if it's filled completely
+    pub notional: u64,
+    /// Orders of the signer that are in the book
+    pub open_orders: &'a [&'a OrderStatus],
+    /// Size of the signer's position in the market, negative if it's short
+    pub position: i128,
+    /// The price orders are compared to, e.g. the mark price
+    pub reference_price: Option<u64>,
+    /// What the signer can spend: its balance plus what is left of its credit line
+    pub available: u128,
+}
+
+/// A single check that runs before an order reaches the matching engine
+pub trait RiskCheck: Debug {
+    /// Passes the `order` or rejects it with the reason
+    fn check(&self, order: &Order, context: &RiskContext) -> Result<(), Rejection>;
+}
+
+/// Every check of this module, in the order they run
+pub fn standard_checks() -> Vec<Box<dyn RiskCheck>> {
+    vec![
+        Box::new(MaxOrderSize),
+        Box::new(MaxNotional),
+        Box::new(MaxOpenOrders),
+        Box::new(MaxPosition),
+        Box::new(PriceDeviation),
+        Box::new(BuyingPower),
+    ]
+}
+
+/// Enforces [`RiskLimits::max_order_size`]
+#[derive(Debug)]
+pub struct MaxOrderSize;
+
+impl RiskCheck for MaxOrderSize {
+    fn check(&self, order: &Order, context: &RiskContext) -> Result<(), Rejection> {
+        match context.limits.max_order_size {
+            Some(max) if order.amount > max => Err(Rejection::OrderSize(max)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Enforces [`RiskLimits::max_notional`]
+#[derive(Debug)]
+pub struct MaxNotional;
+
+impl RiskCheck for MaxNotional {
+    fn check(&self, _: &Order, context: &RiskContext) -> Result<(), Rejection> {
+        match context.limits.max_notional {
+            Some(max) if context.notional > max => Err(Rejection::Notional(max)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Enforces [`RiskLimits::max_open_orders`]
+#[derive(Debug)]
+pub struct MaxOpenOrders;
+
+impl RiskCheck for MaxOpenOrders {
+    fn check(&self, _: &Order, context: &RiskContext) -> Result<(), Rejection> {
+        match context.limits.max_open_orders {
+            Some(max) if context.open_orders.len() >= max => Err(Rejection::OpenOrders(max)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Enforces [`RiskLimits::max_position`] on the side of the order. Orders that can only reduce
+/// the position pass.
+#[derive(Debug)]
+pub struct MaxPosition;
+
+impl RiskCheck for MaxPosition {
+    fn check(&self, order: &Order, context: &RiskContext) -> Result<(), Rejection> {
+        let Some(max) = context.limits.max_position else {
+            return Ok(());
+        };
+        let open = remaining_on(context.open_orders, &order.side) + order.amount as u128;
+        let exposure = match order.side {
+            Side::Buy => context.position.saturating_add_unsigned(open),
+            Side::Sell => context.position.saturating_sub_unsigned(open),
+        };
+        if exposure.unsigned_abs() > max as u128
+            && exposure.unsigned_abs() > context.position.unsigned_abs()
+        {
+            Err(Rejection::Position(max))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Enforces [`RiskLimits::max_deviation_bps`]. Without a reference price every price passes.
+#[derive(Debug)]
+pub struct PriceDeviation;
+
+impl RiskCheck for PriceDeviation {
+    fn check(&self, order: &Order, context: &RiskContext) -> Result<(), Rejection> {
+        let (Some(max), Some(reference)) =
+            (context.limits.max_deviation_bps, context.reference_price)
+        else {
+            return Ok(());
+        };
+        let deviation = order.price.abs_diff(reference) as u128 * BPS as u128;
+        if deviation > reference as u128 * max as u128 {
+            Err(Rejection::PriceDeviation(reference))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Enforces [`RiskLimits::buying_power`]: a buy and the open buys of the signer have to be
+/// covered by what it has available. Sells pass.
+#[derive(Debug)]
+pub struct BuyingPower;
+
+impl RiskCheck for BuyingPower {
+    fn check(&self, order: &Order, context: &RiskContext) -> Result<(), Rejection> {
+        if !context.limits.buying_power || order.side == Side::Sell {
+            return Ok(());
+        }
+        let open: u128 = context
+            .open_orders
+            .iter()
+            .filter(|o| o.side == Side::Buy)
+            .map(|o| {
+                // the notional of an open order fit when it was placed
+                context
+                    .market
+                    .notional(o.price, o.remaining())
+                    .unwrap_or(u64::MAX) as u128
+            })
+            .sum();
+        if open + context.notional as u128 > context.available {
+            Err(Rejection::BuyingPower(context.available))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Units of the open orders on `side` that haven't been traded
+fn remaining_on(orders: &[&OrderStatus], side: &Side) -> u128 {
+    orders
+        .iter()
+        .filter(|o| o.side == *side)
+        .map(|o| o.remaining() as u128)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use crate::core::OrderState;
+
+    use super::*;
+
+    fn order(side: Side, price: u64, amount: u64) -> Order {
+        Order {
+            price,
+            amount,
+            side,
+            signer: "ALICE".to_string(),
+        }
+    }
+
+    /// Runs every standard check against an order of ALICE with one open buy of 5 at 10
+    fn check(order: &Order, limits: &RiskLimits, position: i128) -> Result<(), Rejection> {
+        let market = Market::default();
+        let open = OrderStatus {
+            ordinal: 1,
+            signer: "ALICE".to_string(),
+            side: Side::Buy,
+            price: 10,
+            amount: 5,
+            filled: 0,
+            filled_notional: 0,
+            state: OrderState::New,
+        };
+        let context = RiskContext {
+            limits,
+            market: &market,
+            notional: market.notional(order.price, order.amount).unwrap(),
+            open_orders: &[&open],
+            position,
+            reference_price: Some(100),
+            available: 100,
+        };
+        standard_checks()
+            .iter()
+            .try_for_each(|c| c.check(order, &context))
+    }
+
+    #[test]
+    fn test_standard_checks_reject_with_the_limit() {
+        let buy = order(Side::Buy, 10, 5);
+        assert_eq!(check(&buy, &RiskLimits::default(), 0), Ok(()));
+
+        let limits = |f: fn(&mut RiskLimits)| {
+            let mut limits = RiskLimits::default();
+            f(&mut limits);
+            limits
+        };
+        let size = limits(|l| l.max_order_size = Some(4));
+        assert_eq!(check(&buy, &size, 0), Err(Rejection::OrderSize(4)));
+        let notional = limits(|l| l.max_notional = Some(49));
+        assert_eq!(check(&buy, &notional, 0), Err(Rejection::Notional(49)));
+        let open = limits(|l| l.max_open_orders = Some(1));
+        assert_eq!(check(&buy, &open, 0), Err(Rejection::OpenOrders(1)));
+
+        // the open buy counts towards a long position, a sell of a long position always passes
+        let position = limits(|l| l.max_position = Some(12));
+        assert_eq!(check(&buy, &position, 2), Ok(()));
+        assert_eq!(check(&buy, &position, 3), Err(Rejection::Position(12)));
+        assert_eq!(check(&order(Side::Sell, 10, 20), &position, 15), Ok(()));
+        assert_eq!(
+            check(&order(Side::Sell, 10, 13), &position, 0),
+            Err(Rejection::Position(12))
+        );
+
+        let band = limits(|l| l.max_deviation_bps = Some(1_000));
+        assert_eq!(check(&order(Side::Sell, 110, 1), &band, 0), Ok(()));
+        assert_eq!(
+            check(&order(Side::Buy, 89, 1), &band, 0),
+            Err(Rejection::PriceDeviation(100))
+        );
+
+        // 50 of open buys and 50 for this one are all there is
+        let power = limits(|l| l.buying_power = true);
+        assert_eq!(check(&buy, &power, 0), Ok(()));
+        assert_eq!(
+            check(&order(Side::Buy, 10, 6), &power, 0),
+            Err(Rejection::BuyingPower(100))
+        );
+        assert_eq!(check(&order(Side::Sell, 10, 100), &power, 0), Ok(()));
+    }
+}
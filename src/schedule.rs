@@ -0,0 +1,221 @@
+use crate::clock::{Timestamp, DAY, HOUR};
+
+/// How often a scheduled transfer is executed
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Recurrence {
+    /// Only at the start
+    Once,
+    /// Every day at the time of the start
+    Daily,
+    /// Every week on the weekday and at the time of the start
+    Weekly,
+    /// Every month on the day and at the time of the start. In shorter months, on their last day.
+    Monthly,
+}
+
+impl Recurrence {
+    /// When occurrence `n` of a schedule that starts at `start` is due, `None` if there is none
+    pub fn occurrence(&self, start: Timestamp, n: u64) -> Option<Timestamp> {
+        match self {
+            Recurrence::Once => (n == 0).then_some(start),
+            Recurrence::Daily => n.checked_mul(DAY)?.checked_add(start),
+            Recurrence::Weekly => n.checked_mul(7 * DAY)?.checked_add(start),
+            Recurrence::Monthly => add_months(start, n),
+        }
+    }
+}
+
+/// `timestamp` moved `months` calendar months (UTC) ahead, on the last day of the month if it
+/// is shorter
+fn add_months(timestamp: Timestamp, months: u64) -> Option<Timestamp> {
+    let (year, month, day) = civil_from_days(timestamp / DAY);
+    let months = (month as u64 - 1).checked_add(months)?;
+    let year = year.checked_add(months / 12)?;
+    let month = (months % 12) as u32 + 1;
+    let day = day.min(days_in_month(year, month));
+    days_from_civil(year, month, day)
+        .checked_mul(DAY)?
+        .checked_add(timestamp % DAY)
+}
+
+fn days_in_month(year: u64, month: u32) -> u32 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// (year, month, day) of the day `days` after 1970-01-01
+fn civil_from_days(days: u64) -> (u64, u32, u32) {
+    // shift the epoch to 0000-03-01, so that leap days are at the end of a year
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Days between 1970-01-01 and `year`-`month`-`day`, which isn't before it
+fn days_from_civil(year: u64, month: u32, day: u32) -> u64 {
+    let year = year - u64::from(month <= 2);
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 } as u64;
+    let day_of_year = (153 * shifted_month + 2) / 5 + day as u64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// What happens when a scheduled transfer fails
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RetryPolicy {
+    /// How many times a failed occurrence is attempted again before it is skipped
+    pub max_retries: u32,
+    /// Time between a failed attempt and the next one
+    pub delay: Timestamp,
+}
+
+impl Default for RetryPolicy {
+    /// Three more attempts, an hour apart
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            delay: HOUR,
+        }
+    }
+}
+
+/// A transfer from `sender` to `recipient` that is executed at the times of its [`Recurrence`]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Schedule {
+    /// Sequence number of the transaction that created it
+    pub id: u64,
+    pub sender: String,
+    pub recipient: String,
+    pub amount: u64,
+    /// When the first occurrence is due
+    pub start: Timestamp,
+    pub recurrence: Recurrence,
+    pub retry: RetryPolicy,
+    /// Number of occurrences that were executed or skipped
+    pub runs: u64,
+    /// Failed attempts at the current occurrence
+    pub attempts: u32,
+    /// When the next attempt is due
+    pub next: Timestamp,
+}
+
+impl Schedule {
+    /// Whether an attempt is due at `now`
+    pub fn is_due(&self, now: Timestamp) -> bool {
+        self.next <= now
+    }
+
+    /// Moves on after the current occurrence was executed. Returns whether there are more.
+    pub fn succeeded(&mut self) -> bool {
+        self.next_occurrence()
+    }
+
+    /// Schedules a retry after an attempt at `now` failed, or skips the occurrence once the
+    /// retries are used up. Returns whether there are more attempts.
+    pub fn failed(&mut self, now: Timestamp) -> bool {
+        if self.attempts < self.retry.max_retries {
+            self.attempts += 1;
+            self.next = now.saturating_add(self.retry.delay);
+            true
+        } else {
+            self.next_occurrence()
+        }
+    }
+
+    fn next_occurrence(&mut self) -> bool {
+        self.runs += 1;
+        self.attempts = 0;
+        match self.recurrence.occurrence(self.start, self.runs) {
+            Some(next) => {
+                self.next = next;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    /// 2024-01-31 09:00 UTC
+    const JAN_31: Timestamp = 1_706_691_600_000;
+
+    #[test]
+    fn test_Recurrence_occurrence() {
+        assert_eq!(Recurrence::Once.occurrence(5, 0), Some(5));
+        assert_eq!(Recurrence::Once.occurrence(5, 1), None);
+        assert_eq!(Recurrence::Daily.occurrence(5, 2), Some(5 + 2 * DAY));
+        assert_eq!(Recurrence::Weekly.occurrence(5, 1), Some(5 + 7 * DAY));
+        assert_eq!(Recurrence::Daily.occurrence(5, u64::MAX), None);
+
+        // the last day of shorter months, in a leap year
+        let monthly = |n| Recurrence::Monthly.occurrence(JAN_31, n).unwrap();
+        assert_eq!(monthly(0), JAN_31);
+        assert_eq!(monthly(1), JAN_31 + 29 * DAY);
+        assert_eq!(monthly(2), JAN_31 + 60 * DAY);
+        assert_eq!(monthly(3), JAN_31 + 90 * DAY);
+        assert_eq!(monthly(12), JAN_31 + 366 * DAY);
+        assert_eq!(civil_from_days(monthly(13) / DAY), (2025, 2, 28));
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+    }
+
+    #[test]
+    fn test_Schedule_retries_then_skips() {
+        let mut schedule = Schedule {
+            id: 1,
+            sender: "ALICE".to_string(),
+            recipient: "BOB".to_string(),
+            amount: 10,
+            start: 0,
+            recurrence: Recurrence::Daily,
+            retry: RetryPolicy {
+                max_retries: 1,
+                delay: 10,
+            },
+            runs: 0,
+            attempts: 0,
+            next: 0,
+        };
+        assert!(schedule.is_due(0));
+        assert!(schedule.failed(0));
+        assert_eq!((schedule.attempts, schedule.next), (1, 10));
+        assert!(!schedule.is_due(9));
+        // out of retries, on to the next day
+        assert!(schedule.failed(10));
+        assert_eq!(
+            (schedule.runs, schedule.attempts, schedule.next),
+            (1, 0, DAY)
+        );
+        assert!(schedule.succeeded());
+        assert_eq!((schedule.runs, schedule.next), (2, 2 * DAY));
+
+        schedule.recurrence = Recurrence::Once;
+        assert!(!schedule.succeeded());
+    }
+}
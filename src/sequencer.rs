@@ -0,0 +1,400 @@
+use std::{fmt, str::FromStr};
+
+use crate::{
+    clock::{Clock, ManualClock, Timestamp},
+    core::{Order, PartialOrder, Receipt, Side},
+    errors::ApplicationError,
+    trading_platform::TradingPlatform,
+    tx::Tx,
+};
+
+/// An input to the [`TradingPlatform`]. Together with its sequence number this is everything
+/// that can change the platform's state, including the passing of time.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Command {
+    /// [`TradingPlatform::open_account`]
+    Open { signer: String },
+    /// [`TradingPlatform::freeze`]
+    Freeze { signer: String },
+    /// [`TradingPlatform::unfreeze`]
+    Unfreeze { signer: String },
+    /// [`TradingPlatform::close_account`]
+    Close { signer: String },
+    /// [`TradingPlatform::deposit`]
+    Deposit { signer: String, amount: u64 },
+    /// [`TradingPlatform::withdraw`]
+    Withdraw { signer: String, amount: u64 },
+    /// [`TradingPlatform::send`]
+    Send {
+        sender: String,
+        recipient: String,
+        amount: u64,
+    },
+    /// [`TradingPlatform::order`]
+    Order(Order),
+    /// [`TradingPlatform::cancel`]
+    Cancel { ordinal: u64 },
+    /// Moves the clock to `now`
+    Tick { now: Timestamp },
+}
+
+/// What a [`Command`] returned when it succeeded
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Reply {
+    /// The transaction of a deposit, withdrawal, transfer or status change
+    Tx(Tx),
+    /// The receipt of an order
+    Receipt(Receipt),
+    /// The order that was cancelled
+    Cancelled(PartialOrder),
+    /// The time after a tick
+    Ticked(Timestamp),
+}
+
+/// The outcome of the command with number `sequence`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Output {
+    pub sequence: u64,
+    pub result: Result<Reply, ApplicationError>,
+}
+
+/// Runs a [`TradingPlatform`] as a state machine over a stream of sequenced [`Command`]s. Time
+/// only moves with [`Command::Tick`], so the same commands always produce the same [`Output`]s.
+#[derive(Debug)]
+pub struct Sequencer {
+    platform: TradingPlatform,
+    clock: ManualClock,
+    /// Sequence number of the last command
+    sequence: u64,
+}
+
+impl Default for Sequencer {
+    fn default() -> Self {
+        Sequencer::new()
+    }
+}
+
+impl Sequencer {
+    /// Creates an empty platform with its clock at 0
+    pub fn new() -> Self {
+        let clock = ManualClock::new(0);
+        Sequencer {
+            platform: TradingPlatform::with_clock(clock.clone()),
+            clock,
+            sequence: 0,
+        }
+    }
+
+    /// The platform in its current state
+    pub fn platform(&self) -> &TradingPlatform {
+        &self.platform
+    }
+
+    /// Applies the next command. Commands have to be numbered 1, 2, 3, ...
+    /// # Errors
+    /// `sequence` isn't the next number. The command is ignored.
+    pub fn handle(&mut self, sequence: u64, command: Command) -> Result<Output, ApplicationError> {
+        if sequence != self.sequence + 1 {
+            return Err(ApplicationError::OutOfSequence(sequence));
+        }
+        self.sequence = sequence;
+        let result = match command {
+            Command::Open { signer } => self.platform.open_account(&signer).map(Reply::Tx),
+            Command::Freeze { signer } => self.platform.freeze(&signer).map(Reply::Tx),
+            Command::Unfreeze { signer } => self.platform.unfreeze(&signer).map(Reply::Tx),
+            Command::Close { signer } => self.platform.close_account(&signer).map(Reply::Tx),
+            Command::Deposit { signer, amount } => {
+                self.platform.deposit(&signer, amount).map(Reply::Tx)
+            }
+            Command::Withdraw { signer, amount } => {
+                self.platform.withdraw(&signer, amount).map(Reply::Tx)
+            }
+            Command::Send {
+                sender,
+                recipient,
+                amount,
+            } => self
+                .platform
+                .send(&sender, &recipient, amount)
+                .map(Reply::Tx),
+            Command::Order(order) => self.platform.order(order).map(Reply::Receipt),
+            Command::Cancel { ordinal } => self.platform.cancel(ordinal).map(Reply::Cancelled),
+            Command::Tick { now } => {
+                // time doesn't run backwards
+                self.clock.set(now.max(self.clock.now()));
+                Ok(Reply::Ticked(self.clock.now()))
+            }
+        };
+        Ok(Output { sequence, result })
+    }
+
+    /// Applies every command in order and collects the outputs
+    /// # Errors
+    /// A command is out of sequence
+    pub fn run(
+        &mut self,
+        commands: impl IntoIterator<Item = (u64, Command)>,
+    ) -> Result<Vec<Output>, ApplicationError> {
+        commands
+            .into_iter()
+            .map(|(sequence, command)| self.handle(sequence, command))
+            .collect()
+    }
+}
+
+/// The first place where a replay didn't produce the recorded output
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Divergence {
+    /// Position of the output, counting from 0
+    pub index: usize,
+    /// The recorded line, `None` if the recording is shorter
+    pub expected: Option<String>,
+    /// The replayed line, `None` if the replay is shorter
+    pub actual: Option<String>,
+}
+
+/// Runs `commands` on a fresh [`Sequencer`] and compares the outputs line by line with the
+/// `recorded` ones. Returns `None` if they are identical.
+/// # Errors
+/// A command is out of sequence
+pub fn diff_replay<'a>(
+    commands: impl IntoIterator<Item = (u64, Command)>,
+    recorded: impl IntoIterator<Item = &'a str>,
+) -> Result<Option<Divergence>, ApplicationError> {
+    let actual: Vec<String> = Sequencer::new()
+        .run(commands)?
+        .iter()
+        .map(|o| o.to_string())
+        .collect();
+    let mut recorded = recorded.into_iter();
+    let mut actual = actual.into_iter();
+    let mut index = 0;
+    loop {
+        match (recorded.next(), actual.next()) {
+            (None, None) => return Ok(None),
+            (Some(expected), Some(actual)) if expected == actual => index += 1,
+            (expected, actual) => {
+                return Ok(Some(Divergence {
+                    index,
+                    expected: expected.map(str::to_string),
+                    actual,
+                }))
+            }
+        }
+    }
+}
+
+/// Parses a command journal with one `<sequence> <command>` per line. Empty lines and lines
+/// starting with `#` are skipped.
+/// # Errors
+/// The line number and reason of the first line that isn't a command
+pub fn parse_journal(journal: &str) -> Result<Vec<(u64, Command)>, String> {
+    journal
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(n, line)| {
+            let (sequence, command) = line
+                .trim()
+                .split_once(' ')
+                .ok_or_else(|| format!("line {}: expected '<sequence> <command>'", n + 1))?;
+            let sequence = sequence
+                .parse()
+                .map_err(|_| format!("line {}: invalid sequence '{}'", n + 1, sequence))?;
+            let command = command
+                .parse()
+                .map_err(|e| format!("line {}: {}", n + 1, e))?;
+            Ok((sequence, command))
+        })
+        .collect()
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Open { signer } => write!(f, "open {}", signer),
+            Command::Freeze { signer } => write!(f, "freeze {}", signer),
+            Command::Unfreeze { signer } => write!(f, "unfreeze {}", signer),
+            Command::Close { signer } => write!(f, "close {}", signer),
+            Command::Deposit { signer, amount } => write!(f, "deposit {} {}", signer, amount),
+            Command::Withdraw { signer, amount } => write!(f, "withdraw {} {}", signer, amount),
+            Command::Send {
+                sender,
+                recipient,
+                amount,
+            } => write!(f, "send {} {} {}", sender, recipient, amount),
+            Command::Order(order) => {
+                let side = match order.side {
+                    Side::Buy => "buy",
+                    Side::Sell => "sell",
+                };
+                write!(
+                    f,
+                    "order {} {} {} {}",
+                    order.signer, side, order.price, order.amount
+                )
+            }
+            Command::Cancel { ordinal } => write!(f, "cancel {}", ordinal),
+            Command::Tick { now } => write!(f, "tick {}", now),
+        }
+    }
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    /// Parses what [`Command`]'s `Display` writes, e.g. `order ALICE buy 10 2`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let number = |word: &str| {
+            word.parse::<u64>()
+                .map_err(|_| format!("not a number: '{}'", word))
+        };
+        match words.as_slice() {
+            ["open", signer] => Ok(Command::Open {
+                signer: signer.to_string(),
+            }),
+            ["freeze", signer] => Ok(Command::Freeze {
+                signer: signer.to_string(),
+            }),
+            ["unfreeze", signer] => Ok(Command::Unfreeze {
+                signer: signer.to_string(),
+            }),
+            ["close", signer] => Ok(Command::Close {
+                signer: signer.to_string(),
+            }),
+            ["deposit", signer, amount] => Ok(Command::Deposit {
+                signer: signer.to_string(),
+                amount: number(amount)?,
+            }),
+            ["withdraw", signer, amount] => Ok(Command::Withdraw {
+                signer: signer.to_string(),
+                amount: number(amount)?,
+            }),
+            ["send", sender, recipient, amount] => Ok(Command::Send {
+                sender: sender.to_string(),
+                recipient: recipient.to_string(),
+                amount: number(amount)?,
+            }),
+            ["order", signer, side, price, amount] => {
+                let side = match *side {
+                    "buy" => Side::Buy,
+                    "sell" => Side::Sell,
+                    other => return Err(format!("not a side: '{}'", other)),
+                };
+                Ok(Command::Order(Order {
+                    price: number(price)?,
+                    amount: number(amount)?,
+                    side,
+                    signer: signer.to_string(),
+                }))
+            }
+            ["cancel", ordinal] => Ok(Command::Cancel {
+                ordinal: number(ordinal)?,
+            }),
+            ["tick", now] => Ok(Command::Tick { now: number(now)? }),
+            _ => Err(format!("unknown command: '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.result {
+            Ok(reply) => write!(f, "{} ok {:?}", self.sequence, reply),
+            Err(e) => write!(f, "{} err {:?}", self.sequence, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    const JOURNAL: &str = "
+        # fund both sides
+        1 open ALICE
+        2 open BOB
+        3 deposit ALICE 100
+        4 deposit BOB 100
+        5 tick 1000
+        6 order ALICE sell 10 3
+        7 order BOB buy 10 2
+        8 withdraw CAROL 1
+        9 cancel 1
+        10 send BOB ALICE 5
+        11 freeze BOB
+        12 unfreeze BOB
+    ";
+
+    #[test]
+    fn test_Command_display_round_trips() {
+        for command in parse_journal(JOURNAL).unwrap().into_iter().map(|(_, c)| c) {
+            assert_eq!(command.to_string().parse(), Ok(command));
+        }
+        assert_eq!(
+            "order ALICE hold 1 1".parse::<Command>(),
+            Err("not a side: 'hold'".to_string())
+        );
+        assert_eq!(
+            parse_journal("1 deposit ALICE x"),
+            Err("line 1: not a number: 'x'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_Sequencer_handle_rejects_gaps() {
+        let mut sequencer = Sequencer::new();
+        assert_eq!(
+            sequencer.handle(2, Command::Tick { now: 1 }),
+            Err(ApplicationError::OutOfSequence(2))
+        );
+        assert!(sequencer.handle(1, Command::Tick { now: 5 }).is_ok());
+        assert_eq!(
+            sequencer
+                .handle(2, Command::Tick { now: 1 })
+                .unwrap()
+                .result,
+            Ok(Reply::Ticked(5))
+        );
+    }
+
+    #[test]
+    fn test_Sequencer_run_is_deterministic() {
+        let commands = parse_journal(JOURNAL).unwrap();
+        let first = Sequencer::new().run(commands.clone()).unwrap();
+        let second = Sequencer::new().run(commands.clone()).unwrap();
+        let render = |outputs: &[Output]| {
+            outputs
+                .iter()
+                .map(|o| o.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        assert_eq!(render(&first), render(&second));
+        assert_eq!(
+            first[7].result,
+            Err(ApplicationError::AccountNotFound("CAROL".to_string()))
+        );
+
+        let recorded = render(&first);
+        assert_eq!(diff_replay(commands.clone(), recorded.lines()), Ok(None));
+
+        // tamper with the recording
+        let tampered = recorded.replace("amount: 5", "amount: 6");
+        let divergence = diff_replay(commands.clone(), tampered.lines())
+            .unwrap()
+            .unwrap();
+        assert_eq!(divergence.index, 9);
+        assert_eq!(divergence.actual.as_deref(), recorded.lines().nth(9));
+
+        // a recording that stops early differs where it ends
+        let divergence = diff_replay(commands, recorded.lines().take(3))
+            .unwrap()
+            .unwrap();
+        assert_eq!((divergence.index, divergence.expected), (3, None));
+    }
+}
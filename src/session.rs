@@ -0,0 +1,64 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::errors::ApplicationError;
+
+/// Identifies a client [`Session`]
+pub type SessionId = u64;
+
+/// A client connection and the orders that were placed through it
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Session {
+    /// Unique id
+    pub id: SessionId,
+    /// Cancel the session's open orders when it ends
+    pub cancel_on_disconnect: bool,
+    /// Ordinals of the orders placed through this session
+    pub orders: BTreeSet<u64>,
+}
+
+/// Keeps track of the currently active [`Session`]s
+#[derive(Clone, Debug, Default)]
+pub struct Sessions {
+    last_id: SessionId,
+    active: BTreeMap<SessionId, Session>,
+}
+
+impl Sessions {
+    /// Starts a new session and returns its id
+    pub fn open(&mut self, cancel_on_disconnect: bool) -> SessionId {
+        self.last_id += 1;
+        self.active.insert(
+            self.last_id,
+            Session {
+                id: self.last_id,
+                cancel_on_disconnect,
+                orders: BTreeSet::new(),
+            },
+        );
+        self.last_id
+    }
+
+    /// Retrieves an active session
+    pub fn get(&self, id: SessionId) -> Result<&Session, ApplicationError> {
+        self.active
+            .get(&id)
+            .ok_or(ApplicationError::SessionNotFound(id))
+    }
+
+    /// Records that the order `ordinal` was placed through the session
+    pub fn track(&mut self, id: SessionId, ordinal: u64) -> Result<(), ApplicationError> {
+        self.active
+            .get_mut(&id)
+            .ok_or(ApplicationError::SessionNotFound(id))
+            .map(|session| {
+                session.orders.insert(ordinal);
+            })
+    }
+
+    /// Ends the session and hands it back
+    pub fn close(&mut self, id: SessionId) -> Result<Session, ApplicationError> {
+        self.active
+            .remove(&id)
+            .ok_or(ApplicationError::SessionNotFound(id))
+    }
+}
@@ -0,0 +1,619 @@
+use std::{fs, io::Write, path::Path};
+
+use crate::{
+    accounting::{AccountStatus, Accounts},
+    amount::Market,
+    clock::Clock,
+    codec::{crc32, Decode, Encode, Reader, Writer},
+    core::{MatchingEngine, OrderStatus, PartialOrder},
+    errors::ApplicationError,
+    escrow::Escrow,
+    fees::FeeSchedule,
+    interest::{Accrual, InterestSchedule},
+    limits::{Limits, Usage},
+    margin::{MarginRequirements, Position},
+    risk::RiskLimits,
+    schedule::Schedule,
+};
+
+/// Identifies a snapshot file
+const MAGIC: &[u8; 4] = b"SNAP";
+
+/// Format version of the snapshots written by this build. When the format changes, bump the
+/// version and keep the decoder of every older one in [`Snapshot::decode_versioned`].
+pub const SNAPSHOT_VERSION: u32 = 14;
+
+/// A consistent copy of the state of [`Accounts`] and a [`MatchingEngine`] at one point in time.
+/// Restarting from a snapshot only needs the part of the log that was written after it.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Snapshot {
+    /// Offset in bytes of the first log entry that isn't part of the snapshot
+    pub log_position: u64,
+    /// Sequence number of the last transaction
+    pub sequence: u64,
+    /// Every account that isn't closed and its balance, ordered by account name
+    pub balances: Vec<(String, u64)>,
+    /// Every account that isn't open and its status, ordered by account name. Since version 2.
+    pub statuses: Vec<(String, AccountStatus)>,
+    /// Every sub-account and its parent, ordered by account name. Since version 3.
+    pub parents: Vec<(String, String)>,
+    /// Every open escrow, ordered by id. Since version 4.
+    pub escrows: Vec<Escrow>,
+    /// Every scheduled transfer that has occurrences left, ordered by id. Since version 5.
+    pub schedules: Vec<Schedule>,
+    /// Interest and fees accrued but not posted yet, ordered by account. Since version 6.
+    pub accrued: Vec<Accrual>,
+    /// The day that interest was last accrued up to. Since version 6.
+    pub accrued_day: Option<u64>,
+    /// Every account that owes something and its debt, ordered by account name. Since version 7.
+    pub debts: Vec<(String, u64)>,
+    /// Every account with a credit line and its limit, ordered by account name. Since version 7.
+    pub credit_limits: Vec<(String, u64)>,
+    /// Every position, ordered by account and symbol. Since version 8.
+    pub positions: Vec<Position>,
+    /// Every account with withdrawal and transfer limits, ordered by account name. Since
+    /// version 9.
+    pub limits: Vec<(String, Limits)>,
+    /// What every account withdrew and sent recently, ordered by account name. Since version 9.
+    pub usage: Vec<(String, Usage)>,
+    /// The interest, custody fee and overdraft rates. Since version 10.
+    pub interest_schedule: InterestSchedule,
+    /// The margin needed for positions. Since version 11.
+    pub margin: Option<MarginRequirements>,
+    /// Every market with pre-trade limits, ordered by symbol. Since version 12.
+    pub market_risk_limits: Vec<(String, RiskLimits)>,
+    /// Every account with pre-trade limits of its own and the market, ordered by account and
+    /// symbol. Since version 12.
+    pub risk_limits: Vec<(String, String, RiskLimits)>,
+    /// The assets that are traded and what the balances are held in. Since version 13.
+    pub market: Market,
+    /// The maker and taker fees. Since version 14.
+    pub fee_schedule: FeeSchedule,
+    /// The last order ordinal
+    pub ordinal: u64,
+    /// Resting orders of both sides of the book, ordered by ordinal
+    pub book: Vec<PartialOrder>,
+    /// Status of every open order, ordered by ordinal
+    pub open_orders: Vec<OrderStatus>,
+}
+
+impl Snapshot {
+    /// Copies the current state. `log_position` is where the log continues after it.
+    pub fn capture(accounts: &Accounts, engine: &MatchingEngine, log_position: u64) -> Self {
+        let mut book: Vec<PartialOrder> = engine
+            .bids
+            .values()
+            .chain(engine.asks.values())
+            .flat_map(|orders| orders.iter().cloned())
+            .collect();
+        book.sort_by_key(|o| o.ordinal);
+        Snapshot {
+            log_position,
+            sequence: accounts.sequence(),
+            balances: accounts
+                .balances()
+                .into_iter()
+                .map(|(account, balance)| (account.to_string(), balance))
+                .collect(),
+            statuses: accounts
+                .statuses()
+                .into_iter()
+                .filter(|(_, status)| *status != AccountStatus::Open)
+                .map(|(account, status)| (account.to_string(), status))
+                .collect(),
+            parents: accounts
+                .parents()
+                .into_iter()
+                .map(|(account, parent)| (account.to_string(), parent.to_string()))
+                .collect(),
+            escrows: accounts.escrows().into_iter().cloned().collect(),
+            schedules: accounts.schedules().into_iter().cloned().collect(),
+            accrued: accounts.accruals(),
+            accrued_day: accounts.accrued_day(),
+            debts: accounts
+                .debts()
+                .into_iter()
+                .map(|(account, debt)| (account.to_string(), debt))
+                .collect(),
+            credit_limits: accounts
+                .credit_limits()
+                .into_iter()
+                .map(|(account, limit)| (account.to_string(), limit))
+                .collect(),
+            positions: accounts.positions().into_iter().cloned().collect(),
+            limits: accounts
+                .limits()
+                .into_iter()
+                .map(|(account, limits)| (account.to_string(), limits.clone()))
+                .collect(),
+            usage: accounts
+                .usage()
+                .into_iter()
+                .map(|(account, usage)| (account.to_string(), usage.clone()))
+                .collect(),
+            interest_schedule: accounts.interest_schedule().clone(),
+            margin: accounts.margin_requirements().cloned(),
+            market_risk_limits: accounts
+                .market_risk_limits()
+                .into_iter()
+                .map(|(symbol, limits)| (symbol.to_string(), limits.clone()))
+                .collect(),
+            risk_limits: accounts
+                .risk_limits()
+                .into_iter()
+                .map(|(account, symbol, limits)| {
+                    (account.to_string(), symbol.to_string(), limits.clone())
+                })
+                .collect(),
+            market: accounts.market().clone(),
+            fee_schedule: accounts.fee_schedule().clone(),
+            ordinal: engine.ordinal,
+            book,
+            open_orders: engine.orders.open().into_iter().cloned().collect(),
+        }
+    }
+
+    /// Rebuilds [`Accounts`] and a [`MatchingEngine`] that use `clock`. The journal, order history
+    /// and market statistics are not part of a snapshot and start out empty.
+    pub fn restore(&self, clock: impl Clock + Clone + 'static) -> (Accounts, MatchingEngine) {
+        let accounts = Accounts::restore(self, clock.clone());
+        let engine = MatchingEngine::restore(
+            self.ordinal,
+            self.book.clone(),
+            self.open_orders.clone(),
+            clock,
+        );
+        (accounts, engine)
+    }
+
+    /// Writes the snapshot to `path`. The file is replaced atomically, so a crash leaves either
+    /// the old or the new snapshot behind.
+    /// # Errors
+    /// Writing failed
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ApplicationError> {
+        let path = path.as_ref();
+        let payload = self.to_bytes();
+        let mut header = Writer::new();
+        header.put_u32(SNAPSHOT_VERSION);
+        header.put_u32(crc32(&payload));
+
+        let tmp = path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(MAGIC)?;
+        file.write_all(&header.into_bytes())?;
+        file.write_all(&payload)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Reads the snapshot at `path`, `None` if there is none
+    /// # Errors
+    /// Reading failed, the file is corrupted or of an unknown version
+    pub fn load(path: impl AsRef<Path>) -> Result<Option<Self>, ApplicationError> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if bytes.get(..MAGIC.len()) != Some(MAGIC.as_slice()) {
+            return Err(ApplicationError::Corrupted("not a snapshot".to_string()));
+        }
+        let mut r = Reader::new(&bytes[MAGIC.len()..]);
+        let version = r.get_u32()?;
+        let checksum = r.get_u32()?;
+        let payload = &bytes[MAGIC.len() + 8..];
+        if crc32(payload) != checksum {
+            return Err(ApplicationError::Corrupted(
+                "snapshot checksum mismatch".to_string(),
+            ));
+        }
+        Snapshot::decode_versioned(version, payload).map(Some)
+    }
+
+    /// Decodes a payload written in format `version`
+    fn decode_versioned(version: u32, payload: &[u8]) -> Result<Self, ApplicationError> {
+        let mut r = Reader::new(payload);
+        let snapshot = match version {
+            1 => Snapshot::decode_v1(&mut r)?,
+            2 => Snapshot::decode_v2(&mut r)?,
+            3 => Snapshot::decode_v3(&mut r)?,
+            4 => Snapshot::decode_v4(&mut r)?,
+            5 => Snapshot::decode_v5(&mut r)?,
+            6 => Snapshot::decode_v6(&mut r)?,
+            7 => Snapshot::decode_v7(&mut r)?,
+            8 => Snapshot::decode_v8(&mut r)?,
+            9 => Snapshot::decode_v9(&mut r)?,
+            10 => Snapshot::decode_v10(&mut r)?,
+            11 => Snapshot::decode_v11(&mut r)?,
+            12 => Snapshot::decode_v12(&mut r)?,
+            13 => Snapshot::decode_v13(&mut r)?,
+            14 => return Snapshot::from_bytes(payload),
+            _ => {
+                return Err(ApplicationError::Corrupted(format!(
+                    "unsupported snapshot version {}",
+                    version
+                )))
+            }
+        };
+        if !r.is_empty() {
+            return Err(ApplicationError::Corrupted("trailing bytes".to_string()));
+        }
+        Ok(snapshot)
+    }
+
+    /// Version 1: everything up to the open orders, all accounts are open
+    fn decode_v1(r: &mut Reader) -> Result<Self, ApplicationError> {
+        Ok(Snapshot {
+            log_position: r.get_u64()?,
+            sequence: r.get_u64()?,
+            balances: r.get_vec()?,
+            statuses: vec![],
+            parents: vec![],
+            escrows: vec![],
+            schedules: vec![],
+            accrued: vec![],
+            accrued_day: None,
+            debts: vec![],
+            credit_limits: vec![],
+            positions: vec![],
+            limits: vec![],
+            usage: vec![],
+            interest_schedule: InterestSchedule::default(),
+            margin: None,
+            market_risk_limits: vec![],
+            risk_limits: vec![],
+            market: Market::default(),
+            fee_schedule: FeeSchedule::default(),
+            ordinal: r.get_u64()?,
+            book: r.get_vec()?,
+            open_orders: r.get_vec()?,
+        })
+    }
+
+    /// Version 2: adds the statuses, there are no sub-accounts
+    fn decode_v2(r: &mut Reader) -> Result<Self, ApplicationError> {
+        let mut snapshot = Snapshot::decode_v1(r)?;
+        snapshot.statuses = r.get_vec()?;
+        Ok(snapshot)
+    }
+
+    /// Version 3: adds the sub-accounts, there are no escrows
+    fn decode_v3(r: &mut Reader) -> Result<Self, ApplicationError> {
+        let mut snapshot = Snapshot::decode_v2(r)?;
+        snapshot.parents = r.get_vec()?;
+        Ok(snapshot)
+    }
+
+    /// Version 4: adds the escrows, there are no scheduled transfers
+    fn decode_v4(r: &mut Reader) -> Result<Self, ApplicationError> {
+        let mut snapshot = Snapshot::decode_v3(r)?;
+        snapshot.escrows = r.get_vec()?;
+        Ok(snapshot)
+    }
+
+    /// Version 5: adds the scheduled transfers, nothing accrued interest
+    fn decode_v5(r: &mut Reader) -> Result<Self, ApplicationError> {
+        let mut snapshot = Snapshot::decode_v4(r)?;
+        snapshot.schedules = r.get_vec()?;
+        Ok(snapshot)
+    }
+
+    /// Version 6: adds the accrued interest, there are no credit lines
+    fn decode_v6(r: &mut Reader) -> Result<Self, ApplicationError> {
+        let mut snapshot = Snapshot::decode_v5(r)?;
+        snapshot.accrued = r.get_vec()?;
+        snapshot.accrued_day = r.get_option()?;
+        Ok(snapshot)
+    }
+
+    /// Version 7: adds the credit lines, there are no positions
+    fn decode_v7(r: &mut Reader) -> Result<Self, ApplicationError> {
+        let mut snapshot = Snapshot::decode_v6(r)?;
+        snapshot.debts = r.get_vec()?;
+        snapshot.credit_limits = r.get_vec()?;
+        Ok(snapshot)
+    }
+
+    /// Version 8: adds the positions, there are no limits
+    fn decode_v8(r: &mut Reader) -> Result<Self, ApplicationError> {
+        let mut snapshot = Snapshot::decode_v7(r)?;
+        snapshot.positions = r.get_vec()?;
+        Ok(snapshot)
+    }
+
+    /// Version 9: adds the limits, interest rates are the default ones
+    fn decode_v9(r: &mut Reader) -> Result<Self, ApplicationError> {
+        let mut snapshot = Snapshot::decode_v8(r)?;
+        snapshot.limits = r.get_vec()?;
+        snapshot.usage = r.get_vec()?;
+        Ok(snapshot)
+    }
+
+    /// Version 10: adds the interest rates, there are no margin requirements
+    fn decode_v10(r: &mut Reader) -> Result<Self, ApplicationError> {
+        let mut snapshot = Snapshot::decode_v9(r)?;
+        snapshot.interest_schedule = r.get()?;
+        Ok(snapshot)
+    }
+
+    /// Version 11: adds the margin requirements, there are no risk limits
+    fn decode_v11(r: &mut Reader) -> Result<Self, ApplicationError> {
+        let mut snapshot = Snapshot::decode_v10(r)?;
+        snapshot.margin = r.get_option()?;
+        Ok(snapshot)
+    }
+
+    /// Version 12: adds the risk limits, the market is the default one
+    fn decode_v12(r: &mut Reader) -> Result<Self, ApplicationError> {
+        let mut snapshot = Snapshot::decode_v11(r)?;
+        snapshot.market_risk_limits = r.get_vec()?;
+        snapshot.risk_limits = r.get_vec()?;
+        Ok(snapshot)
+    }
+
+    /// Version 13: adds the market, there are no fees
+    fn decode_v13(r: &mut Reader) -> Result<Self, ApplicationError> {
+        let mut snapshot = Snapshot::decode_v12(r)?;
+        snapshot.market = r.get()?;
+        Ok(snapshot)
+    }
+}
+
+impl Encode for Snapshot {
+    fn encode(&self, w: &mut Writer) {
+        w.put_u64(self.log_position);
+        w.put_u64(self.sequence);
+        w.put_vec(&self.balances);
+        w.put_u64(self.ordinal);
+        w.put_vec(&self.book);
+        w.put_vec(&self.open_orders);
+        w.put_vec(&self.statuses);
+        w.put_vec(&self.parents);
+        w.put_vec(&self.escrows);
+        w.put_vec(&self.schedules);
+        w.put_vec(&self.accrued);
+        w.put_option(&self.accrued_day);
+        w.put_vec(&self.debts);
+        w.put_vec(&self.credit_limits);
+        w.put_vec(&self.positions);
+        w.put_vec(&self.limits);
+        w.put_vec(&self.usage);
+        w.put(&self.interest_schedule);
+        w.put_option(&self.margin);
+        w.put_vec(&self.market_risk_limits);
+        w.put_vec(&self.risk_limits);
+        w.put(&self.market);
+        w.put(&self.fee_schedule);
+    }
+}
+
+impl Decode for Snapshot {
+    /// Decodes the current version
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        let mut snapshot = Snapshot::decode_v13(r)?;
+        snapshot.fee_schedule = r.get()?;
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use crate::{
+        clock::{ManualClock, DAY},
+        core::{Order, Side},
+        escrow::{Condition, ESCROW_ACCOUNT},
+        limits::{Cap, Limit, Window},
+        schedule::{Recurrence, RetryPolicy},
+    };
+
+    use super::*;
+
+    fn temp_snapshot(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.snap", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn state() -> (Accounts, MatchingEngine) {
+        let clock = ManualClock::new(1_000);
+        let mut accounts = Accounts::with_clock(clock.clone());
+        accounts.open_account("BOB").unwrap();
+        accounts.open_account("ALICE").unwrap();
+        accounts.deposit("BOB", 100).unwrap();
+        accounts.deposit("ALICE", 50).unwrap();
+        let mut engine = MatchingEngine::with_clock(clock);
+        for (signer, side, price, amount) in [
+            ("ALICE", Side::Sell, 11, 3),
+            ("BOB", Side::Buy, 11, 1),
+            ("BOB", Side::Buy, 9, 2),
+            ("ALICE", Side::Sell, 12, 1),
+        ] {
+            engine
+                .process(Order {
+                    price,
+                    amount,
+                    side,
+                    signer: signer.to_string(),
+                })
+                .unwrap();
+        }
+        engine.cancel(4).unwrap();
+        (accounts, engine)
+    }
+
+    #[test]
+    fn test_Snapshot_restore_rebuilds_state() {
+        let (accounts, engine) = state();
+        let snapshot = Snapshot::capture(&accounts, &engine, 42);
+        assert_eq!(
+            snapshot.balances,
+            vec![("ALICE".to_string(), 50), ("BOB".to_string(), 100)]
+        );
+        assert_eq!(
+            snapshot.book.iter().map(|o| o.ordinal).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+
+        let (restored_accounts, restored_engine) = snapshot.restore(ManualClock::default());
+        assert_eq!(restored_accounts.balances(), accounts.balances());
+        assert_eq!(restored_accounts.sequence(), 4);
+        assert_eq!(restored_engine.ordinal, 4);
+        assert_eq!(
+            restored_engine.open_orders("ALICE"),
+            engine.open_orders("ALICE")
+        );
+        assert_eq!(
+            restored_engine.open_orders("BOB"),
+            engine.open_orders("BOB")
+        );
+        assert_eq!(
+            Snapshot::capture(&restored_accounts, &restored_engine, 42),
+            snapshot
+        );
+    }
+
+    #[test]
+    fn test_Snapshot_save_and_load() {
+        let path = temp_snapshot("snapshot-round-trip");
+        assert_eq!(Snapshot::load(&path), Ok(None));
+
+        let (accounts, engine) = state();
+        let snapshot = Snapshot::capture(&accounts, &engine, 7);
+        snapshot.save(&path).unwrap();
+        assert_eq!(Snapshot::load(&path), Ok(Some(snapshot)));
+
+        // flip a bit in the payload
+        let mut bytes = fs::read(&path).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(
+            Snapshot::load(&path),
+            Err(ApplicationError::Corrupted(
+                "snapshot checksum mismatch".to_string()
+            ))
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_Snapshot_keeps_sub_accounts_escrows_and_schedules() {
+        let path = temp_snapshot("snapshot-sub-accounts");
+        let (mut accounts, engine) = state();
+        accounts.open_sub_account("BOB", "BOB/1").unwrap();
+        let escrow = accounts
+            .open_escrow("BOB", "ALICE", 10, Condition::Deadline(2_000))
+            .unwrap();
+        accounts
+            .schedule_transfer(
+                "ALICE",
+                "BOB",
+                5,
+                2_000,
+                Recurrence::Weekly,
+                RetryPolicy::default(),
+            )
+            .unwrap();
+        Snapshot::capture(&accounts, &engine, 0)
+            .save(&path)
+            .unwrap();
+
+        let (restored, _) = Snapshot::load(&path)
+            .unwrap()
+            .unwrap()
+            .restore(ManualClock::default());
+        assert_eq!(restored.parent_of("BOB/1"), Some("BOB"));
+        assert_eq!(restored.escrows(), accounts.escrows());
+        assert_eq!(restored.balance_of(ESCROW_ACCOUNT), Ok(&10));
+        assert!(restored.escrow(escrow.meta().sequence).is_some());
+        assert_eq!(restored.schedules(), accounts.schedules());
+        assert_eq!(restored.state_hash(), accounts.state_hash());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_Snapshot_keeps_limits_and_usage() {
+        let path = temp_snapshot("snapshot-limits");
+        let (mut accounts, engine) = state();
+        let limits = Limits {
+            withdrawal: Some(Cap {
+                amount: 30,
+                window: Window::Rolling(DAY),
+            }),
+            ..Limits::default()
+        };
+        accounts.set_limits("BOB", limits.clone()).unwrap();
+        accounts.withdraw("BOB", 20).unwrap();
+        Snapshot::capture(&accounts, &engine, 0)
+            .save(&path)
+            .unwrap();
+
+        // the withdrawal before the snapshot still counts
+        let (mut restored, _) = Snapshot::load(&path)
+            .unwrap()
+            .unwrap()
+            .restore(ManualClock::new(1_000));
+        assert_eq!(restored.limits_of("BOB"), Some(&limits));
+        assert_eq!(restored.usage(), accounts.usage());
+        assert_eq!(restored.state_hash(), accounts.state_hash());
+        assert_eq!(
+            restored.withdraw("BOB", 11),
+            Err(ApplicationError::LimitExceeded(
+                "BOB".to_string(),
+                Limit::Withdrawal(30),
+                10
+            ))
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_Snapshot_load_reads_version_1() {
+        let path = temp_snapshot("snapshot-v1");
+        let (accounts, engine) = state();
+        let snapshot = Snapshot::capture(&accounts, &engine, 7);
+        // all accounts are open top-level accounts: version 1 is the current payload without the
+        // empty statuses, parents, escrows, schedules, accruals, credit lines, positions and limits
+        // and the default interest rates, margin requirements, risk limits, market and fees
+        assert!(snapshot.statuses.is_empty() && snapshot.parents.is_empty());
+        assert!(snapshot.escrows.is_empty() && snapshot.schedules.is_empty());
+        assert!(snapshot.accrued.is_empty() && snapshot.accrued_day.is_none());
+        assert!(snapshot.debts.is_empty() && snapshot.credit_limits.is_empty());
+        assert!(snapshot.positions.is_empty());
+        assert!(snapshot.limits.is_empty() && snapshot.usage.is_empty());
+        assert_eq!(snapshot.interest_schedule, InterestSchedule::default());
+        assert!(snapshot.margin.is_none());
+        assert!(snapshot.market_risk_limits.is_empty() && snapshot.risk_limits.is_empty());
+        assert_eq!(snapshot.market, Market::default());
+        assert_eq!(snapshot.fee_schedule, FeeSchedule::default());
+        let mut payload = snapshot.to_bytes();
+        payload.truncate(payload.len() - 102);
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&crc32(&payload).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(Snapshot::load(&path), Ok(Some(snapshot)));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_Snapshot_load_rejects_unknown_version() {
+        let path = temp_snapshot("snapshot-version");
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes());
+        bytes.extend_from_slice(&crc32(&[]).to_le_bytes());
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(
+            Snapshot::load(&path),
+            Err(ApplicationError::Corrupted(format!(
+                "unsupported snapshot version {}",
+                SNAPSHOT_VERSION + 1
+            )))
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
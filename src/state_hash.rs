@@ -0,0 +1,85 @@
+use std::fmt;
+
+/// FNV-1a 128 offset basis
+const FNV_OFFSET: u128 = 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d;
+/// FNV-1a 128 prime
+const FNV_PRIME: u128 = 0x0000_0000_0100_0000_0000_0000_0000_013b;
+
+/// An order-independent digest over a multiset of items: the wrapping sum of every item's
+/// FNV-1a hash. Adding or removing an item is O(1), so the digest can be kept up to date with
+/// every change instead of being recomputed.
+///
+/// It only detects accidental divergence, e.g. a replay that applied a transaction differently.
+/// Neither FNV-1a nor the sum is collision resistant: anyone who can choose items, such as
+/// account names, can make different states add up to the same digest. Equal digests are not a
+/// proof that two states are equal and must not be used as one.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct StateHash(pub u128);
+
+impl StateHash {
+    /// Hashes all `items` at once
+    pub fn of<'a>(items: impl IntoIterator<Item = &'a [u8]>) -> Self {
+        let mut hash = StateHash::default();
+        for item in items {
+            hash.add(item);
+        }
+        hash
+    }
+
+    /// Adds an item
+    pub fn add(&mut self, item: &[u8]) {
+        self.0 = self.0.wrapping_add(fnv1a(item));
+    }
+
+    /// Removes an item that was added before
+    pub fn remove(&mut self, item: &[u8]) {
+        self.0 = self.0.wrapping_sub(fnv1a(item));
+    }
+
+    /// Digest of both multisets together
+    pub fn combine(self, other: StateHash) -> StateHash {
+        StateHash(self.0.wrapping_add(other.0))
+    }
+}
+
+impl fmt::Display for StateHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+/// FNV-1a (128 bit) of `bytes`
+fn fnv1a(bytes: &[u8]) -> u128 {
+    bytes.iter().fold(FNV_OFFSET, |hash, byte| {
+        (hash ^ *byte as u128).wrapping_mul(FNV_PRIME)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_fnv1a_known_values() {
+        assert_eq!(fnv1a(b""), FNV_OFFSET);
+        assert_eq!(fnv1a(b"a"), 0xd228_cb69_6f1a_8caf_7891_2b70_4e4a_8964);
+    }
+
+    #[test]
+    fn test_StateHash_is_order_independent_and_incremental() {
+        let items: [&[u8]; 3] = [b"alice", b"bob", b"carol"];
+        let forward = StateHash::of(items);
+        let backward = StateHash::of(items.into_iter().rev());
+        assert_eq!(forward, backward);
+
+        let mut hash = StateHash::of([b"alice".as_slice(), b"bob"]);
+        assert_ne!(hash, forward);
+        hash.add(b"carol");
+        assert_eq!(hash, forward);
+        hash.remove(b"bob");
+        assert_eq!(hash, StateHash::of([b"carol".as_slice(), b"alice"]));
+    }
+}
@@ -0,0 +1,419 @@
+use std::{fmt::Write, ops::RangeInclusive};
+
+use crate::{
+    accounting::AccountStatus,
+    amount::Asset,
+    clock::Timestamp,
+    tx::{Leg, Tx, TxId},
+};
+
+/// What a [`StatementLine`] did to the account
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Entry {
+    Deposit,
+    Withdrawal,
+    /// Funds came in from another account
+    TransferIn,
+    /// Funds went out to another account
+    TransferOut,
+    /// The account moved into this status
+    Status(AccountStatus),
+    /// The account was open again after being frozen
+    Unfrozen,
+}
+
+impl Entry {
+    /// The name used in exports
+    pub fn label(&self) -> &'static str {
+        match self {
+            Entry::Deposit => "deposit",
+            Entry::Withdrawal => "withdrawal",
+            Entry::TransferIn => "transfer_in",
+            Entry::TransferOut => "transfer_out",
+            Entry::Status(AccountStatus::Open) => "opened",
+            Entry::Status(AccountStatus::Frozen) => "frozen",
+            Entry::Status(AccountStatus::Closed) => "closed",
+            Entry::Unfrozen => "unfrozen",
+        }
+    }
+}
+
+/// A single transaction as seen by one account
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct StatementLine {
+    pub tx_id: TxId,
+    pub sequence: u64,
+    pub timestamp: Timestamp,
+    pub entry: Entry,
+    /// The other account of a transfer
+    pub counterparty: Option<String>,
+    pub memo: Option<String>,
+    /// Funds in, negative for funds out
+    pub change: i128,
+    /// The balance right after the transaction, negative while the account owes something
+    pub balance: i128,
+}
+
+/// Everything that happened to an account in a period of time
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Statement {
+    pub account: String,
+    /// What the amounts are in
+    pub asset: Asset,
+    /// The period, both ends included
+    pub timestamps: RangeInclusive<Timestamp>,
+    /// The balance before the first transaction of the period
+    pub opening_balance: i128,
+    /// Every transaction of the account in the period, oldest first
+    pub lines: Vec<StatementLine>,
+    /// The balance after the last transaction of the period
+    pub closing_balance: i128,
+}
+
+impl Statement {
+    /// Builds the statement of `account` for the period `timestamps` from a `journal` that starts
+    /// with an empty ledger, e.g. [`crate::accounting::Accounts::journal`]. Amounts are minor
+    /// units of `asset`.
+    pub fn generate(
+        journal: &[Tx],
+        account: &str,
+        asset: &Asset,
+        timestamps: RangeInclusive<Timestamp>,
+    ) -> Self {
+        let mut opening_balance = 0;
+        let mut balance = 0i128;
+        let mut lines = vec![];
+        let mut status = None;
+        for tx in journal {
+            let timestamp = tx.meta().timestamp;
+            if timestamp > *timestamps.end() {
+                break;
+            }
+            let items = items(tx, account, &mut status);
+            for (entry, counterparty, change) in items {
+                balance += change;
+                if timestamp < *timestamps.start() {
+                    opening_balance = balance;
+                    continue;
+                }
+                let meta = tx.meta();
+                lines.push(StatementLine {
+                    tx_id: meta.id,
+                    sequence: meta.sequence,
+                    timestamp,
+                    entry,
+                    counterparty,
+                    memo: meta.memo.clone(),
+                    change,
+                    balance,
+                });
+            }
+        }
+        Statement {
+            account: account.to_string(),
+            asset: asset.clone(),
+            timestamps,
+            opening_balance,
+            lines,
+            closing_balance: balance,
+        }
+    }
+
+    /// The statement as CSV with a header row. The opening and closing balances are rows of
+    /// their own, amounts are decimals.
+    pub fn to_csv(&self) -> String {
+        let mut csv =
+            String::from("sequence,tx_id,timestamp,entry,counterparty,memo,change,balance\n");
+        let _ = writeln!(
+            csv,
+            ",,{},opening_balance,,,,{}",
+            self.timestamps.start(),
+            self.signed(self.opening_balance)
+        );
+        for line in &self.lines {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{},{},{}",
+                line.sequence,
+                line.tx_id,
+                line.timestamp,
+                line.entry.label(),
+                csv_field(line.counterparty.as_deref().unwrap_or("")),
+                csv_field(line.memo.as_deref().unwrap_or("")),
+                self.signed(line.change),
+                self.signed(line.balance)
+            );
+        }
+        let _ = writeln!(
+            csv,
+            ",,{},closing_balance,,,,{}",
+            self.timestamps.end(),
+            self.signed(self.closing_balance)
+        );
+        csv
+    }
+
+    /// The statement as a JSON object. Amounts are decimal strings so they don't lose precision.
+    pub fn to_json(&self) -> String {
+        let lines: Vec<String> = self
+            .lines
+            .iter()
+            .map(|line| {
+                format!(
+                    "{{\"sequence\":{},\"tx_id\":\"{}\",\"timestamp\":{},\"entry\":\"{}\",\"counterparty\":{},\"memo\":{},\"change\":\"{}\",\"balance\":\"{}\"}}",
+                    line.sequence,
+                    line.tx_id,
+                    line.timestamp,
+                    line.entry.label(),
+                    json_option(line.counterparty.as_deref()),
+                    json_option(line.memo.as_deref()),
+                    self.signed(line.change),
+                    self.signed(line.balance)
+                )
+            })
+            .collect();
+        format!(
+            "{{\"account\":{},\"asset\":{},\"from\":{},\"to\":{},\"opening_balance\":\"{}\",\"lines\":[{}],\"closing_balance\":\"{}\"}}",
+            json_string(&self.account),
+            json_string(&self.asset.code),
+            self.timestamps.start(),
+            self.timestamps.end(),
+            self.signed(self.opening_balance),
+            lines.join(","),
+            self.signed(self.closing_balance)
+        )
+    }
+
+    /// `value` as a decimal with a sign for funds out or debt
+    fn signed(&self, value: i128) -> String {
+        let amount = self
+            .asset
+            .amount(u64::try_from(value.unsigned_abs()).unwrap_or(u64::MAX));
+        if value < 0 {
+            format!("-{}", amount)
+        } else {
+            amount.to_string()
+        }
+    }
+}
+
+/// The balance of `account` after every transaction up to and including `at`, `None` if it
+/// wasn't opened by then. `journal` has to start with an empty ledger.
+pub fn balance_at(journal: &[Tx], account: &str, at: Timestamp) -> Option<i128> {
+    let mut balance = None;
+    let mut status = None;
+    for tx in journal.iter().take_while(|tx| tx.meta().timestamp <= at) {
+        for (entry, _, change) in items(tx, account, &mut status) {
+            balance = match entry {
+                Entry::Status(AccountStatus::Open) => Some(balance.unwrap_or(0)),
+                Entry::Status(AccountStatus::Closed) => None,
+                _ => balance.map(|b| b + change),
+            };
+        }
+    }
+    balance
+}
+
+/// What `tx` did to `account`: nothing, or one item per side it was on. `status` is the status
+/// of the account before `tx` and is moved along with it.
+fn items(
+    tx: &Tx,
+    account: &str,
+    status: &mut Option<AccountStatus>,
+) -> Vec<(Entry, Option<String>, i128)> {
+    match tx {
+        Tx::StatusChange {
+            account: a,
+            status: new,
+            ..
+        } if a == account => {
+            let entry = match (*status, new) {
+                (Some(AccountStatus::Frozen), AccountStatus::Open) => Entry::Unfrozen,
+                _ => Entry::Status(*new),
+            };
+            *status = Some(*new);
+            return vec![(entry, None, 0)];
+        }
+        Tx::SubAccount { account: a, .. } if a == account => {
+            *status = Some(AccountStatus::Open);
+            return vec![(Entry::Status(AccountStatus::Open), None, 0)];
+        }
+        _ => {}
+    }
+    let mut items = vec![];
+    match tx.leg() {
+        Some(Leg::Deposit { account: a, amount }) if a == account => {
+            items.push((Entry::Deposit, None, amount as i128))
+        }
+        Some(Leg::Withdraw { account: a, amount }) if a == account => {
+            items.push((Entry::Withdrawal, None, -(amount as i128)))
+        }
+        Some(Leg::Transfer {
+            sender,
+            recipient,
+            amount,
+        }) => {
+            if sender == account {
+                items.push((
+                    Entry::TransferOut,
+                    Some(recipient.clone()),
+                    -(amount as i128),
+                ));
+            }
+            if recipient == account {
+                items.push((Entry::TransferIn, Some(sender), amount as i128));
+            }
+        }
+        _ => {}
+    }
+    items
+}
+
+/// Quotes a CSV field if it needs to be
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// A JSON string literal
+fn json_string(value: &str) -> String {
+    let mut json = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// A JSON string literal or `null`
+fn json_option(value: Option<&str>) -> String {
+    value.map(json_string).unwrap_or_else(|| "null".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use crate::{
+        accounting::Accounts,
+        clock::{ManualClock, DAY},
+        tx::TxContext,
+    };
+
+    use super::*;
+
+    /// BOB's history over three days
+    fn journal() -> Vec<Tx> {
+        let clock = ManualClock::new(0);
+        let mut accounts = Accounts::with_clock(clock.clone());
+        accounts.open_account("BOB").unwrap();
+        accounts.open_account("ALICE").unwrap();
+        accounts.deposit("BOB", 100).unwrap();
+        clock.set(DAY);
+        accounts.deposit("ALICE", 50).unwrap();
+        accounts
+            .send_with(
+                "ALICE",
+                "BOB",
+                20,
+                TxContext::default().with_memo("rent, \"May\""),
+            )
+            .unwrap();
+        accounts.withdraw("BOB", 30).unwrap();
+        clock.set(2 * DAY);
+        accounts.send("BOB", "ALICE", 40).unwrap();
+        accounts.journal().to_vec()
+    }
+
+    #[test]
+    fn test_balance_at() {
+        let journal = journal();
+        assert_eq!(balance_at(&journal, "BOB", 0), Some(100));
+        assert_eq!(balance_at(&journal, "BOB", DAY - 1), Some(100));
+        assert_eq!(balance_at(&journal, "BOB", DAY), Some(90));
+        assert_eq!(balance_at(&journal, "BOB", 3 * DAY), Some(50));
+        assert_eq!(balance_at(&journal, "CAROL", 3 * DAY), None);
+    }
+
+    #[test]
+    fn test_Statement_generate() {
+        let journal = journal();
+        let statement = Statement::generate(&journal, "BOB", &Asset::default(), DAY..=2 * DAY - 1);
+        assert_eq!(statement.opening_balance, 100);
+        assert_eq!(statement.closing_balance, 90);
+        assert_eq!(
+            statement
+                .lines
+                .iter()
+                .map(|l| (l.entry, l.change, l.balance))
+                .collect::<Vec<_>>(),
+            vec![(Entry::TransferIn, 20, 120), (Entry::Withdrawal, -30, 90)]
+        );
+        assert_eq!(statement.lines[0].counterparty.as_deref(), Some("ALICE"));
+
+        let everything = Statement::generate(&journal, "BOB", &Asset::default(), 0..=3 * DAY);
+        assert_eq!(everything.opening_balance, 0);
+        assert_eq!(
+            everything.lines[0].entry,
+            Entry::Status(AccountStatus::Open)
+        );
+        assert_eq!(everything.lines.len(), 5);
+        assert_eq!(everything.closing_balance, 50);
+    }
+
+    #[test]
+    fn test_Statement_tells_unfreezing_from_opening() {
+        let mut accounts = Accounts::with_clock(ManualClock::default());
+        accounts.open_account("BOB").unwrap();
+        accounts.freeze("BOB").unwrap();
+        accounts.unfreeze("BOB").unwrap();
+        let statement = Statement::generate(accounts.journal(), "BOB", &Asset::default(), 0..=0);
+        assert_eq!(
+            statement.lines.iter().map(|l| l.entry).collect::<Vec<_>>(),
+            vec![
+                Entry::Status(AccountStatus::Open),
+                Entry::Status(AccountStatus::Frozen),
+                Entry::Unfrozen
+            ]
+        );
+        assert!(statement.to_csv().contains(",unfrozen,"));
+        assert_eq!(balance_at(accounts.journal(), "BOB", 0), Some(0));
+    }
+
+    #[test]
+    fn test_Statement_exports() {
+        let journal = journal();
+        let statement =
+            Statement::generate(&journal, "BOB", &Asset::new("USD", 2), DAY..=2 * DAY - 1);
+        let csv = statement.to_csv();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows.len(), 5);
+        assert_eq!(rows[1], format!(",,{},opening_balance,,,,1.00", DAY));
+        assert!(rows[2].ends_with(",transfer_in,ALICE,\"rent, \"\"May\"\"\",0.20,1.20"));
+        assert!(rows[3].ends_with(",withdrawal,,,-0.30,0.90"));
+        assert_eq!(
+            rows[4],
+            format!(",,{},closing_balance,,,,0.90", 2 * DAY - 1)
+        );
+
+        let json = statement.to_json();
+        assert!(json.starts_with("{\"account\":\"BOB\",\"asset\":\"USD\""));
+        assert!(json.contains("\"memo\":\"rent, \\\"May\\\"\""));
+        assert!(json.contains("\"counterparty\":null"));
+        assert!(json.ends_with("\"closing_balance\":\"0.90\"}"));
+    }
+}
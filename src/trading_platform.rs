@@ -1,43 +1,627 @@
-use std::collections::HashMap;
+use std::{collections::BTreeMap, path::Path};
 
 use crate::{
-    accounting::Accounts,
-    core::{MatchingEngine, Order, PartialOrder, Receipt, Side},
-    errors::{ApplicationError, ApplicationError},
-    tx::Tx,
+    accounting::{AccountStatus, Accounts},
+    amount::{Amount, Market},
+    clock::{Clock, SystemClock, Timestamp},
+    core::{
+        CancelReceipt, FillFee, MassCancel, MatchingEngine, Order, OrderStatus, PartialOrder,
+        Receipt, Side,
+    },
+    double_entry::{AccountType, ChartOfAccounts, TrialBalance},
+    errors::ApplicationError,
+    escrow::{Condition, Escrow, ESCROW_ACCOUNT},
+    fees::{FeeSchedule, VolumeTracker},
+    idempotency::IdempotencyCache,
+    interest::InterestSchedule,
+    limits::Limits,
+    margin::{Fill, MarginRequirements, Position},
+    risk::{self, RiskCheck, RiskContext, RiskLimits},
+    schedule::{Recurrence, RetryPolicy, Schedule},
+    session::{SessionId, Sessions},
+    snapshot::Snapshot,
+    state_hash::StateHash,
+    tx::{Cause, Leg, Tx, TxContext},
+    wal::{self, EngineEvent, FsyncPolicy, LogEvent, WriteAheadLog},
 };
 
 /// The core of the core: the [`TradingPlatform`]. Manages accounts, validates-, and orchestrates the processing of each order.
+#[derive(Debug)]
 pub struct TradingPlatform {
-    todo!();
+    matching_engine: MatchingEngine,
+    accounts: Accounts,
+    sessions: Sessions,
+    volumes: VolumeTracker,
+    /// The price positions are valued at, by market symbol
+    marks: BTreeMap<String, u64>,
+    /// Run in order before an order reaches the matching engine
+    risk_checks: Vec<Box<dyn RiskCheck>>,
+    /// Orders and receipts of recent requests with an idempotency key
+    requests: IdempotencyCache<(Order, Receipt)>,
+    clock: Box<dyn Clock>,
+    /// Where every change is persisted, if anywhere
+    wal: Option<WriteAheadLog>,
+    /// Number of journal entries that have been written to the log
+    logged: usize,
+}
+
+impl Default for TradingPlatform {
+    fn default() -> Self {
+        TradingPlatform::new()
+    }
 }
 
 impl TradingPlatform {
     /// Creates a new instance without any data.
     pub fn new() -> Self {
-        TradingPlatform {
-            todo!();
+        TradingPlatform::with_clock(SystemClock)
+    }
+
+    /// Creates a new instance without any data that uses the provided [`Clock`]
+    pub fn with_clock(clock: impl Clock + Clone + 'static) -> Self {
+        TradingPlatform::from_parts(
+            MatchingEngine::with_clock(clock.clone()),
+            Accounts::with_clock(clock.clone()),
+            clock,
+        )
+    }
+
+    /// Recovers the state persisted in the [`WriteAheadLog`] at `path` (if any) and keeps
+    /// persisting every change to it. Fee volumes, sessions, mark prices, idempotency keys and
+    /// added risk checks are not persisted: they start over and have to be set again.
+    /// # Errors
+    /// The log can't be read, is corrupted or doesn't describe a valid history
+    pub fn open(
+        path: impl AsRef<Path>,
+        policy: FsyncPolicy,
+        clock: impl Clock + Clone + 'static,
+    ) -> Result<Self, ApplicationError> {
+        let (log, events) = WriteAheadLog::open(path, policy)?;
+        let (accounts, matching_engine) = wal::recover(events, clock.clone())?;
+        TradingPlatform::resume(log, accounts, matching_engine, clock)
+    }
+
+    /// Like [`TradingPlatform::open`], but starts from the snapshot at `snapshot` (if there is one)
+    /// and only replays the part of the log that was written after it
+    /// # Errors
+    /// The snapshot or log can't be read or is corrupted, or the log doesn't continue the snapshot
+    pub fn restore(
+        snapshot: impl AsRef<Path>,
+        path: impl AsRef<Path>,
+        policy: FsyncPolicy,
+        clock: impl Clock + Clone + 'static,
+    ) -> Result<Self, ApplicationError> {
+        let Some(snapshot) = Snapshot::load(snapshot)? else {
+            return TradingPlatform::open(path, policy, clock);
+        };
+        let (log, events) = WriteAheadLog::open_from(path, policy, snapshot.log_position)?;
+        let (accounts, matching_engine) = snapshot.restore(clock.clone());
+        let (accounts, matching_engine) =
+            wal::replay(accounts, matching_engine, events, clock.clone())?;
+        TradingPlatform::resume(log, accounts, matching_engine, clock)
+    }
+
+    /// Writes a snapshot of balances, books and open orders to `path`, so that a restart with
+    /// [`TradingPlatform::restore`] doesn't have to replay the whole log
+    /// # Errors
+    /// Writing the snapshot or syncing the log failed
+    pub fn snapshot(&mut self, path: impl AsRef<Path>) -> Result<(), ApplicationError> {
+        self.persist(None)?;
+        let position = match self.wal.as_mut() {
+            Some(log) => {
+                log.sync()?;
+                log.position()
+            }
+            None => 0,
+        };
+        Snapshot::capture(&self.accounts, &self.matching_engine, position).save(path)
+    }
+
+    /// Keeps persisting to `log` after its contents were recovered into `accounts` and `matching_engine`
+    fn resume(
+        log: WriteAheadLog,
+        accounts: Accounts,
+        matching_engine: MatchingEngine,
+        clock: impl Clock + 'static,
+    ) -> Result<Self, ApplicationError> {
+        let logged = accounts.journal().len();
+        let mut platform = TradingPlatform::from_parts(matching_engine, accounts, clock);
+        platform.logged = logged;
+        platform.wal = Some(log);
+        // the fee account may have just been opened
+        platform.persist(None)?;
+        Ok(platform)
+    }
+
+    fn from_parts(
+        matching_engine: MatchingEngine,
+        accounts: Accounts,
+        clock: impl Clock + 'static,
+    ) -> Self {
+        let mut platform = TradingPlatform {
+            matching_engine,
+            accounts,
+            sessions: Sessions::default(),
+            volumes: VolumeTracker::default(),
+            marks: BTreeMap::new(),
+            risk_checks: risk::standard_checks(),
+            requests: IdempotencyCache::default(),
+            clock: Box::new(clock),
+            wal: None,
+            logged: 0,
+        };
+        let account = platform.accounts.fee_schedule().account.clone();
+        // the account of a schedule isn't reserved, so opening it can't fail
+        let _ = platform.ensure_account(&account);
+        platform
+    }
+
+    /// Writes `event` and the transactions since the last call to the log as one entry. Changes
+    /// are applied in memory first, so when this fails they won't survive a restart.
+    fn persist(&mut self, event: Option<EngineEvent>) -> Result<(), ApplicationError> {
+        let Some(log) = self.wal.as_mut() else {
+            return Ok(());
+        };
+        let mut events: Vec<LogEvent> = event.into_iter().map(LogEvent::Engine).collect();
+        events.extend(
+            self.accounts.journal()[self.logged..]
+                .iter()
+                .cloned()
+                .map(LogEvent::Tx),
+        );
+        log.append(&events)?;
+        self.logged = self.accounts.journal().len();
+        Ok(())
+    }
+
+    /// Opens `account` unless it was opened before, closed or not
+    fn ensure_account(&mut self, account: &str) -> Result<(), ApplicationError> {
+        if self.accounts.status_of(account).is_none() {
+            self.accounts.open_account(account)?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the fees charged on every match from now on and opens the fee account if needed
+    pub fn set_fee_schedule(&mut self, schedule: FeeSchedule) -> Result<Tx, ApplicationError> {
+        self.ensure_account(&schedule.account)?;
+        let tx = self.accounts.set_fee_schedule(schedule)?;
+        self.persist(None)?;
+        Ok(tx)
+    }
+
+    /// Replaces the interest and custody fee rates and opens the house account if needed
+    pub fn set_interest_schedule(
+        &mut self,
+        schedule: InterestSchedule,
+    ) -> Result<Tx, ApplicationError> {
+        self.ensure_account(&schedule.account)?;
+        let tx = self.accounts.set_interest_schedule(schedule)?;
+        self.persist(None)?;
+        Ok(tx)
+    }
+
+    /// Accrues interest and custody fees on every balance, meant to be called once a day. See
+    /// [`Accounts::accrue_interest`].
+    pub fn accrue_interest(&mut self) -> Result<Option<Tx>, ApplicationError> {
+        let tx = self.accounts.accrue_interest();
+        self.persist(None)?;
+        Ok(tx)
+    }
+
+    /// Posts the whole minor units of accrued interest and custody fees against the house
+    /// account, meant to be called periodically, e.g. monthly
+    pub fn post_interest(&mut self) -> Result<Vec<Tx>, ApplicationError> {
+        let txs = self.accounts.post_interest()?;
+        self.persist(None)?;
+        Ok(txs)
+    }
+
+    /// Requires margin for positions from now on and opens the liquidation account if needed.
+    /// Leverage on buys comes from the credit line of the account.
+    pub fn set_margin_requirements(
+        &mut self,
+        requirements: MarginRequirements,
+    ) -> Result<Tx, ApplicationError> {
+        self.ensure_account(&requirements.account)?;
+        let tx = self.accounts.set_margin_requirements(requirements)?;
+        self.persist(None)?;
+        Ok(tx)
+    }
+
+    /// Sets the price that positions in the current market are valued at, e.g. from an index.
+    /// Kept in memory only.
+    pub fn set_mark_price(&mut self, price: u64) {
+        self.marks.insert(self.market().symbol(), price);
+    }
+
+    /// The price that positions in the current market are valued at, if one was set
+    pub fn mark_price(&self) -> Option<u64> {
+        self.marks.get(&self.market().symbol()).copied()
+    }
+
+    /// The position of `signer` in the current market, if it ever traded there
+    pub fn position(&self, signer: &str) -> Option<&Position> {
+        self.accounts.position(signer, &self.market().symbol())
+    }
+
+    /// Profit (or loss) of the position of `signer` in the current market if it was closed at the
+    /// mark price, see [`Position::unrealised_pnl`]
+    pub fn unrealised_pnl(&self, signer: &str) -> Result<i128, ApplicationError> {
+        self.accounts.balance_of(signer)?;
+        match self.position(signer) {
+            Some(position) => position.unrealised_pnl(self.mark_price(), self.market()),
+            None => Ok(0),
+        }
+    }
+
+    /// The net balance of `signer` plus what its positions are worth at the mark price.
+    /// Positions in other markets are valued at cost.
+    pub fn equity(&self, signer: &str) -> Result<i128, ApplicationError> {
+        let mut equity = self.accounts.net_balance(signer)?;
+        for position in self.accounts.positions() {
+            if position.account == signer {
+                equity += self.value_of(position)?;
+            }
+        }
+        Ok(equity)
+    }
+
+    /// Closes the positions in the current market of every account whose equity fell below the
+    /// maintenance margin against the liquidation account at the mark price, after cancelling
+    /// its open orders. Does nothing without margin requirements or a mark price.
+    /// # Errors
+    /// The liquidation account can't pay for a long position. The positions liquidated before
+    /// stay liquidated, the orders of the account that failed stay open.
+    pub fn liquidate(&mut self) -> Result<Vec<Tx>, ApplicationError> {
+        let (Some(margin), Some(mark)) = (
+            self.accounts.margin_requirements().cloned(),
+            self.mark_price(),
+        ) else {
+            return Ok(vec![]);
+        };
+        let symbol = self.market().symbol();
+        let accounts: Vec<String> = self
+            .accounts
+            .positions()
+            .into_iter()
+            .filter(|p| p.symbol == symbol && p.size != 0 && p.account != margin.account)
+            .map(|p| p.account.clone())
+            .collect();
+        let mut txs = vec![];
+        for account in accounts {
+            let Some(position) = self.accounts.position(&account, &symbol) else {
+                continue;
+            };
+            let value = position.value(Some(mark), self.market())?;
+            let requirement = margin.maintenance(value.unsigned_abs());
+            if self.equity(&account)? >= requirement as i128 {
+                continue;
+            }
+            // the value was a notional, so it fits
+            let notional = value.unsigned_abs() as u64;
+            self.accounts
+                .check_liquidation(&account, &margin.account, &symbol, mark, notional)?;
+            self.mass_cancel(&MassCancel::Signer(account.clone()))?;
+            let liquidated =
+                self.accounts
+                    .liquidate(&account, &margin.account, &symbol, mark, notional);
+            self.persist(None)?;
+            txs.push(liquidated?);
+        }
+        Ok(txs)
+    }
+
+    /// What `position` is worth at the mark price if it's in the current market, at cost otherwise
+    fn value_of(&self, position: &Position) -> Result<i128, ApplicationError> {
+        let mark = if position.symbol == self.market().symbol() {
+            self.mark_price()
+        } else {
+            None
+        };
+        position.value(mark, self.market())
+    }
+
+    /// Rejects an order that would leave `signer` with less equity than the initial margin of its
+    /// position if it was filled completely at its price. Orders that reduce the position always
+    /// pass. Other open orders aren't counted.
+    fn check_margin(&self, order: &Order) -> Result<(), ApplicationError> {
+        let Some(margin) = self.accounts.margin_requirements() else {
+            return Ok(());
+        };
+        let symbol = self.market().symbol();
+        let current = self
+            .accounts
+            .position(&order.signer, &symbol)
+            .cloned()
+            .unwrap_or_else(|| Position::new(&order.signer, &symbol));
+        let notional = self.market().notional(order.price, order.amount)?;
+        let mut position = current.clone();
+        position.fill(order.side.clone(), order.amount, notional);
+        if position.size.unsigned_abs() <= current.size.unsigned_abs() {
+            return Ok(());
+        }
+
+        let mark = self.mark_price().unwrap_or(order.price);
+        let value = position.value(Some(mark), self.market())?;
+        let paid = match order.side {
+            Side::Buy => notional as i128,
+            Side::Sell => -(notional as i128),
+        };
+        let equity = self.equity(&order.signer)? - self.value_of(&current)? - paid + value;
+        let requirement = margin.initial(value.unsigned_abs());
+        if equity < requirement as i128 {
+            return Err(ApplicationError::InsufficientMargin(
+                order.signer.clone(),
+                u64::try_from(requirement).unwrap_or(u64::MAX),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Sets the pre-trade limits of every account in the current market that doesn't have limits
+    /// of its own
+    pub fn set_market_risk_limits(&mut self, limits: RiskLimits) -> Result<Tx, ApplicationError> {
+        let tx = self
+            .accounts
+            .set_market_risk_limits(&self.market().symbol(), limits);
+        self.persist(None)?;
+        Ok(tx)
+    }
+
+    /// Sets the pre-trade limits of `signer` in the current market, they replace the ones of the
+    /// market
+    pub fn set_risk_limits(
+        &mut self,
+        signer: &str,
+        limits: RiskLimits,
+    ) -> Result<Tx, ApplicationError> {
+        let tx = self
+            .accounts
+            .set_risk_limits(signer, &self.market().symbol(), limits)?;
+        self.persist(None)?;
+        Ok(tx)
+    }
+
+    /// The pre-trade limits of `signer` in the current market: its own, the market's or none
+    pub fn risk_limits(&self, signer: &str) -> RiskLimits {
+        self.accounts
+            .risk_limits_of(signer, &self.market().symbol())
+    }
+
+    /// Adds a check that runs after the [`risk::standard_checks`] and the ones added before it
+    pub fn add_risk_check(&mut self, check: impl RiskCheck + 'static) {
+        self.risk_checks.push(Box::new(check));
+    }
+
+    /// Runs the pre-trade risk checks against `order`, the first one that fails rejects it
+    fn check_risk(&self, order: &Order) -> Result<(), ApplicationError> {
+        let limits = self.risk_limits(&order.signer);
+        let open_orders = self.matching_engine.open_orders(&order.signer);
+        let context = RiskContext {
+            limits: &limits,
+            market: self.market(),
+            notional: self.market().notional(order.price, order.amount)?,
+            open_orders: &open_orders,
+            position: self.position(&order.signer).map_or(0, |p| p.size),
+            reference_price: self.mark_price(),
+            available: self.accounts.available(&order.signer)?,
+        };
+        self.risk_checks
+            .iter()
+            .try_for_each(|check| check.check(order, &context))
+            .map_err(|reason| ApplicationError::OrderRejected(order.signer.clone(), reason))
+    }
+
+    /// The assets that are traded
+    pub fn market(&self) -> &Market {
+        self.accounts.market()
+    }
+
+    /// Sets the assets that are traded. Balances are held in the quote asset.
+    /// # Errors
+    /// Orders rest in the book, or the ledger holds amounts already, see [`Accounts::set_market`]
+    pub fn set_market(&mut self, market: Market) -> Result<Tx, ApplicationError> {
+        let resting = self.orderbook().len();
+        if resting > 0 {
+            return Err(ApplicationError::BookNotEmpty(resting));
         }
+        let tx = self.accounts.set_market(market)?;
+        self.persist(None)?;
+        Ok(tx)
+    }
+
+    /// Changes how long idempotency keys of orders and transactions are remembered. Keys are kept
+    /// in memory only and are forgotten on restart.
+    pub fn set_idempotency_window(&mut self, window: Timestamp) {
+        self.requests.set_window(window);
+        self.accounts.set_idempotency_window(window);
+    }
+
+    /// Replaces the withdrawal and transfer limits of `signer`. Trade settlements aren't limited.
+    pub fn set_limits(&mut self, signer: &str, limits: Limits) -> Result<Tx, ApplicationError> {
+        let tx = self.accounts.set_limits(signer, limits)?;
+        self.persist(None)?;
+        Ok(tx)
     }
 
     /// Fetches the complete order book at this time
     pub fn orderbook(&self) -> Vec<PartialOrder> {
-        todo!();
+        self.matching_engine
+            .bids
+            .values()
+            .chain(self.matching_engine.asks.values())
+            .flat_map(|orders| orders.iter().cloned())
+            .collect()
     }
 
-    /// Withdraw funds
-    pub fn balance_of(&mut self, signer: &str) -> Result<&u64, ApplicationError> {
-        todo!();
+    /// Fetch the balance of an account
+    pub fn balance_of(&self, signer: &str) -> Result<&u64, ApplicationError> {
+        self.accounts.balance_of(signer)
+    }
+
+    /// What `signer` owes on its credit line
+    pub fn debt_of(&self, signer: &str) -> Result<u64, ApplicationError> {
+        self.accounts.debt_of(signer)
+    }
+
+    /// Lets `signer` go up to `limit` below zero when withdrawing, sending or paying for trades
+    pub fn set_credit_limit(&mut self, signer: &str, limit: u64) -> Result<Tx, ApplicationError> {
+        let tx = self.accounts.set_credit_limit(signer, limit)?;
+        self.persist(None)?;
+        Ok(tx)
+    }
+
+    /// Opens a new, empty account
+    pub fn open_account(&mut self, signer: &str) -> Result<Tx, ApplicationError> {
+        let tx = self.accounts.open_account(signer)?;
+        self.persist(None)?;
+        Ok(tx)
+    }
+
+    /// Opens a new, empty sub-account of `parent` that signs its own orders, see
+    /// [`Accounts::open_sub_account`]
+    pub fn open_sub_account(&mut self, parent: &str, signer: &str) -> Result<Tx, ApplicationError> {
+        let tx = self.accounts.open_sub_account(parent, signer)?;
+        self.persist(None)?;
+        Ok(tx)
+    }
+
+    /// The combined net balance of `signer` and all of its sub-accounts
+    pub fn aggregate_balance(&self, signer: &str) -> Result<i128, ApplicationError> {
+        self.accounts.aggregate_balance(signer)
+    }
+
+    /// Moves funds between two accounts of the same entity without counting towards limits
+    pub fn transfer_internal(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: u64,
+    ) -> Result<Tx, ApplicationError> {
+        let tx = self.accounts.transfer_internal(sender, recipient, amount)?;
+        self.persist(None)?;
+        Ok(tx)
+    }
+
+    /// Moves `amount` from `sender` into escrow until `condition` allows it to be paid out to
+    /// `recipient` or back. Returns the transaction, its sequence number is the escrow's id.
+    pub fn open_escrow(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: u64,
+        condition: Condition,
+    ) -> Result<Tx, ApplicationError> {
+        let tx = self
+            .accounts
+            .open_escrow(sender, recipient, amount, condition)?;
+        self.persist(None)?;
+        Ok(tx)
+    }
+
+    /// Pays escrow `id` out to its recipient on behalf of `by`
+    pub fn release_escrow(&mut self, id: u64, by: &str) -> Result<Tx, ApplicationError> {
+        let tx = self.accounts.release_escrow(id, by)?;
+        self.persist(None)?;
+        Ok(tx)
+    }
+
+    /// Pays escrow `id` back to its sender on behalf of `by`
+    pub fn refund_escrow(&mut self, id: u64, by: &str) -> Result<Tx, ApplicationError> {
+        let tx = self.accounts.refund_escrow(id, by)?;
+        self.persist(None)?;
+        Ok(tx)
+    }
+
+    /// The open escrow with this id
+    pub fn escrow(&self, id: u64) -> Option<&Escrow> {
+        self.accounts.escrow(id)
+    }
+
+    /// Schedules a transfer from `sender` to `recipient` at `start` and then at the times of
+    /// `recurrence`, see [`Accounts::schedule_transfer`]. The schedule is persisted with the
+    /// ledger.
+    pub fn schedule_transfer(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: u64,
+        start: Timestamp,
+        recurrence: Recurrence,
+        retry: RetryPolicy,
+    ) -> Result<Tx, ApplicationError> {
+        let tx = self
+            .accounts
+            .schedule_transfer(sender, recipient, amount, start, recurrence, retry)?;
+        self.persist(None)?;
+        Ok(tx)
+    }
+
+    /// Stops the scheduled transfer with this id
+    pub fn cancel_schedule(&mut self, id: u64) -> Result<Tx, ApplicationError> {
+        let tx = self.accounts.cancel_schedule(id)?;
+        self.persist(None)?;
+        Ok(tx)
+    }
+
+    /// Executes every scheduled transfer that is due, meant to be called periodically. Returns the
+    /// transfers and failed attempts.
+    pub fn run_schedules(&mut self) -> Result<Vec<Tx>, ApplicationError> {
+        let txs = self.accounts.run_schedules();
+        self.persist(None)?;
+        Ok(txs)
+    }
+
+    /// The scheduled transfer with this id, if it has occurrences left
+    pub fn schedule(&self, id: u64) -> Option<&Schedule> {
+        self.accounts.schedule(id)
+    }
+
+    /// Blocks withdrawals, outgoing transfers and trading for `signer` and cancels their open
+    /// orders. Incoming funds are still accepted.
+    pub fn freeze(&mut self, signer: &str) -> Result<Tx, ApplicationError> {
+        let tx = self.accounts.freeze(signer)?;
+        let selector = MassCancel::Signer(signer.to_string());
+        self.matching_engine.mass_cancel(&selector);
+        self.persist(Some(EngineEvent::MassCancel(selector)))?;
+        Ok(tx)
+    }
+
+    /// Lifts a [`TradingPlatform::freeze`]
+    pub fn unfreeze(&mut self, signer: &str) -> Result<Tx, ApplicationError> {
+        let tx = self.accounts.unfreeze(signer)?;
+        self.persist(None)?;
+        Ok(tx)
+    }
+
+    /// Closes an account for good. It must be empty, hold no positions and have no orders in the
+    /// book.
+    pub fn close_account(&mut self, signer: &str) -> Result<Tx, ApplicationError> {
+        let open = self.matching_engine.open_orders(signer).len();
+        if open > 0 {
+            return Err(ApplicationError::AccountHasOpenOrders(
+                signer.to_string(),
+                open,
+            ));
+        }
+        let tx = self.accounts.close(signer)?;
+        self.persist(None)?;
+        Ok(tx)
     }
 
     /// Deposit funds
     pub fn deposit(&mut self, signer: &str, amount: u64) -> Result<Tx, ApplicationError> {
-        todo!();
+        let tx = self.accounts.deposit(signer, amount)?;
+        self.persist(None)?;
+        Ok(tx)
     }
 
     /// Withdraw funds
     pub fn withdraw(&mut self, signer: &str, amount: u64) -> Result<Tx, ApplicationError> {
-        todo!();
+        let tx = self.accounts.withdraw(signer, amount)?;
+        self.persist(None)?;
+        Ok(tx)
     }
 
     /// Transfer funds between sender and recipient
@@ -46,14 +630,342 @@ impl TradingPlatform {
         sender: &str,
         recipient: &str,
         amount: u64,
-    ) -> Result<(Tx, Tx), ApplicationError> {
-        todo!();
+    ) -> Result<Tx, ApplicationError> {
+        let tx = self.accounts.send(sender, recipient, amount)?;
+        self.persist(None)?;
+        Ok(tx)
     }
 
-    /// Process a given order and apply the outcome to the accounts involved. Note that there are very few safeguards in place.
+    /// Deposit `amount` of the quote asset, see [`Accounts::deposit_amount`]
+    pub fn deposit_amount(&mut self, signer: &str, amount: Amount) -> Result<Tx, ApplicationError> {
+        let tx = self.accounts.deposit_amount(signer, amount)?;
+        self.persist(None)?;
+        Ok(tx)
+    }
+
+    /// Withdraw `amount` of the quote asset, see [`Accounts::withdraw_amount`]
+    pub fn withdraw_amount(
+        &mut self,
+        signer: &str,
+        amount: Amount,
+    ) -> Result<Tx, ApplicationError> {
+        let tx = self.accounts.withdraw_amount(signer, amount)?;
+        self.persist(None)?;
+        Ok(tx)
+    }
+
+    /// Transfer `amount` of the quote asset, see [`Accounts::send_amount`]
+    pub fn send_amount(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Amount,
+    ) -> Result<Tx, ApplicationError> {
+        let tx = self.accounts.send_amount(sender, recipient, amount)?;
+        self.persist(None)?;
+        Ok(tx)
+    }
+
+    /// Process a given order and apply the outcome to the accounts involved. Orders of unknown,
+    /// frozen or closed accounts are rejected, as are orders that fail a pre-trade risk check or
+    /// the margin requirements.
     pub fn order(&mut self, order: Order) -> Result<Receipt, ApplicationError> {
-        todo!();
+        // the trades, their settlement and the log all happen at the same time
+        let now = self.clock.now();
+        let tradeable = match self.accounts.status_of(&order.signer) {
+            _ if order.signer == ESCROW_ACCOUNT => {
+                Err(ApplicationError::ReservedAccount(order.signer.clone()))
+            }
+            Some(AccountStatus::Frozen) => {
+                Err(ApplicationError::AccountFrozen(order.signer.clone()))
+            }
+            // no fill of an order that passes can overflow, it's at most the order's notional
+            // for a buy and the resting order's notional for a sell
+            _ => self
+                .accounts
+                .balance_of(&order.signer)
+                .and_then(|_| self.check_risk(&order))
+                .and_then(|_| self.check_margin(&order))
+                .and_then(|_| self.check_settlement(&order, now)),
+        };
+        let settlements = match tradeable {
+            Ok(settlements) => settlements,
+            Err(e) => {
+                self.matching_engine.reject(order.clone());
+                self.persist(Some(EngineEvent::Reject { order }))?;
+                return Err(e);
+            }
+        };
+
+        let mut receipt = self.matching_engine.process_at(order.clone(), now)?;
+        let settled = self.settle(&order, &mut receipt, settlements, now);
+        self.persist(Some(EngineEvent::Process {
+            timestamp: now,
+            order,
+        }))?;
+        settled.map(|_| receipt)
+    }
+
+    /// Like [`TradingPlatform::order`], but a retry with the same `key` returns the original
+    /// receipt instead of placing the order again
+    /// # Errors
+    /// See [`TradingPlatform::order`], or `key` was used for a different order
+    pub fn order_idempotent(
+        &mut self,
+        key: &str,
+        order: Order,
+    ) -> Result<Receipt, ApplicationError> {
+        let now = self.clock.now();
+        if let Some((original, receipt)) = self.requests.get(key, now) {
+            return if *original == order {
+                Ok(receipt.clone())
+            } else {
+                Err(ApplicationError::IdempotencyKeyReused(key.to_string()))
+            };
+        }
+        let receipt = self.order(order.clone())?;
+        self.requests.insert(key, now, (order, receipt.clone()));
+        Ok(receipt)
     }
+
+    /// Pays for every match of `order` and charges the fees, as checked before it was matched
+    fn settle(
+        &mut self,
+        order: &Order,
+        receipt: &mut Receipt,
+        settlements: Vec<Settlement>,
+        now: Timestamp,
+    ) -> Result<(), ApplicationError> {
+        for (m, settlement) in receipt.matches.iter().zip(settlements) {
+            let Settlement {
+                legs,
+                fee,
+                notional,
+            } = settlement;
+            // Payment and fees settle together or not at all
+            let cause = Cause::Trade {
+                taker_ordinal: receipt.ordinal,
+                maker_ordinal: m.ordinal,
+            };
+            self.accounts.execute_batch(
+                legs,
+                TxContext::caused_by(cause.clone()).with_memo("settlement"),
+            )?;
+            let (buyer, seller) = match order.side {
+                Side::Buy => (&order.signer, &m.signer),
+                Side::Sell => (&m.signer, &order.signer),
+            };
+            for (signer, side) in [(buyer, Side::Buy), (seller, Side::Sell)] {
+                let fill = Fill {
+                    symbol: self.market().symbol(),
+                    side,
+                    amount: m.amount,
+                    price: m.price,
+                    notional,
+                };
+                self.accounts
+                    .fill(signer, fill, TxContext::caused_by(cause.clone()))?;
+            }
+            self.volumes.record(&order.signer, now, notional);
+            self.volumes.record(&m.signer, now, notional);
+            receipt.fees.push(fee);
+        }
+        Ok(())
+    }
+
+    /// The settlement of every match `order` would get right now, without changing anything.
+    /// Runs before the order reaches the book so a failed settlement can't leave it matched.
+    /// # Errors
+    /// A match couldn't be settled
+    fn check_settlement(
+        &mut self,
+        order: &Order,
+        now: Timestamp,
+    ) -> Result<Vec<Settlement>, ApplicationError> {
+        let matches = self.matching_engine.preview(order)?;
+        let settlements = self.settlements(order, &matches, now)?;
+        let legs: Vec<Leg> = settlements
+            .iter()
+            .flat_map(|s| s.legs.iter().cloned())
+            .collect();
+        self.accounts.check_batch(&legs)?;
+        Ok(settlements)
+    }
+
+    /// How each of the `matches` of `order` settles, based on the orders before they are matched
+    fn settlements(
+        &mut self,
+        order: &Order,
+        matches: &[PartialOrder],
+        now: Timestamp,
+    ) -> Result<Vec<Settlement>, ApplicationError> {
+        let taker = &order.signer;
+        // the volume tracker only sees a match once it's settled
+        let mut traded: BTreeMap<&str, u128> = BTreeMap::new();
+        // price * amount of the matches so far
+        let mut taker_filled = 0u128;
+        let mut settlements = vec![];
+        for m in matches {
+            // The buyer pays the maker's price for every unit matched. Its order is rounded up
+            // as a whole, not every fill on its own.
+            let buyer_filled = match order.side {
+                Side::Buy => taker_filled,
+                Side::Sell => self
+                    .matching_engine
+                    .order_status(m.ordinal)
+                    .map(|s| s.filled_notional)
+                    .unwrap_or(0),
+            };
+            let notional = self
+                .market()
+                .fill_notional(buyer_filled, m.price, m.amount)?;
+            taker_filled += m.price as u128 * m.amount as u128;
+            let (buyer, seller) = match order.side {
+                Side::Buy => (taker, &m.signer),
+                Side::Sell => (&m.signer, taker),
+            };
+            let mut legs = vec![Leg::Transfer {
+                sender: buyer.clone(),
+                recipient: seller.clone(),
+                amount: notional,
+            }];
+
+            // Fee tiers are based on the volume before this fill. Taker fees are collected first
+            // so they can fund the maker's rebate.
+            let taker_volume =
+                self.volumes.volume(taker, now) + traded.get(taker.as_str()).unwrap_or(&0);
+            let maker_volume =
+                self.volumes.volume(&m.signer, now) + traded.get(m.signer.as_str()).unwrap_or(&0);
+            let fee = FillFee {
+                maker_ordinal: m.ordinal,
+                maker_fee: self
+                    .accounts
+                    .fee_schedule()
+                    .maker_fee(maker_volume, notional),
+                taker_fee: self
+                    .accounts
+                    .fee_schedule()
+                    .taker_fee(taker_volume, notional),
+            };
+            legs.extend(self.fee_leg(taker, fee.taker_fee));
+            legs.extend(self.fee_leg(&m.signer, fee.maker_fee));
+
+            *traded.entry(taker).or_default() += notional as u128;
+            *traded.entry(&m.signer).or_default() += notional as u128;
+            settlements.push(Settlement {
+                legs,
+                fee,
+                notional,
+            });
+        }
+        Ok(settlements)
+    }
+
+    /// Moves a fee from `signer` to the fee account, or a rebate (negative fee) the other way
+    fn fee_leg(&self, signer: &str, fee: i128) -> Option<Leg> {
+        let amount = u64::try_from(fee.unsigned_abs()).unwrap_or(u64::MAX);
+        let account = self.accounts.fee_schedule().account.clone();
+        match fee.signum() {
+            1 => Some(Leg::Transfer {
+                sender: signer.to_string(),
+                recipient: account,
+                amount,
+            }),
+            -1 => Some(Leg::Transfer {
+                sender: account,
+                recipient: signer.to_string(),
+                amount,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Double-entry view of every transaction so far, with the fee account booked as revenue
+    pub fn trial_balance(&self) -> TrialBalance {
+        let chart = ChartOfAccounts::default()
+            .with(&self.accounts.fee_schedule().account, AccountType::Revenue);
+        TrialBalance::from_journal(&chart, self.accounts.journal())
+    }
+
+    /// Every transaction that was recovered or made since, oldest first. After a restore it starts
+    /// at the snapshot.
+    pub fn journal(&self) -> &[Tx] {
+        self.accounts.journal()
+    }
+
+    /// Digest of all balances and resting orders. Replicas and replays whose hashes differ have
+    /// diverged; see [`StateHash`] for what matching hashes don't prove.
+    pub fn state_hash(&self) -> StateHash {
+        self.accounts
+            .state_hash()
+            .combine(self.matching_engine.book_hash())
+    }
+
+    /// Cancels an open order
+    pub fn cancel(&mut self, ordinal: u64) -> Result<PartialOrder, ApplicationError> {
+        let cancelled = self.matching_engine.cancel(ordinal)?;
+        self.persist(Some(EngineEvent::Cancel { ordinal }))?;
+        Ok(cancelled)
+    }
+
+    /// Cancels every open order selected by `selector` at once
+    pub fn mass_cancel(
+        &mut self,
+        selector: &MassCancel,
+    ) -> Result<CancelReceipt, ApplicationError> {
+        let receipt = self.matching_engine.mass_cancel(selector);
+        self.persist(Some(EngineEvent::MassCancel(selector.clone())))?;
+        Ok(receipt)
+    }
+
+    /// Starts a client session. With `cancel_on_disconnect` the orders placed through the session
+    /// are cancelled when it ends.
+    pub fn open_session(&mut self, cancel_on_disconnect: bool) -> SessionId {
+        self.sessions.open(cancel_on_disconnect)
+    }
+
+    /// Places an order through a session, see [`TradingPlatform::order`]
+    pub fn order_in_session(
+        &mut self,
+        session: SessionId,
+        order: Order,
+    ) -> Result<Receipt, ApplicationError> {
+        self.sessions.get(session)?;
+        let receipt = self.order(order)?;
+        self.sessions.track(session, receipt.ordinal)?;
+        Ok(receipt)
+    }
+
+    /// Ends a client session and cancels its open orders if it was opened with `cancel_on_disconnect`
+    pub fn end_session(&mut self, session: SessionId) -> Result<CancelReceipt, ApplicationError> {
+        let session = self.sessions.close(session)?;
+        if session.cancel_on_disconnect {
+            let ordinals: Vec<u64> = session.orders.into_iter().collect();
+            let receipt = self.matching_engine.cancel_many(ordinals.clone());
+            self.persist(Some(EngineEvent::CancelMany(ordinals)))?;
+            Ok(receipt)
+        } else {
+            Ok(CancelReceipt::default())
+        }
+    }
+
+    /// The status of the order with sequence number `ordinal`
+    pub fn order_status(&self, ordinal: u64) -> Option<&OrderStatus> {
+        self.matching_engine.order_status(ordinal)
+    }
+
+    /// All open orders of `signer`, oldest first
+    pub fn open_orders(&self, signer: &str) -> Vec<&OrderStatus> {
+        self.matching_engine.open_orders(signer)
+    }
+}
+
+/// The payment and fee legs of one match, with the fees and what the buyer pays
+#[derive(Debug)]
+struct Settlement {
+    legs: Vec<Leg>,
+    fee: FillFee,
+    notional: u64,
 }
 
 #[cfg(test)]
@@ -61,10 +973,18 @@ mod tests {
     // reduce the warnings for naming tests
     #![allow(non_snake_case)]
 
+    use crate::{
+        amount::Asset,
+        clock::{ManualClock, DAY},
+        core::OrderState,
+        fees::{FeeTier, VOLUME_WINDOW},
+        interest::Rates,
+        risk::Rejection,
+    };
+
     use super::*;
 
     #[test]
-    #[ignore]
     fn test_TradingPlatform_order_requires_deposit_to_order() {
         let mut trading_platform = TradingPlatform::new();
 
@@ -86,7 +1006,9 @@ mod tests {
         let mut trading_platform = TradingPlatform::new();
 
         // Set up accounts
+        assert!(trading_platform.accounts.open_account("ALICE").is_ok());
         assert!(trading_platform.accounts.deposit("ALICE", 100).is_ok());
+        assert!(trading_platform.accounts.open_account("BOB").is_ok());
         assert!(trading_platform.accounts.deposit("BOB", 100).is_ok());
 
         let alice_receipt = trading_platform
@@ -133,7 +1055,9 @@ mod tests {
         let mut trading_platform = TradingPlatform::new();
 
         // Set up accounts
+        assert!(trading_platform.accounts.open_account("ALICE").is_ok());
         assert!(trading_platform.accounts.deposit("ALICE", 100).is_ok());
+        assert!(trading_platform.accounts.open_account("BOB").is_ok());
         assert!(trading_platform.accounts.deposit("BOB", 100).is_ok());
 
         let alice_receipt = trading_platform
@@ -182,8 +1106,11 @@ mod tests {
         let mut trading_platform = TradingPlatform::new();
 
         // Set up accounts
+        assert!(trading_platform.accounts.open_account("ALICE").is_ok());
         assert!(trading_platform.accounts.deposit("ALICE", 100).is_ok());
+        assert!(trading_platform.accounts.open_account("BOB").is_ok());
         assert!(trading_platform.accounts.deposit("BOB", 100).is_ok());
+        assert!(trading_platform.accounts.open_account("CHARLIE").is_ok());
         assert!(trading_platform.accounts.deposit("CHARLIE", 100).is_ok());
 
         let alice_receipt = trading_platform
@@ -253,7 +1180,9 @@ mod tests {
         let mut trading_platform = TradingPlatform::new();
 
         // Set up accounts
+        assert!(trading_platform.accounts.open_account("ALICE").is_ok());
         assert!(trading_platform.accounts.deposit("ALICE", 100).is_ok());
+        assert!(trading_platform.accounts.open_account("CHARLIE").is_ok());
         assert!(trading_platform.accounts.deposit("CHARLIE", 100).is_ok());
 
         let alice_receipt = trading_platform
@@ -311,7 +1240,9 @@ mod tests {
         let mut trading_platform = TradingPlatform::new();
 
         // Set up accounts
+        assert!(trading_platform.accounts.open_account("ALICE").is_ok());
         assert!(trading_platform.accounts.deposit("ALICE", 100).is_ok());
+        assert!(trading_platform.accounts.open_account("BOB").is_ok());
         assert!(trading_platform.accounts.deposit("BOB", 100).is_ok());
 
         let alice_receipt = trading_platform
@@ -341,4 +1272,1060 @@ mod tests {
         assert_eq!(trading_platform.accounts.balance_of("ALICE"), Ok(&100));
         assert_eq!(trading_platform.accounts.balance_of("BOB"), Ok(&100));
     }
+
+    #[test]
+    fn test_TradingPlatform_order_status_and_open_orders() {
+        let mut trading_platform = TradingPlatform::new();
+        assert!(trading_platform.open_account("ALICE").is_ok());
+        assert!(trading_platform.deposit("ALICE", 100).is_ok());
+        assert!(trading_platform.open_account("BOB").is_ok());
+        assert!(trading_platform.deposit("BOB", 100).is_ok());
+
+        trading_platform
+            .order(Order {
+                price: 10,
+                amount: 2,
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+            })
+            .unwrap();
+        trading_platform
+            .order(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Buy,
+                signer: "BOB".to_string(),
+            })
+            .unwrap();
+
+        let open = trading_platform.open_orders("ALICE");
+        assert_eq!(open.len(), 1);
+        assert_eq!(open[0].state, OrderState::PartiallyFilled);
+        assert_eq!(open[0].remaining(), 1);
+        assert_eq!(
+            trading_platform.order_status(2).unwrap().state,
+            OrderState::Filled
+        );
+
+        // Orders from unknown signers are rejected
+        assert!(trading_platform
+            .order(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Buy,
+                signer: "MALLORY".to_string(),
+            })
+            .is_err());
+        assert_eq!(
+            trading_platform.order_status(3).unwrap().state,
+            OrderState::Rejected
+        );
+
+        trading_platform.cancel(1).unwrap();
+        assert!(trading_platform.open_orders("ALICE").is_empty());
+        assert!(trading_platform.orderbook().is_empty());
+    }
+
+    #[test]
+    fn test_TradingPlatform_end_session_cancels_on_disconnect() {
+        let mut trading_platform = TradingPlatform::new();
+        assert!(trading_platform.open_account("ALICE").is_ok());
+        assert!(trading_platform.deposit("ALICE", 100).is_ok());
+
+        let session = trading_platform.open_session(true);
+        let keep = trading_platform.open_session(false);
+        for (session, price) in [(session, 10), (session, 11), (keep, 12)] {
+            trading_platform
+                .order_in_session(
+                    session,
+                    Order {
+                        price,
+                        amount: 1,
+                        side: Side::Sell,
+                        signer: "ALICE".to_string(),
+                    },
+                )
+                .unwrap();
+        }
+        trading_platform.cancel(2).unwrap();
+
+        let receipt = trading_platform.end_session(session).unwrap();
+        assert_eq!(receipt.cancelled.len(), 1);
+        assert_eq!(receipt.cancelled[0].ordinal, 1);
+        assert_eq!(
+            trading_platform.end_session(session),
+            Err(ApplicationError::SessionNotFound(session))
+        );
+
+        assert_eq!(
+            trading_platform.end_session(keep),
+            Ok(CancelReceipt::default())
+        );
+        assert_eq!(trading_platform.orderbook().len(), 1);
+
+        let receipt = trading_platform
+            .mass_cancel(&MassCancel::Signer("ALICE".to_string()))
+            .unwrap();
+        assert_eq!(receipt.amount(), 1);
+        assert!(trading_platform.orderbook().is_empty());
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_charges_tiered_fees() {
+        let clock = ManualClock::new(0);
+        let mut trading_platform = TradingPlatform::with_clock(clock.clone());
+        trading_platform
+            .set_fee_schedule(FeeSchedule::new(
+                "HOUSE",
+                vec![
+                    FeeTier {
+                        min_volume: 0,
+                        maker_bps: -10,
+                        taker_bps: 20,
+                    },
+                    FeeTier {
+                        min_volume: 10_000,
+                        maker_bps: 0,
+                        taker_bps: 10,
+                    },
+                ],
+            ))
+            .unwrap();
+        assert!(trading_platform.open_account("ALICE").is_ok());
+        assert!(trading_platform.deposit("ALICE", 100_000).is_ok());
+        assert!(trading_platform.open_account("BOB").is_ok());
+        assert!(trading_platform.deposit("BOB", 100_000).is_ok());
+
+        let sell = Order {
+            price: 100,
+            amount: 100,
+            side: Side::Sell,
+            signer: "ALICE".to_string(),
+        };
+        let buy = Order {
+            side: Side::Buy,
+            signer: "BOB".to_string(),
+            ..sell.clone()
+        };
+
+        trading_platform.order(sell.clone()).unwrap();
+        let receipt = trading_platform.order(buy.clone()).unwrap();
+        assert_eq!(
+            receipt.fees,
+            vec![FillFee {
+                maker_ordinal: 1,
+                maker_fee: -10,
+                taker_fee: 20,
+            }]
+        );
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&110_010));
+        assert_eq!(trading_platform.balance_of("BOB"), Ok(&89_980));
+        assert_eq!(trading_platform.balance_of("HOUSE"), Ok(&10));
+
+        // Both signers now qualify for the next tier
+        trading_platform.order(sell.clone()).unwrap();
+        let receipt = trading_platform.order(buy.clone()).unwrap();
+        assert_eq!(receipt.fees[0].maker_fee, 0);
+        assert_eq!(receipt.fees[0].taker_fee, 10);
+        assert_eq!(trading_platform.balance_of("HOUSE"), Ok(&20));
+
+        // ... until their volume rolls off
+        clock.advance(VOLUME_WINDOW);
+        trading_platform.order(sell).unwrap();
+        let receipt = trading_platform.order(buy).unwrap();
+        assert_eq!(receipt.fees[0].taker_fee, 20);
+        assert_eq!(trading_platform.balance_of("HOUSE"), Ok(&30));
+
+        // fees and rebates move money around without creating any
+        let trial_balance = trading_platform.trial_balance();
+        assert!(trial_balance.is_balanced());
+        assert_eq!(trial_balance.rows["HOUSE"].balance(), 30);
+        assert_eq!(trial_balance.reconcile(&trading_platform.accounts), Ok(()));
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_links_settlement_txs_to_trade() {
+        let mut trading_platform = TradingPlatform::new();
+        assert!(trading_platform.open_account("ALICE").is_ok());
+        assert!(trading_platform.deposit("ALICE", 100).is_ok());
+        assert!(trading_platform.open_account("BOB").is_ok());
+        assert!(trading_platform.deposit("BOB", 100).is_ok());
+
+        trading_platform
+            .order(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+            })
+            .unwrap();
+        trading_platform
+            .order(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Buy,
+                signer: "BOB".to_string(),
+            })
+            .unwrap();
+
+        let settlement: Vec<&Tx> = trading_platform
+            .accounts
+            .journal()
+            .iter()
+            .filter(|tx| {
+                tx.meta().cause
+                    == Some(Cause::Trade {
+                        taker_ordinal: 2,
+                        maker_ordinal: 1,
+                    })
+            })
+            .collect();
+        // the payment, then the fills of the buyer and the seller
+        assert_eq!(settlement.len(), 3);
+        assert!(matches!(
+            settlement[1],
+            Tx::PositionFilled { account, fill, .. } if account == "BOB" && fill.side == Side::Buy
+        ));
+        assert_eq!(
+            settlement[0].leg(),
+            Some(Leg::Transfer {
+                sender: "BOB".to_string(),
+                recipient: "ALICE".to_string(),
+                amount: 10,
+            })
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_open_recovers_from_log() {
+        let path = std::env::temp_dir().join(format!("platform-{}.wal", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let clock = ManualClock::new(1_000);
+
+        let mut trading_platform =
+            TradingPlatform::open(&path, FsyncPolicy::Always, clock.clone()).unwrap();
+        trading_platform.open_account("ALICE").unwrap();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.open_account("BOB").unwrap();
+        trading_platform.deposit("BOB", 100).unwrap();
+        trading_platform
+            .order(Order {
+                price: 10,
+                amount: 3,
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+            })
+            .unwrap();
+        clock.advance(1);
+        trading_platform
+            .order(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Buy,
+                signer: "BOB".to_string(),
+            })
+            .unwrap();
+        assert!(trading_platform
+            .order(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Buy,
+                signer: "CAROL".to_string(),
+            })
+            .is_err());
+        let before = trading_platform.accounts.journal().to_vec();
+        let orderbook = trading_platform.orderbook();
+        drop(trading_platform);
+
+        let recovered = TradingPlatform::open(&path, FsyncPolicy::Always, clock.clone()).unwrap();
+        assert_eq!(recovered.accounts.journal(), before.as_slice());
+        assert_eq!(recovered.balance_of("ALICE"), Ok(&110));
+        assert_eq!(recovered.balance_of("BOB"), Ok(&90));
+        assert_eq!(recovered.orderbook(), orderbook);
+        assert_eq!(
+            recovered.order_status(1).unwrap().state,
+            OrderState::PartiallyFilled
+        );
+        assert_eq!(
+            recovered.order_status(3).unwrap().state,
+            OrderState::Rejected
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_TradingPlatform_restore_from_snapshot_and_log_tail() {
+        let dir = std::env::temp_dir();
+        let log = dir.join(format!("platform-tail-{}.wal", std::process::id()));
+        let snapshot = dir.join(format!("platform-tail-{}.snap", std::process::id()));
+        let _ = std::fs::remove_file(&log);
+        let _ = std::fs::remove_file(&snapshot);
+        let clock = ManualClock::new(1_000);
+        let order = |signer: &str, side: Side, amount: u64| Order {
+            price: 10,
+            amount,
+            side,
+            signer: signer.to_string(),
+        };
+
+        let mut trading_platform =
+            TradingPlatform::open(&log, FsyncPolicy::Always, clock.clone()).unwrap();
+        trading_platform.open_account("ALICE").unwrap();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.open_account("BOB").unwrap();
+        trading_platform.deposit("BOB", 100).unwrap();
+        trading_platform
+            .order(order("ALICE", Side::Sell, 5))
+            .unwrap();
+        trading_platform.snapshot(&snapshot).unwrap();
+
+        trading_platform.order(order("BOB", Side::Buy, 2)).unwrap();
+        trading_platform.cancel(1).unwrap();
+        trading_platform.order(order("BOB", Side::Buy, 1)).unwrap();
+        trading_platform.withdraw("ALICE", 5).unwrap();
+        drop(trading_platform);
+
+        let restored =
+            TradingPlatform::restore(&snapshot, &log, FsyncPolicy::Always, clock.clone()).unwrap();
+        let replayed = TradingPlatform::open(&log, FsyncPolicy::Always, clock.clone()).unwrap();
+        assert_eq!(restored.accounts.balances(), replayed.accounts.balances());
+        assert_eq!(restored.balance_of("ALICE"), Ok(&115));
+        assert_eq!(restored.orderbook(), replayed.orderbook());
+        assert_eq!(restored.open_orders("BOB"), replayed.open_orders("BOB"));
+        assert_eq!(restored.matching_engine.ordinal, 3);
+        assert_eq!(restored.state_hash(), replayed.state_hash());
+        // only the tail was replayed: a settlement with its two fills and a withdrawal
+        assert_eq!(restored.accounts.journal().len(), 4);
+        assert_eq!(restored.position("BOB").map(|p| p.size), Some(2));
+        drop(restored);
+        drop(replayed);
+
+        std::fs::remove_file(&log).unwrap();
+        std::fs::remove_file(&snapshot).unwrap();
+    }
+
+    #[test]
+    fn test_TradingPlatform_interest_survives_a_snapshot() {
+        let dir = std::env::temp_dir();
+        let log = dir.join(format!("platform-interest-{}.wal", std::process::id()));
+        let snapshot = dir.join(format!("platform-interest-{}.snap", std::process::id()));
+        let _ = std::fs::remove_file(&log);
+        let _ = std::fs::remove_file(&snapshot);
+        let clock = ManualClock::new(DAY);
+
+        let mut trading_platform =
+            TradingPlatform::open(&log, FsyncPolicy::Always, clock.clone()).unwrap();
+        trading_platform
+            .set_interest_schedule(InterestSchedule::new(
+                "HOUSE",
+                [(
+                    "UNIT".to_string(),
+                    Rates {
+                        custody_fee_bps: 365,
+                        ..Rates::default()
+                    },
+                )],
+            ))
+            .unwrap();
+        trading_platform.open_account("ALICE").unwrap();
+        trading_platform.deposit("ALICE", 5_000).unwrap();
+        trading_platform.accrue_interest().unwrap();
+        trading_platform.snapshot(&snapshot).unwrap();
+        clock.advance(DAY);
+        trading_platform.accrue_interest().unwrap();
+        // 3.65% a year is 1 in 10_000 a day
+        assert_eq!(trading_platform.post_interest().unwrap().len(), 1);
+        assert_eq!(trading_platform.balance_of("HOUSE"), Ok(&1));
+        drop(trading_platform);
+
+        let mut restored =
+            TradingPlatform::restore(&snapshot, &log, FsyncPolicy::Always, clock.clone()).unwrap();
+        let replayed = TradingPlatform::open(&log, FsyncPolicy::Always, clock.clone()).unwrap();
+        assert_eq!(restored.balance_of("ALICE"), Ok(&4_999));
+        assert_eq!(restored.state_hash(), replayed.state_hash());
+        // the rates came back with the snapshot and the log, so accrual goes on
+        for platform in [&restored, &replayed] {
+            assert_eq!(
+                platform.accounts.interest_schedule().account,
+                "HOUSE".to_string()
+            );
+        }
+        clock.advance(DAY);
+        assert!(restored.accrue_interest().unwrap().is_some());
+        drop(restored);
+        drop(replayed);
+
+        std::fs::remove_file(&log).unwrap();
+        std::fs::remove_file(&snapshot).unwrap();
+    }
+
+    #[test]
+    fn test_TradingPlatform_settings_survive_a_restart() {
+        let dir = std::env::temp_dir();
+        let log = dir.join(format!("platform-settings-{}.wal", std::process::id()));
+        let snapshot = dir.join(format!("platform-settings-{}.snap", std::process::id()));
+        let _ = std::fs::remove_file(&log);
+        let _ = std::fs::remove_file(&snapshot);
+        let clock = ManualClock::new(1_000);
+        let buy = |amount| Order {
+            price: 10,
+            amount,
+            side: Side::Buy,
+            signer: "ALICE".to_string(),
+        };
+        let market = Market::new(Asset::new("BTC", 1), Asset::new("USD", 0));
+
+        let mut trading_platform =
+            TradingPlatform::open(&log, FsyncPolicy::Always, clock.clone()).unwrap();
+        trading_platform.set_market(market.clone()).unwrap();
+        trading_platform
+            .set_margin_requirements(MarginRequirements::new("LIQUIDATION", 1_000, 500))
+            .unwrap();
+        trading_platform.open_account("ALICE").unwrap();
+        trading_platform.deposit("ALICE", 10).unwrap();
+        trading_platform.set_credit_limit("ALICE", 1_000).unwrap();
+        trading_platform.snapshot(&snapshot).unwrap();
+        // the risk limits are only in the log tail
+        let market_limits = RiskLimits {
+            max_order_size: Some(20),
+            ..RiskLimits::default()
+        };
+        let own_limits = RiskLimits {
+            max_open_orders: Some(1),
+            ..RiskLimits::default()
+        };
+        trading_platform
+            .set_market_risk_limits(market_limits.clone())
+            .unwrap();
+        trading_platform
+            .set_risk_limits("ALICE", own_limits.clone())
+            .unwrap();
+        let fees = FeeSchedule::new(
+            "HOUSE",
+            vec![FeeTier {
+                min_volume: 0,
+                maker_bps: -1,
+                taker_bps: 2,
+            }],
+        );
+        trading_platform.set_fee_schedule(fees.clone()).unwrap();
+        drop(trading_platform);
+
+        let restored =
+            TradingPlatform::restore(&snapshot, &log, FsyncPolicy::Always, clock.clone()).unwrap();
+        let replayed = TradingPlatform::open(&log, FsyncPolicy::Always, clock.clone()).unwrap();
+        for mut platform in [restored, replayed] {
+            assert_eq!(platform.market(), &market);
+            assert_eq!(platform.accounts.fee_schedule(), &fees);
+            assert_eq!(
+                platform.accounts.margin_requirements(),
+                Some(&MarginRequirements::new("LIQUIDATION", 1_000, 500))
+            );
+            // 10 of equity carries 100 of initial margin, not more. Amounts are tenths of BTC.
+            assert_eq!(
+                platform.order(buy(110)),
+                Err(ApplicationError::InsufficientMargin(
+                    "ALICE".to_string(),
+                    11
+                ))
+            );
+            let resting = platform.order(buy(100)).unwrap().ordinal;
+            assert_eq!(platform.risk_limits("ALICE"), own_limits);
+            assert_eq!(platform.risk_limits("BOB"), market_limits);
+            assert_eq!(
+                platform.order(buy(1)),
+                Err(ApplicationError::OrderRejected(
+                    "ALICE".to_string(),
+                    Rejection::OpenOrders(1)
+                ))
+            );
+            // the scale of amounts can't change under the orders and balances
+            assert_eq!(
+                platform.set_market(Market::default()),
+                Err(ApplicationError::BookNotEmpty(1))
+            );
+            platform.cancel(resting).unwrap();
+            assert_eq!(
+                platform.set_market(Market::default()),
+                Err(ApplicationError::AssetInUse("USD".to_string()))
+            );
+        }
+
+        std::fs::remove_file(&log).unwrap();
+        std::fs::remove_file(&snapshot).unwrap();
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_settles_on_credit() {
+        let mut trading_platform = TradingPlatform::new();
+        let order = |signer: &str, side, amount| Order {
+            price: 10,
+            amount,
+            side,
+            signer: signer.to_string(),
+        };
+        trading_platform.open_account("ALICE").unwrap();
+        trading_platform.open_account("BOB").unwrap();
+        trading_platform.set_credit_limit("BOB", 25).unwrap();
+
+        trading_platform
+            .order(order("ALICE", Side::Sell, 3))
+            .unwrap();
+        trading_platform.order(order("BOB", Side::Buy, 2)).unwrap();
+        assert_eq!(trading_platform.debt_of("BOB"), Ok(20));
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&20));
+        // the next unit would go over the credit line
+        assert_eq!(
+            trading_platform.order(order("BOB", Side::Buy, 1)),
+            Err(ApplicationError::OrderRejected(
+                "BOB".to_string(),
+                Rejection::BuyingPower(5)
+            ))
+        );
+        assert_eq!(trading_platform.debt_of("BOB"), Ok(20));
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_rejects_what_it_cannot_settle() {
+        let mut trading_platform = TradingPlatform::new();
+        // only the settlement stands in the way
+        trading_platform
+            .set_market_risk_limits(RiskLimits {
+                buying_power: false,
+                ..RiskLimits::default()
+            })
+            .unwrap();
+        trading_platform.open_account("ALICE").unwrap();
+        trading_platform.open_account("BOB").unwrap();
+        let sell = Order {
+            price: 10,
+            amount: 5,
+            side: Side::Sell,
+            signer: "BOB".to_string(),
+        };
+        let buy = Order {
+            side: Side::Buy,
+            signer: "ALICE".to_string(),
+            ..sell.clone()
+        };
+
+        // ALICE can't pay, so the order doesn't reach the book and BOB's sell keeps resting
+        trading_platform.order(sell).unwrap();
+        assert_eq!(
+            trading_platform.order(buy.clone()),
+            Err(ApplicationError::AccountUnderFunded(
+                "ALICE".to_string(),
+                50
+            ))
+        );
+        assert_eq!(
+            trading_platform.order_status(2).unwrap().state,
+            OrderState::Rejected
+        );
+        let open = trading_platform.open_orders("BOB");
+        assert_eq!(open.len(), 1);
+        assert_eq!((open[0].state, open[0].remaining()), (OrderState::New, 5));
+        assert_eq!(trading_platform.position("BOB"), None);
+
+        // once ALICE has the funds the same order settles against it
+        trading_platform.deposit("ALICE", 50).unwrap();
+        let receipt = trading_platform.order(buy).unwrap();
+        assert_eq!(receipt.matches[0].ordinal, 1);
+        assert_eq!(trading_platform.balance_of("BOB"), Ok(&50));
+
+        // a rebate the taker fee can't fund leaves the book alone too
+        trading_platform
+            .set_fee_schedule(FeeSchedule::new(
+                "FEES",
+                vec![FeeTier {
+                    min_volume: 0,
+                    maker_bps: -10,
+                    taker_bps: 5,
+                }],
+            ))
+            .unwrap();
+        let sell = Order {
+            price: 1_000,
+            amount: 10,
+            side: Side::Sell,
+            signer: "BOB".to_string(),
+        };
+        trading_platform.order(sell.clone()).unwrap();
+        trading_platform.deposit("ALICE", 10_005).unwrap();
+        assert_eq!(
+            trading_platform.order(Order {
+                side: Side::Buy,
+                signer: "ALICE".to_string(),
+                ..sell
+            }),
+            Err(ApplicationError::AccountUnderFunded("FEES".to_string(), 10))
+        );
+        assert_eq!(trading_platform.open_orders("BOB").len(), 1);
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&10_005));
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_runs_risk_checks() {
+        /// Rejects everything from BOB
+        #[derive(Debug)]
+        struct NoBob;
+
+        impl RiskCheck for NoBob {
+            fn check(&self, order: &Order, _: &RiskContext) -> Result<(), Rejection> {
+                if order.signer == "BOB" {
+                    Err(Rejection::OrderSize(0))
+                } else {
+                    Ok(())
+                }
+            }
+        }
+
+        let mut trading_platform = TradingPlatform::new();
+        let order = |signer: &str, amount| Order {
+            price: 10,
+            amount,
+            side: Side::Buy,
+            signer: signer.to_string(),
+        };
+        for signer in ["ALICE", "BOB", "CAROL"] {
+            trading_platform.open_account(signer).unwrap();
+            trading_platform.deposit(signer, 100).unwrap();
+        }
+        trading_platform
+            .set_market_risk_limits(RiskLimits {
+                max_order_size: Some(5),
+                ..RiskLimits::default()
+            })
+            .unwrap();
+        trading_platform
+            .set_risk_limits(
+                "CAROL",
+                RiskLimits {
+                    max_open_orders: Some(1),
+                    buying_power: false,
+                    ..RiskLimits::default()
+                },
+            )
+            .unwrap();
+
+        assert_eq!(
+            trading_platform.order(order("ALICE", 6)),
+            Err(ApplicationError::OrderRejected(
+                "ALICE".to_string(),
+                Rejection::OrderSize(5)
+            ))
+        );
+        assert_eq!(
+            trading_platform.order_status(1).map(|s| &s.state),
+            Some(&OrderState::Rejected)
+        );
+        trading_platform.order(order("ALICE", 5)).unwrap();
+        trading_platform.order(order("ALICE", 5)).unwrap();
+        // the open buys take up all of ALICE's funds
+        assert_eq!(
+            trading_platform.order(order("ALICE", 1)),
+            Err(ApplicationError::OrderRejected(
+                "ALICE".to_string(),
+                Rejection::BuyingPower(100)
+            ))
+        );
+
+        // CAROL's own limits replace the market's
+        trading_platform.order(order("CAROL", 20)).unwrap();
+        assert_eq!(
+            trading_platform.order(order("CAROL", 1)),
+            Err(ApplicationError::OrderRejected(
+                "CAROL".to_string(),
+                Rejection::OpenOrders(1)
+            ))
+        );
+
+        trading_platform.order(order("BOB", 1)).unwrap();
+        trading_platform.add_risk_check(NoBob);
+        assert_eq!(
+            trading_platform.order(order("BOB", 1)),
+            Err(ApplicationError::OrderRejected(
+                "BOB".to_string(),
+                Rejection::OrderSize(0)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_margin_and_liquidation() {
+        let mut trading_platform = TradingPlatform::new();
+        let order = |signer: &str, side, amount| Order {
+            price: 10,
+            amount,
+            side,
+            signer: signer.to_string(),
+        };
+        trading_platform
+            .set_margin_requirements(MarginRequirements::new("LIQUIDATION", 1_000, 500))
+            .unwrap();
+        trading_platform.deposit("LIQUIDATION", 1_000).unwrap();
+        for signer in ["ALICE", "BOB"] {
+            trading_platform.open_account(signer).unwrap();
+            trading_platform.deposit(signer, 100).unwrap();
+        }
+        trading_platform.set_credit_limit("ALICE", 1_000).unwrap();
+
+        // 10x on both sides: BOB shorts 50 at 10, ALICE buys them on credit
+        trading_platform
+            .order(order("BOB", Side::Sell, 50))
+            .unwrap();
+        trading_platform
+            .order(order("ALICE", Side::Buy, 50))
+            .unwrap();
+        let position = trading_platform.position("ALICE").unwrap();
+        assert_eq!(position.size, 50);
+        assert_eq!(position.entry_price(trading_platform.market()), Some(10));
+        assert_eq!(trading_platform.equity("ALICE"), Ok(100));
+        // 110 units would need 110 of initial margin
+        assert_eq!(
+            trading_platform.order(order("ALICE", Side::Buy, 60)),
+            Err(ApplicationError::InsufficientMargin(
+                "ALICE".to_string(),
+                110
+            ))
+        );
+        // closing is always fine
+        trading_platform
+            .order(order("ALICE", Side::Sell, 1))
+            .unwrap();
+        trading_platform.cancel(4).unwrap();
+
+        // at 9 ALICE has 50 of equity for 23 of maintenance margin, at 8 nothing is left
+        trading_platform.set_mark_price(9);
+        assert_eq!(trading_platform.unrealised_pnl("ALICE"), Ok(-50));
+        assert_eq!(trading_platform.liquidate(), Ok(vec![]));
+        trading_platform.set_mark_price(8);
+        assert_eq!(trading_platform.unrealised_pnl("BOB"), Ok(100));
+
+        // the liquidation account can't take over the position yet, so ALICE's order stays open
+        trading_platform.withdraw("LIQUIDATION", 700).unwrap();
+        trading_platform
+            .order(Order {
+                price: 20,
+                ..order("ALICE", Side::Sell, 1)
+            })
+            .unwrap();
+        assert_eq!(
+            trading_platform.liquidate(),
+            Err(ApplicationError::AccountUnderFunded(
+                "LIQUIDATION".to_string(),
+                400
+            ))
+        );
+        assert_eq!(trading_platform.open_orders("ALICE").len(), 1);
+        assert_eq!(trading_platform.position("ALICE").map(|p| p.size), Some(50));
+
+        trading_platform.deposit("LIQUIDATION", 700).unwrap();
+        let txs = trading_platform.liquidate().unwrap();
+        assert!(trading_platform.open_orders("ALICE").is_empty());
+        assert_eq!(txs.len(), 1);
+        let position = trading_platform.position("ALICE").unwrap();
+        assert_eq!((position.size, position.realised_pnl), (0, -100));
+        assert_eq!(trading_platform.equity("ALICE"), Ok(0));
+        assert_eq!(trading_platform.equity("BOB"), Ok(200));
+        assert_eq!(
+            trading_platform
+                .accounts
+                .position("LIQUIDATION", &Market::default().symbol())
+                .map(|p| p.size),
+            Some(50)
+        );
+        assert_eq!(trading_platform.balance_of("LIQUIDATION"), Ok(&600));
+    }
+
+    #[test]
+    fn test_TradingPlatform_account_lifecycle() {
+        let mut trading_platform = TradingPlatform::new();
+        let order = |signer: &str, side| Order {
+            price: 10,
+            amount: 1,
+            side,
+            signer: signer.to_string(),
+        };
+        trading_platform.open_account("ALICE").unwrap();
+        trading_platform.open_account("BOB").unwrap();
+        trading_platform.deposit("BOB", 20).unwrap();
+        trading_platform.order(order("ALICE", Side::Sell)).unwrap();
+
+        // an account with resting orders can't be closed
+        assert_eq!(
+            trading_platform.close_account("ALICE"),
+            Err(ApplicationError::AccountHasOpenOrders(
+                "ALICE".to_string(),
+                1
+            ))
+        );
+
+        // freezing cancels the resting orders and blocks trading
+        trading_platform.freeze("ALICE").unwrap();
+        assert!(trading_platform.open_orders("ALICE").is_empty());
+        assert_eq!(
+            trading_platform.order(order("ALICE", Side::Sell)),
+            Err(ApplicationError::AccountFrozen("ALICE".to_string()))
+        );
+        assert_eq!(
+            trading_platform.order_status(2).unwrap().state,
+            OrderState::Rejected
+        );
+        // but incoming funds are still accepted
+        assert!(trading_platform.send("BOB", "ALICE", 5).is_ok());
+        assert_eq!(
+            trading_platform.withdraw("ALICE", 5),
+            Err(ApplicationError::AccountFrozen("ALICE".to_string()))
+        );
+
+        trading_platform.unfreeze("ALICE").unwrap();
+        trading_platform.withdraw("ALICE", 5).unwrap();
+        trading_platform.close_account("ALICE").unwrap();
+        assert_eq!(
+            trading_platform.deposit("ALICE", 1),
+            Err(ApplicationError::AccountClosed("ALICE".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_close_account_with_a_position() {
+        let mut trading_platform = TradingPlatform::new();
+        let order = |signer: &str, side| Order {
+            price: 10,
+            amount: 1,
+            side,
+            signer: signer.to_string(),
+        };
+        trading_platform.open_account("ALICE").unwrap();
+        trading_platform.open_account("BOB").unwrap();
+        trading_platform.deposit("ALICE", 10).unwrap();
+        trading_platform.order(order("BOB", Side::Sell)).unwrap();
+        trading_platform.order(order("ALICE", Side::Buy)).unwrap();
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&0));
+        assert_eq!(trading_platform.position("ALICE").unwrap().size, 1);
+
+        // the balance went into the position, so the account isn't empty
+        assert_eq!(
+            trading_platform.close_account("ALICE"),
+            Err(ApplicationError::AccountHasPosition(
+                "ALICE".to_string(),
+                trading_platform.market().symbol()
+            ))
+        );
+        assert_eq!(
+            trading_platform.close_account("BOB"),
+            Err(ApplicationError::AccountNotEmpty("BOB".to_string(), 10))
+        );
+
+        // once the position is sold the account can be closed
+        trading_platform.order(order("ALICE", Side::Sell)).unwrap();
+        trading_platform.order(order("BOB", Side::Buy)).unwrap();
+        trading_platform.withdraw("ALICE", 10).unwrap();
+        trading_platform.close_account("ALICE").unwrap();
+    }
+
+    #[test]
+    fn test_TradingPlatform_set_fee_schedule_with_a_reserved_account() {
+        let mut trading_platform = TradingPlatform::new();
+        assert_eq!(
+            trading_platform.set_fee_schedule(FeeSchedule::new(ESCROW_ACCOUNT, vec![])),
+            Err(ApplicationError::ReservedAccount(
+                ESCROW_ACCOUNT.to_string()
+            ))
+        );
+        assert_eq!(
+            trading_platform.accounts.fee_schedule(),
+            &FeeSchedule::default()
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_escrow_survives_a_restart() {
+        let path = std::env::temp_dir().join(format!("escrow-{}.wal", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let clock = ManualClock::new(1_000);
+
+        let mut trading_platform =
+            TradingPlatform::open(&path, FsyncPolicy::Always, clock.clone()).unwrap();
+        trading_platform.open_account("ALICE").unwrap();
+        trading_platform.open_account("BOB").unwrap();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        let id = trading_platform
+            .open_escrow("ALICE", "BOB", 40, Condition::Deadline(2_000))
+            .unwrap()
+            .meta()
+            .sequence;
+        assert_eq!(
+            trading_platform.order(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Buy,
+                signer: ESCROW_ACCOUNT.to_string(),
+            }),
+            Err(ApplicationError::ReservedAccount(
+                ESCROW_ACCOUNT.to_string()
+            ))
+        );
+        drop(trading_platform);
+
+        let mut recovered =
+            TradingPlatform::open(&path, FsyncPolicy::Always, clock.clone()).unwrap();
+        assert_eq!(recovered.escrow(id).map(|e| e.amount), Some(40));
+        assert_eq!(recovered.balance_of("ALICE"), Ok(&60));
+        clock.set(2_000);
+        recovered.release_escrow(id, "BOB").unwrap();
+        assert_eq!(recovered.balance_of("BOB"), Ok(&40));
+        assert_eq!(recovered.escrow(id), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_TradingPlatform_schedules_survive_a_restart() {
+        let path = std::env::temp_dir().join(format!("schedule-{}.wal", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let clock = ManualClock::new(1_000);
+
+        let mut trading_platform =
+            TradingPlatform::open(&path, FsyncPolicy::Always, clock.clone()).unwrap();
+        trading_platform.open_account("ALICE").unwrap();
+        trading_platform.open_account("BOB").unwrap();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        let id = trading_platform
+            .schedule_transfer(
+                "ALICE",
+                "BOB",
+                30,
+                2_000,
+                Recurrence::Daily,
+                RetryPolicy::default(),
+            )
+            .unwrap()
+            .meta()
+            .sequence;
+        clock.set(2_000);
+        assert_eq!(trading_platform.run_schedules().unwrap().len(), 1);
+        drop(trading_platform);
+
+        let mut recovered =
+            TradingPlatform::open(&path, FsyncPolicy::Always, clock.clone()).unwrap();
+        assert_eq!(recovered.schedule(id).map(|s| s.runs), Some(1));
+        clock.advance(DAY);
+        assert_eq!(recovered.run_schedules().unwrap().len(), 1);
+        assert_eq!(recovered.balance_of("BOB"), Ok(&60));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_settles_in_minor_units_of_the_market() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform
+            .set_market(Market::new(Asset::new("BTC", 8), Asset::new("USD", 2)))
+            .unwrap();
+        let market = trading_platform.market().clone();
+        let usd = |s| Amount::parse(s, 2).unwrap();
+        let btc = |s| Amount::parse(s, 8).unwrap();
+        trading_platform.open_account("ALICE").unwrap();
+        trading_platform.open_account("BOB").unwrap();
+        // amounts are checked against the scale of their asset
+        assert_eq!(
+            trading_platform.deposit_amount("BOB", Amount::parse("0.001", 3).unwrap()),
+            Err(ApplicationError::InvalidAmount(
+                "0.001 has more than 2 decimals".to_string()
+            ))
+        );
+        assert!(Order::new(&market, Side::Sell, "ALICE", usd("1"), Amount::new(1, 9)).is_err());
+        trading_platform
+            .deposit_amount("BOB", Amount::parse("20000", 0).unwrap())
+            .unwrap();
+
+        let sell = Order::new(&market, Side::Sell, "ALICE", usd("30000.50"), btc("1")).unwrap();
+        trading_platform.order(sell).unwrap();
+        let buy = Order::new(&market, Side::Buy, "BOB", usd("30001"), btc("0.5")).unwrap();
+        assert_eq!(buy.price, 3_000_100);
+        assert_eq!(buy.amount, 50_000_000);
+        trading_platform.order(buy).unwrap();
+        assert_eq!(
+            trading_platform.accounts.balance_amount("ALICE"),
+            Ok(Amount::parse("15000.25", 2).unwrap())
+        );
+        assert_eq!(
+            trading_platform
+                .accounts
+                .balance_amount("BOB")
+                .unwrap()
+                .to_string(),
+            "4999.75"
+        );
+
+        // three fills of a satoshi at a cent cost a fraction of a cent, rounded up once
+        for _ in 0..3 {
+            trading_platform
+                .order(Order {
+                    price: 1,
+                    amount: 1,
+                    side: Side::Sell,
+                    signer: "ALICE".to_string(),
+                })
+                .unwrap();
+        }
+        let receipt = trading_platform
+            .order(Order {
+                price: 1,
+                amount: 3,
+                side: Side::Buy,
+                signer: "BOB".to_string(),
+            })
+            .unwrap();
+        assert_eq!(receipt.matches.len(), 3);
+        assert_eq!(trading_platform.balance_of("BOB"), Ok(&499_974));
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&1_500_026));
+
+        // orders that could settle for more than fits are rejected up front
+        assert!(matches!(
+            trading_platform.order(Order {
+                price: u64::MAX,
+                amount: market.base.parse("2").unwrap(),
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+            }),
+            Err(ApplicationError::InvalidAmount(_))
+        ));
+        assert_eq!(
+            trading_platform.order_status(7).unwrap().state,
+            OrderState::Rejected
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_idempotent_places_once() {
+        let mut trading_platform = TradingPlatform::with_clock(ManualClock::default());
+        trading_platform.open_account("ALICE").unwrap();
+        let order = |amount| Order {
+            price: 10,
+            amount,
+            side: Side::Sell,
+            signer: "ALICE".to_string(),
+        };
+
+        let receipt = trading_platform
+            .order_idempotent("req-1", order(1))
+            .unwrap();
+        assert_eq!(
+            trading_platform.order_idempotent("req-1", order(1)),
+            Ok(receipt)
+        );
+        assert_eq!(
+            trading_platform.order_idempotent("req-1", order(2)),
+            Err(ApplicationError::IdempotencyKeyReused("req-1".to_string()))
+        );
+        assert_eq!(trading_platform.orderbook().len(), 1);
+        assert_eq!(
+            trading_platform
+                .order_idempotent("req-2", order(1))
+                .unwrap()
+                .ordinal,
+            2
+        );
+    }
 }
@@ -1,12 +1,430 @@
-use std::clone;
+use std::fmt;
+
+use crate::{
+    accounting::AccountStatus,
+    amount::Market,
+    clock::Timestamp,
+    core::Side,
+    escrow::{Escrow, ESCROW_ACCOUNT},
+    fees::FeeSchedule,
+    interest::{Accrual, AccrualKind, InterestSchedule},
+    limits::Limits,
+    margin::{Fill, MarginRequirements},
+    risk::RiskLimits,
+    schedule::Schedule,
+};
+
+/// Uniquely identifies a [`Tx`]. The upper 64 bits are the timestamp, the lower 64 bits the
+/// ledger sequence number, so ids sort by time and don't repeat across restarts of a ledger.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub struct TxId(pub u128);
+
+impl TxId {
+    /// Creates the id for the transaction with `sequence` number created at `timestamp`
+    pub fn new(timestamp: Timestamp, sequence: u64) -> Self {
+        TxId(((timestamp as u128) << 64) | sequence as u128)
+    }
+}
+
+impl fmt::Display for TxId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+/// Whatever caused a [`Tx`] to happen
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Cause {
+    /// Placing the order with this ordinal
+    Order(u64),
+    /// A match between an incoming (taker) and a resting (maker) order
+    Trade {
+        taker_ordinal: u64,
+        maker_ordinal: u64,
+    },
+    /// Another transaction, e.g. the debit leg of a transfer
+    Tx(TxId),
+    /// An execution of the scheduled transfer with this id
+    Schedule(u64),
+}
+
+/// Caller-provided information to attach to a [`Tx`]
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct TxContext {
+    /// Free text
+    pub memo: Option<String>,
+    /// What caused the transaction
+    pub cause: Option<Cause>,
+    /// Client-supplied id of the request, retries with the same key are only executed once. Not
+    /// part of the transaction.
+    pub idempotency_key: Option<String>,
+}
+
+impl TxContext {
+    /// A context that only links to a `cause`
+    pub fn caused_by(cause: Cause) -> Self {
+        TxContext {
+            cause: Some(cause),
+            ..TxContext::default()
+        }
+    }
+
+    /// Adds a memo
+    pub fn with_memo(mut self, memo: &str) -> Self {
+        self.memo = Some(memo.to_string());
+        self
+    }
+
+    /// Adds an idempotency key
+    pub fn with_idempotency_key(mut self, key: &str) -> Self {
+        self.idempotency_key = Some(key.to_string());
+        self
+    }
+}
+
+/// Audit information that every [`Tx`] carries
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TxMeta {
+    /// Unique id
+    pub id: TxId,
+    /// Position in the ledger, strictly increasing
+    pub sequence: u64,
+    /// When the transaction was applied
+    pub timestamp: Timestamp,
+    /// Free text
+    pub memo: Option<String>,
+    /// What caused the transaction
+    pub cause: Option<Cause>,
+}
+
+impl TxMeta {
+    /// Creates the metadata for the transaction with `sequence` number at `timestamp`
+    pub fn new(sequence: u64, timestamp: Timestamp, context: TxContext) -> Self {
+        TxMeta {
+            id: TxId::new(timestamp, sequence),
+            sequence,
+            timestamp,
+            memo: context.memo,
+            cause: context.cause,
+        }
+    }
+}
 
 /// A transaction type. Transactions should be able to rebuild a ledger's state
 /// when they are applied in the same sequence to an empty state.
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Tx {
     /// Currency was added to the account
-    Deposit { account: String, amount: u64 },
+    Deposit {
+        account: String,
+        amount: u64,
+        meta: TxMeta,
+    },
 
     /// Currency was withdrawn from the account
+    Withdraw {
+        account: String,
+        amount: u64,
+        meta: TxMeta,
+    },
+
+    /// Currency was moved from one account to another
+    Transfer {
+        sender: String,
+        recipient: String,
+        amount: u64,
+        meta: TxMeta,
+    },
+
+    /// The account was opened, frozen, unfrozen or closed
+    StatusChange {
+        account: String,
+        status: AccountStatus,
+        meta: TxMeta,
+    },
+
+    /// The account was opened as a sub-account of `parent`
+    SubAccount {
+        account: String,
+        parent: String,
+        meta: TxMeta,
+    },
+
+    /// Currency was moved from the sender into escrow
+    EscrowOpened { escrow: Escrow, meta: TxMeta },
+
+    /// Escrowed currency was paid out to the recipient
+    EscrowReleased {
+        id: u64,
+        recipient: String,
+        amount: u64,
+        meta: TxMeta,
+    },
+
+    /// Escrowed currency was paid back to the sender
+    EscrowRefunded {
+        id: u64,
+        sender: String,
+        amount: u64,
+        meta: TxMeta,
+    },
+
+    /// A transfer was scheduled. Its executions are transfers caused by the schedule.
+    ScheduleCreated { schedule: Schedule, meta: TxMeta },
+
+    /// An execution of the scheduled transfer failed (the error)
+    ScheduleFailed {
+        id: u64,
+        reason: String,
+        meta: TxMeta,
+    },
+
+    /// The scheduled transfer was cancelled
+    ScheduleCancelled { id: u64, meta: TxMeta },
+
+    /// Interest and custody fees accrued on the balances up to the start of `day` (days since the
+    /// UNIX epoch)
+    InterestAccrued {
+        day: u64,
+        accruals: Vec<Accrual>,
+        meta: TxMeta,
+    },
+
+    /// Whole minor units of accrued interest were paid by the house account, or accrued fees or
+    /// overdraft interest were paid to it
+    AccrualPosted {
+        account: String,
+        house: String,
+        kind: AccrualKind,
+        amount: u64,
+        meta: TxMeta,
+    },
+
+    /// The account may go up to `limit` below zero
+    CreditLimitSet {
+        account: String,
+        limit: u64,
+        meta: TxMeta,
+    },
+
+    /// The account bought or sold in a match, which moved its position. The payment is a
+    /// transfer of its own.
+    PositionFilled {
+        account: String,
+        fill: Fill,
+        meta: TxMeta,
+    },
+
+    /// The position of the account was closed against the house account at the mark price
+    /// because its equity fell below the maintenance margin. `fill` is the account's side of it.
+    Liquidated {
+        account: String,
+        house: String,
+        fill: Fill,
+        meta: TxMeta,
+    },
+
+    /// The withdrawal and transfer limits of the account were replaced
+    LimitsSet {
+        account: String,
+        limits: Limits,
+        meta: TxMeta,
+    },
+
+    /// The interest, custody fee and overdraft rates were replaced from the next accrual on
+    InterestScheduleSet {
+        schedule: InterestSchedule,
+        meta: TxMeta,
+    },
+
+    /// Positions need margin from now on, see [`MarginRequirements`]
+    MarginRequirementsSet {
+        requirements: MarginRequirements,
+        meta: TxMeta,
+    },
+
+    /// The pre-trade limits of the account in the market `symbol` were replaced, or the ones of
+    /// the market itself without an account
+    RiskLimitsSet {
+        account: Option<String>,
+        symbol: String,
+        limits: RiskLimits,
+        meta: TxMeta,
+    },
+
+    /// The assets that are traded were set, balances are held in the quote asset from now on
+    MarketSet { market: Market, meta: TxMeta },
+
+    /// The maker and taker fees were replaced from the next match on
+    FeeScheduleSet { schedule: FeeSchedule, meta: TxMeta },
+}
+
+impl Tx {
+    /// The transaction's audit information
+    pub fn meta(&self) -> &TxMeta {
+        match self {
+            Tx::Deposit { meta, .. }
+            | Tx::Withdraw { meta, .. }
+            | Tx::Transfer { meta, .. }
+            | Tx::StatusChange { meta, .. }
+            | Tx::SubAccount { meta, .. }
+            | Tx::EscrowOpened { meta, .. }
+            | Tx::EscrowReleased { meta, .. }
+            | Tx::EscrowRefunded { meta, .. }
+            | Tx::ScheduleCreated { meta, .. }
+            | Tx::ScheduleFailed { meta, .. }
+            | Tx::ScheduleCancelled { meta, .. }
+            | Tx::InterestAccrued { meta, .. }
+            | Tx::AccrualPosted { meta, .. }
+            | Tx::CreditLimitSet { meta, .. }
+            | Tx::LimitsSet { meta, .. }
+            | Tx::InterestScheduleSet { meta, .. }
+            | Tx::MarginRequirementsSet { meta, .. }
+            | Tx::RiskLimitsSet { meta, .. }
+            | Tx::MarketSet { meta, .. }
+            | Tx::FeeScheduleSet { meta, .. }
+            | Tx::PositionFilled { meta, .. }
+            | Tx::Liquidated { meta, .. } => meta,
+        }
+    }
+
+    /// The movement of funds without the metadata, `None` if no funds moved
+    pub fn leg(&self) -> Option<Leg> {
+        match self {
+            Tx::Deposit {
+                account, amount, ..
+            } => Some(Leg::Deposit {
+                account: account.clone(),
+                amount: *amount,
+            }),
+            Tx::Withdraw {
+                account, amount, ..
+            } => Some(Leg::Withdraw {
+                account: account.clone(),
+                amount: *amount,
+            }),
+            Tx::Transfer {
+                sender,
+                recipient,
+                amount,
+                ..
+            } => Some(Leg::Transfer {
+                sender: sender.clone(),
+                recipient: recipient.clone(),
+                amount: *amount,
+            }),
+            Tx::StatusChange { .. }
+            | Tx::SubAccount { .. }
+            | Tx::ScheduleCreated { .. }
+            | Tx::ScheduleFailed { .. }
+            | Tx::ScheduleCancelled { .. }
+            | Tx::InterestAccrued { .. }
+            | Tx::CreditLimitSet { .. }
+            | Tx::LimitsSet { .. }
+            | Tx::InterestScheduleSet { .. }
+            | Tx::MarginRequirementsSet { .. }
+            | Tx::RiskLimitsSet { .. }
+            | Tx::MarketSet { .. }
+            | Tx::FeeScheduleSet { .. }
+            | Tx::PositionFilled { .. } => None,
+            Tx::AccrualPosted {
+                account,
+                house,
+                kind,
+                amount,
+                ..
+            } => {
+                let (sender, recipient) = if kind.is_charge() {
+                    (account, house)
+                } else {
+                    (house, account)
+                };
+                Some(Leg::Transfer {
+                    sender: sender.clone(),
+                    recipient: recipient.clone(),
+                    amount: *amount,
+                })
+            }
+            Tx::Liquidated {
+                account,
+                house,
+                fill,
+                ..
+            } => {
+                let (sender, recipient) = match fill.side {
+                    Side::Buy => (account, house),
+                    Side::Sell => (house, account),
+                };
+                Some(Leg::Transfer {
+                    sender: sender.clone(),
+                    recipient: recipient.clone(),
+                    amount: fill.notional,
+                })
+            }
+            Tx::EscrowOpened { escrow, .. } => Some(Leg::Transfer {
+                sender: escrow.sender.clone(),
+                recipient: ESCROW_ACCOUNT.to_string(),
+                amount: escrow.amount,
+            }),
+            Tx::EscrowReleased {
+                recipient: account,
+                amount,
+                ..
+            }
+            | Tx::EscrowRefunded {
+                sender: account,
+                amount,
+                ..
+            } => Some(Leg::Transfer {
+                sender: ESCROW_ACCOUNT.to_string(),
+                recipient: account.clone(),
+                amount: *amount,
+            }),
+        }
+    }
+}
+
+/// A single movement of funds, i.e. a [`Tx`] that hasn't happened yet. Used to build atomic batches.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Leg {
+    /// Add currency to an account that isn't closed
+    Deposit { account: String, amount: u64 },
+
+    /// Take currency out of the account
     Withdraw { account: String, amount: u64 },
+
+    /// Move currency between two existing accounts
+    Transfer {
+        sender: String,
+        recipient: String,
+        amount: u64,
+    },
+}
+
+impl Leg {
+    /// Turns the leg into the [`Tx`] recording it
+    pub fn into_tx(self, meta: TxMeta) -> Tx {
+        match self {
+            Leg::Deposit { account, amount } => Tx::Deposit {
+                account,
+                amount,
+                meta,
+            },
+            Leg::Withdraw { account, amount } => Tx::Withdraw {
+                account,
+                amount,
+                meta,
+            },
+            Leg::Transfer {
+                sender,
+                recipient,
+                amount,
+            } => Tx::Transfer {
+                sender,
+                recipient,
+                amount,
+                meta,
+            },
+        }
+    }
 }
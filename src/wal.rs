@@ -0,0 +1,504 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    path::Path,
+};
+
+use crate::{
+    accounting::Accounts,
+    clock::{Clock, Timestamp},
+    codec::{crc32, Decode, Encode, Reader, Writer},
+    core::{MassCancel, MatchingEngine, Order},
+    errors::ApplicationError,
+    tx::Tx,
+};
+
+/// Bytes in front of every entry: payload length and CRC-32 of the payload
+const HEADER_LEN: usize = 8;
+
+/// An input to the [`MatchingEngine`], recorded so the engine can be rebuilt by running them again
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum EngineEvent {
+    /// [`MatchingEngine::process`] at `timestamp`
+    Process { timestamp: Timestamp, order: Order },
+    /// [`MatchingEngine::reject`]
+    Reject { order: Order },
+    /// [`MatchingEngine::cancel`]
+    Cancel { ordinal: u64 },
+    /// [`MatchingEngine::expire`]
+    Expire { ordinal: u64 },
+    /// [`MatchingEngine::mass_cancel`]
+    MassCancel(MassCancel),
+    /// [`MatchingEngine::cancel_many`]
+    CancelMany(Vec<u64>),
+}
+
+/// Anything that is written to the [`WriteAheadLog`]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum LogEvent {
+    /// A transaction from the [`Accounts`] journal
+    Tx(Tx),
+    /// An input to the [`MatchingEngine`]
+    Engine(EngineEvent),
+}
+
+/// When the [`WriteAheadLog`] asks the operating system to flush to disk
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FsyncPolicy {
+    /// After every entry. Nothing that was acknowledged gets lost.
+    Always,
+    /// After every n entries. Up to n - 1 entries can be lost in a crash.
+    EveryN(usize),
+    /// Leave it to the operating system
+    Never,
+}
+
+/// An append-only file of [`LogEvent`]s.
+///
+/// Events are written in entries: each entry is framed by its length and a CRC-32 checksum and
+/// holds everything one operation produced, so an operation is either recovered completely or
+/// not at all. A crash in the middle of a write leaves a torn final entry, which is cut off the
+/// next time the log is opened. A bad checksum anywhere else means the file is corrupted.
+#[derive(Debug)]
+pub struct WriteAheadLog {
+    file: File,
+    policy: FsyncPolicy,
+    /// Entries written since the last fsync
+    unsynced: usize,
+    /// Length of the file in bytes
+    position: u64,
+}
+
+impl WriteAheadLog {
+    /// Opens the log at `path`, creating it if needed, and returns it with every event that was
+    /// recovered, oldest first. A torn final entry is removed from the file.
+    /// # Errors
+    /// The file can't be read or written, or is corrupted
+    pub fn open(
+        path: impl AsRef<Path>,
+        policy: FsyncPolicy,
+    ) -> Result<(Self, Vec<LogEvent>), ApplicationError> {
+        WriteAheadLog::open_from(path, policy, 0)
+    }
+
+    /// Like [`WriteAheadLog::open`], but only returns the events of the entries starting at
+    /// byte `position` or later, e.g. the ones a snapshot doesn't cover yet
+    /// # Errors
+    /// The file can't be read or written, is corrupted or shorter than `position`
+    pub fn open_from(
+        path: impl AsRef<Path>,
+        policy: FsyncPolicy,
+        position: u64,
+    ) -> Result<(Self, Vec<LogEvent>), ApplicationError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let mut bytes = vec![];
+        file.read_to_end(&mut bytes)?;
+
+        let (entries, valid) = decode_entries(&bytes)?;
+        if valid < bytes.len() {
+            file.set_len(valid as u64)?;
+            file.sync_data()?;
+        }
+        if position > valid as u64 {
+            return Err(ApplicationError::Corrupted(format!(
+                "log ends at offset {} before {}",
+                valid, position
+            )));
+        }
+        let events = entries
+            .into_iter()
+            .filter(|(offset, _)| *offset >= position)
+            .flat_map(|(_, events)| events)
+            .collect();
+        let wal = WriteAheadLog {
+            file,
+            policy,
+            unsynced: 0,
+            position: valid as u64,
+        };
+        Ok((wal, events))
+    }
+
+    /// Writes `events` as a single entry and syncs according to the [`FsyncPolicy`]
+    /// # Errors
+    /// Writing or syncing failed
+    pub fn append(&mut self, events: &[LogEvent]) -> Result<(), ApplicationError> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let mut payload = Writer::new();
+        payload.put_vec(events);
+        let payload = payload.into_bytes();
+
+        let mut entry = Writer::new();
+        entry.put_u32(payload.len() as u32);
+        entry.put_u32(crc32(&payload));
+        let mut entry = entry.into_bytes();
+        entry.extend_from_slice(&payload);
+        self.file.write_all(&entry)?;
+        self.position += entry.len() as u64;
+
+        self.unsynced += 1;
+        match self.policy {
+            FsyncPolicy::Always => self.sync(),
+            FsyncPolicy::EveryN(n) if self.unsynced >= n => self.sync(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Offset in bytes at which the next entry will be written
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Flushes everything written so far to disk
+    /// # Errors
+    /// Syncing failed
+    pub fn sync(&mut self) -> Result<(), ApplicationError> {
+        self.file.sync_data()?;
+        self.unsynced = 0;
+        Ok(())
+    }
+}
+
+impl Drop for WriteAheadLog {
+    fn drop(&mut self) {
+        if self.unsynced > 0 {
+            // best effort, there's no one left to report to
+            let _ = self.file.sync_data();
+        }
+    }
+}
+
+/// Offset and events of every entry
+type Entries = Vec<(u64, Vec<LogEvent>)>;
+
+/// Decodes all complete entries and returns them with the number of bytes they span. Only the
+/// final entry can be torn: a broken entry that is followed by an intact one means the file is
+/// corrupted, e.g. by a bad length in its header.
+fn decode_entries(bytes: &[u8]) -> Result<(Entries, usize), ApplicationError> {
+    let mut entries = vec![];
+    let mut pos = 0;
+    while bytes.len() - pos >= HEADER_LEN {
+        let Some(end) = entry_end(bytes, pos) else {
+            if (pos + 1..bytes.len()).any(|next| entry_end(bytes, next).is_some()) {
+                return Err(ApplicationError::Corrupted(format!(
+                    "checksum mismatch at offset {}",
+                    pos
+                )));
+            }
+            // torn: the final entry is only partially on disk
+            break;
+        };
+        entries.push((
+            pos as u64,
+            Reader::new(&bytes[pos + HEADER_LEN..end]).get_vec()?,
+        ));
+        pos = end;
+    }
+    Ok((entries, pos))
+}
+
+/// End of the entry starting at `pos` if it's complete and its checksum matches. Entries are never
+/// empty, which keeps a run of zeros from passing for one.
+fn entry_end(bytes: &[u8], pos: usize) -> Option<usize> {
+    let mut header = Reader::new(bytes.get(pos..pos.checked_add(HEADER_LEN)?)?);
+    let len = header.get_u32().ok().filter(|len| *len > 0)? as usize;
+    let checksum = header.get_u32().ok()?;
+    let start = pos + HEADER_LEN;
+    let payload = bytes.get(start..start.checked_add(len)?)?;
+    (crc32(payload) == checksum).then_some(start + len)
+}
+
+/// Rebuilds [`Accounts`] and a [`MatchingEngine`] from the events of a [`WriteAheadLog`]. Both
+/// use `clock` from then on; trades are replayed at their original timestamps.
+/// # Errors
+/// An event can't be applied, i.e. the log doesn't describe a valid history
+pub fn recover(
+    events: impl IntoIterator<Item = LogEvent>,
+    clock: impl Clock + Clone + 'static,
+) -> Result<(Accounts, MatchingEngine), ApplicationError> {
+    let accounts = Accounts::with_clock(clock.clone());
+    let engine = MatchingEngine::with_clock(clock.clone());
+    replay(accounts, engine, events, clock)
+}
+
+/// Like [`recover`], but applies the events on top of existing state, e.g. one restored from a
+/// snapshot
+/// # Errors
+/// An event can't be applied, i.e. the log doesn't continue the state
+pub fn replay(
+    mut accounts: Accounts,
+    mut engine: MatchingEngine,
+    events: impl IntoIterator<Item = LogEvent>,
+    clock: impl Clock + 'static,
+) -> Result<(Accounts, MatchingEngine), ApplicationError> {
+    for event in events {
+        match event {
+            LogEvent::Tx(tx) => accounts.apply(&tx)?,
+            LogEvent::Engine(EngineEvent::Process { timestamp, order }) => {
+                engine.process_at(order, timestamp)?;
+            }
+            LogEvent::Engine(EngineEvent::Reject { order }) => {
+                engine.reject(order);
+            }
+            LogEvent::Engine(EngineEvent::Cancel { ordinal }) => {
+                engine.cancel(ordinal)?;
+            }
+            LogEvent::Engine(EngineEvent::Expire { ordinal }) => {
+                engine.expire(ordinal)?;
+            }
+            LogEvent::Engine(EngineEvent::MassCancel(selector)) => {
+                engine.mass_cancel(&selector);
+            }
+            LogEvent::Engine(EngineEvent::CancelMany(ordinals)) => {
+                engine.cancel_many(ordinals);
+            }
+        }
+    }
+    engine.set_clock(clock);
+    Ok((accounts, engine))
+}
+
+impl Encode for EngineEvent {
+    fn encode(&self, w: &mut Writer) {
+        match self {
+            EngineEvent::Process { timestamp, order } => {
+                w.put_u8(0);
+                w.put_u64(*timestamp);
+                w.put(order);
+            }
+            EngineEvent::Reject { order } => {
+                w.put_u8(1);
+                w.put(order);
+            }
+            EngineEvent::Cancel { ordinal } => {
+                w.put_u8(2);
+                w.put_u64(*ordinal);
+            }
+            EngineEvent::Expire { ordinal } => {
+                w.put_u8(3);
+                w.put_u64(*ordinal);
+            }
+            EngineEvent::MassCancel(selector) => {
+                w.put_u8(4);
+                w.put(selector);
+            }
+            EngineEvent::CancelMany(ordinals) => {
+                w.put_u8(5);
+                w.put_vec(ordinals);
+            }
+        }
+    }
+}
+
+impl Decode for EngineEvent {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        match r.get_u8()? {
+            0 => Ok(EngineEvent::Process {
+                timestamp: r.get_u64()?,
+                order: r.get()?,
+            }),
+            1 => Ok(EngineEvent::Reject { order: r.get()? }),
+            2 => Ok(EngineEvent::Cancel {
+                ordinal: r.get_u64()?,
+            }),
+            3 => Ok(EngineEvent::Expire {
+                ordinal: r.get_u64()?,
+            }),
+            4 => Ok(EngineEvent::MassCancel(r.get()?)),
+            5 => Ok(EngineEvent::CancelMany(r.get_vec()?)),
+            tag => Err(ApplicationError::Corrupted(format!(
+                "invalid EngineEvent tag {}",
+                tag
+            ))),
+        }
+    }
+}
+
+impl Encode for LogEvent {
+    fn encode(&self, w: &mut Writer) {
+        match self {
+            LogEvent::Tx(tx) => {
+                w.put_u8(0);
+                w.put(tx);
+            }
+            LogEvent::Engine(event) => {
+                w.put_u8(1);
+                w.put(event);
+            }
+        }
+    }
+}
+
+impl Decode for LogEvent {
+    fn decode(r: &mut Reader) -> Result<Self, ApplicationError> {
+        match r.get_u8()? {
+            0 => Ok(LogEvent::Tx(r.get()?)),
+            1 => Ok(LogEvent::Engine(r.get()?)),
+            tag => Err(ApplicationError::Corrupted(format!(
+                "invalid LogEvent tag {}",
+                tag
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use std::{fs, path::PathBuf};
+
+    use crate::{clock::ManualClock, core::Side};
+
+    use super::*;
+
+    fn temp_log(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.wal", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn order(signer: &str, side: Side, price: u64, amount: u64) -> Order {
+        Order {
+            price,
+            amount,
+            side,
+            signer: signer.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_WriteAheadLog_open_returns_appended_events() {
+        let path = temp_log("wal-round-trip");
+        let mut accounts = Accounts::with_clock(ManualClock::default());
+        let open = accounts.open_account("ALICE").unwrap();
+        let deposit = accounts.deposit("ALICE", 100).unwrap();
+        let events = vec![
+            LogEvent::Tx(open),
+            LogEvent::Tx(deposit),
+            LogEvent::Engine(EngineEvent::Process {
+                timestamp: 5,
+                order: order("ALICE", Side::Sell, 10, 1),
+            }),
+        ];
+        let cancel = vec![LogEvent::Engine(EngineEvent::Cancel { ordinal: 1 })];
+
+        let (mut wal, recovered) = WriteAheadLog::open(&path, FsyncPolicy::Always).unwrap();
+        assert!(recovered.is_empty());
+        wal.append(&events).unwrap();
+        wal.append(&cancel).unwrap();
+        drop(wal);
+
+        let (_, recovered) = WriteAheadLog::open(&path, FsyncPolicy::Never).unwrap();
+        assert_eq!(recovered, [events, cancel].concat());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_WriteAheadLog_open_cuts_off_torn_entry() {
+        let path = temp_log("wal-torn");
+        let first = vec![LogEvent::Engine(EngineEvent::Cancel { ordinal: 1 })];
+        let second = vec![LogEvent::Engine(EngineEvent::Expire { ordinal: 2 })];
+        let (mut wal, _) = WriteAheadLog::open(&path, FsyncPolicy::EveryN(2)).unwrap();
+        wal.append(&first).unwrap();
+        wal.append(&second).unwrap();
+        drop(wal);
+
+        // lose the last byte of the second entry
+        let intact = fs::read(&path).unwrap();
+        fs::write(&path, &intact[..intact.len() - 1]).unwrap();
+
+        let (mut wal, recovered) = WriteAheadLog::open(&path, FsyncPolicy::Always).unwrap();
+        assert_eq!(recovered, first);
+        wal.append(&second).unwrap();
+        drop(wal);
+        assert_eq!(fs::read(&path).unwrap(), intact);
+
+        // a flipped bit in the final entry is a torn write as well
+        let mut flipped = intact.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        fs::write(&path, &flipped).unwrap();
+        let (_, recovered) = WriteAheadLog::open(&path, FsyncPolicy::Always).unwrap();
+        assert_eq!(recovered, first);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_WriteAheadLog_open_rejects_corruption_before_the_tail() {
+        let path = temp_log("wal-corrupt");
+        let (mut wal, _) = WriteAheadLog::open(&path, FsyncPolicy::Always).unwrap();
+        wal.append(&[LogEvent::Engine(EngineEvent::Cancel { ordinal: 1 })])
+            .unwrap();
+        wal.append(&[LogEvent::Engine(EngineEvent::Cancel { ordinal: 2 })])
+            .unwrap();
+        drop(wal);
+
+        let intact = fs::read(&path).unwrap();
+        let mut bytes = intact.clone();
+        bytes[HEADER_LEN] ^= 1;
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(
+            WriteAheadLog::open(&path, FsyncPolicy::Always).unwrap_err(),
+            ApplicationError::Corrupted("checksum mismatch at offset 0".to_string())
+        );
+
+        // a length that runs past the end isn't a torn write while an intact entry follows,
+        // and the file stays as it is
+        let mut bytes = intact.clone();
+        bytes[..4].fill(0xff);
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(
+            WriteAheadLog::open(&path, FsyncPolicy::Always).unwrap_err(),
+            ApplicationError::Corrupted("checksum mismatch at offset 0".to_string())
+        );
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_recover_rebuilds_accounts_and_engine() {
+        let clock = ManualClock::new(1_000);
+        let mut accounts = Accounts::with_clock(clock.clone());
+        let mut engine = MatchingEngine::with_clock(clock.clone());
+        let mut events = vec![];
+
+        events.push(LogEvent::Tx(accounts.open_account("ALICE").unwrap()));
+        events.push(LogEvent::Tx(accounts.deposit("ALICE", 100).unwrap()));
+        for o in [
+            order("ALICE", Side::Sell, 10, 3),
+            order("BOB", Side::Buy, 10, 1),
+            order("BOB", Side::Buy, 9, 2),
+        ] {
+            clock.advance(1);
+            events.push(LogEvent::Engine(EngineEvent::Process {
+                timestamp: clock.now(),
+                order: o.clone(),
+            }));
+            engine.process(o).unwrap();
+        }
+        engine.cancel(3).unwrap();
+        events.push(LogEvent::Engine(EngineEvent::Cancel { ordinal: 3 }));
+
+        let (recovered_accounts, recovered_engine) =
+            recover(events, ManualClock::new(2_000)).unwrap();
+        assert_eq!(recovered_accounts.journal(), accounts.journal());
+        assert_eq!(recovered_engine.ordinal, engine.ordinal);
+        assert_eq!(
+            recovered_engine.asks.get(&10).unwrap().peek(),
+            engine.asks.get(&10).unwrap().peek()
+        );
+        assert!(recovered_engine.bids.is_empty());
+        assert_eq!(recovered_engine.order_status(3), engine.order_status(3));
+        assert!(recovered_engine
+            .statistics
+            .tape()
+            .eq(engine.statistics.tape()));
+    }
+}